@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::convert::From;
+use std::ops::Range;
+use std::str;
+use bencode::{Bencode, BString, BInt, BList, BDict, DecodeError};
+use bencode::decode::{belement_validate, DecodeResult};
+use convert::TryFrom;
+
+// A view of bencoded data that borrows from the input instead of copying it.
+// Lists and dicts keep their raw bytes and are walked on demand, so decoding
+// allocates nothing no matter how large the strings inside are.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum BencodeRef<'a> {
+    BString(&'a [u8]),
+    BInt(i64),
+    BList(BListRef<'a>),
+    BDict(BDictRef<'a>),
+}
+
+// Lists and dicts also remember where their bytes start in the decoded input,
+// so the exact byte range of any value can be recovered afterwards.
+#[derive(Clone, Copy, Debug)]
+pub struct BListRef<'a>(&'a [u8], usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct BDictRef<'a>(&'a [u8], usize);
+
+pub fn belement_decode_ref<'a>(bytes: &'a [u8]) -> Result<DecodeResult<BencodeRef<'a>>, DecodeError> {
+    let length = try!(belement_validate(bytes));
+    let (element, _) = read_element(&bytes[0..length], 0, 0);
+    Ok(DecodeResult(element, length))
+}
+
+impl<'a> BencodeRef<'a> {
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            BencodeRef::BString(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|bytes| str::from_utf8(bytes).ok())
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            BencodeRef::BInt(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<BListRef<'a>> {
+        match *self {
+            BencodeRef::BList(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<BDictRef<'a>> {
+        match *self {
+            BencodeRef::BDict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        match *self {
+            BencodeRef::BString(bytes) => Bencode::BString(BString::new(bytes)),
+            BencodeRef::BInt(number) => Bencode::BInt(BInt::new(number)),
+            BencodeRef::BList(list) => Bencode::BList(list.to_blist()),
+            BencodeRef::BDict(dict) => Bencode::BDict(dict.to_bdict()),
+        }
+    }
+}
+
+impl<'a> From<BencodeRef<'a>> for Bencode {
+    fn from(element: BencodeRef<'a>) -> Bencode {
+        element.to_bencode()
+    }
+}
+
+impl<'a> PartialEq for BListRef<'a> {
+    fn eq(&self, other: &BListRef<'a>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'a> Eq for BListRef<'a> {}
+
+impl<'a> BListRef<'a> {
+    pub fn iter(&self) -> BListRefIter<'a> {
+        BListRefIter {
+            bytes: self.0,
+            offset: self.1,
+            position: 1,
+        }
+    }
+
+    // The byte range in the decoded input of the item at `index`.
+    pub fn get_span(&self, index: usize) -> Option<Range<usize>> {
+        let mut position = 1;
+        for _ in 0..index {
+            if self.0[position] == b'e' {
+                return None;
+            }
+            position = skip_element(self.0, position);
+        }
+        if self.0[position] == b'e' {
+            return None;
+        }
+        Some((self.1 + position)..(self.1 + skip_element(self.0, position)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 2
+    }
+
+    // The encoded bytes of the whole list, as they appeared in the input.
+    pub fn raw(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.1..(self.1 + self.0.len())
+    }
+
+    pub fn to_blist(&self) -> BList {
+        BList::from(self.iter().map(|element| element.to_bencode()).collect())
+    }
+}
+
+impl<'a> PartialEq for BDictRef<'a> {
+    fn eq(&self, other: &BDictRef<'a>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'a> Eq for BDictRef<'a> {}
+
+impl<'a> BDictRef<'a> {
+    pub fn iter(&self) -> BDictRefIter<'a> {
+        BDictRefIter {
+            bytes: self.0,
+            offset: self.1,
+            position: 1,
+        }
+    }
+
+    // The byte range in the decoded input of the value stored under `key`,
+    // whatever its type.
+    pub fn get_span(&self, key: &str) -> Option<Range<usize>> {
+        let key = key.as_bytes();
+        let mut position = 1;
+        while self.0[position] != b'e' {
+            let (entry_key, value_start) = read_string(self.0, position);
+            let value_end = skip_element(self.0, value_start);
+            if entry_key == key {
+                return Some((self.1 + value_start)..(self.1 + value_end));
+            } else if entry_key > key {
+                break;
+            }
+            position = value_end;
+        }
+        None
+    }
+
+    pub fn get(&self, key: &str) -> Option<BencodeRef<'a>> {
+        let key = key.as_bytes();
+        for (entry_key, value) in self.iter() {
+            if entry_key == key {
+                return Some(value);
+            } else if entry_key > key {
+                // keys are sorted, it cannot come later
+                break;
+            }
+        }
+        None
+    }
+
+    pub fn get_bytes(&self, key: &str) -> Option<&'a [u8]> {
+        self.get(key).and_then(|element| element.as_bytes())
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&'a str> {
+        self.get(key).and_then(|element| element.as_str())
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(|element| element.as_int())
+    }
+
+    pub fn get_list(&self, key: &str) -> Option<BListRef<'a>> {
+        self.get(key).and_then(|element| element.as_list())
+    }
+
+    pub fn get_dict(&self, key: &str) -> Option<BDictRef<'a>> {
+        self.get(key).and_then(|element| element.as_dict())
+    }
+
+    // Copies out a single field, for when the owned types are more convenient.
+    pub fn get_copy<A: TryFrom<Bencode>>(&self, key: &str) -> Option<A> {
+        self.get(key).and_then(|element| A::try_from(element.to_bencode()).ok())
+    }
+
+    // The encoded bytes of the whole dict, as they appeared in the input.
+    pub fn raw(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.1..(self.1 + self.0.len())
+    }
+
+    pub fn to_bdict(&self) -> BDict {
+        let mut map = BTreeMap::new();
+        for (key, value) in self.iter() {
+            map.insert(BString::new(key), value.to_bencode());
+        }
+        BDict(map)
+    }
+}
+
+pub struct BListRefIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    position: usize,
+}
+
+impl<'a> Iterator for BListRefIter<'a> {
+    type Item = BencodeRef<'a>;
+
+    fn next(&mut self) -> Option<BencodeRef<'a>> {
+        if self.bytes[self.position] == b'e' {
+            return None;
+        }
+        let (element, end) = read_element(self.bytes, self.position, self.offset);
+        self.position = end;
+        Some(element)
+    }
+}
+
+pub struct BDictRefIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    position: usize,
+}
+
+impl<'a> Iterator for BDictRefIter<'a> {
+    type Item = (&'a [u8], BencodeRef<'a>);
+
+    fn next(&mut self) -> Option<(&'a [u8], BencodeRef<'a>)> {
+        if self.bytes[self.position] == b'e' {
+            return None;
+        }
+        let (key, key_end) = read_string(self.bytes, self.position);
+        let (value, end) = read_element(self.bytes, key_end, self.offset);
+        self.position = end;
+        Some((key, value))
+    }
+}
+
+// The readers below are only ever handed input that `belement_validate` has
+// accepted, so they skip all the checks the validator already made.
+
+fn read_number(digits: &[u8]) -> i64 {
+    let (negative, digits) = match digits.first() {
+        Some(&b'-') => (true, &digits[1..]),
+        _ => (false, digits),
+    };
+    let mut number: i64 = 0;
+    for &digit in digits {
+        // accumulating downwards lets i64::min_value() through without overflow
+        number = number * 10 - (digit - b'0') as i64;
+    }
+    if negative { number } else { -number }
+}
+
+fn find(bytes: &[u8], from: usize, byte: u8) -> usize {
+    let mut position = from;
+    while bytes[position] != byte {
+        position += 1;
+    }
+    position
+}
+
+fn read_string<'a>(bytes: &'a [u8], position: usize) -> (&'a [u8], usize) {
+    let colon = find(bytes, position, b':');
+    let len = read_number(&bytes[position..colon]) as usize;
+    let start = colon + 1;
+    (&bytes[start..(start + len)], start + len)
+}
+
+fn skip_element(bytes: &[u8], position: usize) -> usize {
+    match bytes[position] {
+        b'i' => find(bytes, position, b'e') + 1,
+        b'l' | b'd' => {
+            let mut position = position + 1;
+            while bytes[position] != b'e' {
+                position = skip_element(bytes, position);
+            }
+            position + 1
+        }
+        _ => read_string(bytes, position).1,
+    }
+}
+
+// `offset` is where `bytes` itself starts in the decoded input.
+fn read_element<'a>(bytes: &'a [u8], position: usize, offset: usize) -> (BencodeRef<'a>, usize) {
+    match bytes[position] {
+        b'i' => {
+            let end = find(bytes, position, b'e');
+            (BencodeRef::BInt(read_number(&bytes[(position + 1)..end])), end + 1)
+        }
+        b'l' => {
+            let end = skip_element(bytes, position);
+            (BencodeRef::BList(BListRef(&bytes[position..end], offset + position)), end)
+        }
+        b'd' => {
+            let end = skip_element(bytes, position);
+            (BencodeRef::BDict(BDictRef(&bytes[position..end], offset + position)), end)
+        }
+        _ => {
+            let (string, end) = read_string(bytes, position);
+            (BencodeRef::BString(string), end)
+        }
+    }
+}
@@ -1,230 +1,228 @@
-use std::collections::BTreeMap;
 use bencode::{Bencode, BString, BInt, BList, BDict, DecodeError, DecodeErrorKind};
-use sha1::Sha1;
-use byteorder::{WriteBytesExt, ByteOrder, BigEndian};
-use std::collections::HashMap;
+use bencode::borrowed::belement_decode_ref;
+
+// Deep enough for any real torrent or tracker reply, shallow enough that
+// hostile input cannot exhaust the stack.
+pub const MAX_NESTING_DEPTH: usize = 128;
 
 pub struct DecodeResult<T>(pub T, pub usize);
 
 pub fn belement_decode(bytes: &[u8]) -> Result<DecodeResult<Bencode>, DecodeError> {
-    if bytes.len() == 0 {
-        return Err(DecodeError {
-            position: Some(0),
-            kind: DecodeErrorKind::EndOfStream,
-        });
-    }
+    let result = try!(belement_decode_ref(bytes));
+    Ok(DecodeResult(result.0.to_bencode(), result.1))
+}
 
-    Ok(if bytes[0] == 'i' as u8 {
-        let result = try!(bint_decode(bytes));
-        DecodeResult(Bencode::BInt(result.0), result.1)
-    } else if bytes[0] == 'l' as u8 {
-        let result = try!(blist_decode(bytes));
-        DecodeResult(Bencode::BList(result.0), result.1)
-    } else if bytes[0] == 'd' as u8 {
-        let result = try!(bdict_decode(bytes));
-        DecodeResult(Bencode::BDict(result.0), result.1)
-    } else {
-        let result = try!(bstring_decode(bytes));
-        DecodeResult(Bencode::BString(result.0), result.1)
-    })
+// Checks that the input starts with one well-formed value and returns its
+// length, without building anything.
+pub fn belement_validate(bytes: &[u8]) -> Result<usize, DecodeError> {
+    let mut decoder = Decoder::new(bytes);
+    try!(decoder.element());
+    Ok(decoder.position)
 }
 
 pub fn bstring_decode(bytes: &[u8]) -> Result<DecodeResult<BString>, DecodeError> {
-    let mut position = 0;
-    const ASCII_HEX_ZERO: u8 = 0x30;
-    const ASCII_HEX_NINE: u8 = 0x39;
+    match try!(typed_decode(bytes, b'0', DecodeErrorKind::InvalidString)) {
+        DecodeResult(Bencode::BString(bstring), position) => Ok(DecodeResult(bstring, position)),
+        _ => Err(conversion_error()),
+    }
+}
 
-    if !(ASCII_HEX_ZERO <= bytes[position] && bytes[position] <= ASCII_HEX_NINE) {
-        return Err(DecodeError {
-            position: Some(position),
-            kind: DecodeErrorKind::InvalidString,
-        });
+pub fn bint_decode(bytes: &[u8]) -> Result<DecodeResult<BInt>, DecodeError> {
+    match try!(typed_decode(bytes, b'i', DecodeErrorKind::ExpectedByte('i'))) {
+        DecodeResult(Bencode::BInt(bint), position) => Ok(DecodeResult(bint, position)),
+        _ => Err(conversion_error()),
     }
+}
 
-    while ASCII_HEX_ZERO <= bytes[position] && bytes[position] <= 0x39 {
-        position += 1;
+pub fn blist_decode(bytes: &[u8]) -> Result<DecodeResult<BList>, DecodeError> {
+    match try!(typed_decode(bytes, b'l', DecodeErrorKind::ExpectedByte('l'))) {
+        DecodeResult(Bencode::BList(blist), position) => Ok(DecodeResult(blist, position)),
+        _ => Err(conversion_error()),
     }
+}
 
+pub fn bdict_decode(bytes: &[u8]) -> Result<DecodeResult<BDict>, DecodeError> {
+    match try!(typed_decode(bytes, b'd', DecodeErrorKind::ExpectedByte('d'))) {
+        DecodeResult(Bencode::BDict(bdict), position) => Ok(DecodeResult(bdict, position)),
+        _ => Err(conversion_error()),
+    }
+}
 
-    if bytes[position] != ':' as u8 {
+// Rejects input whose first byte cannot start the wanted type before decoding
+// it. A `lead` of b'0' stands for any digit, i.e. a string.
+fn typed_decode(bytes: &[u8],
+                lead: u8,
+                kind: DecodeErrorKind)
+                -> Result<DecodeResult<Bencode>, DecodeError> {
+    let matches = match bytes.first() {
+        Some(&byte) if lead == b'0' => b'0' <= byte && byte <= b'9',
+        Some(&byte) => byte == lead,
+        None => true,
+    };
+    if !matches {
         return Err(DecodeError {
-            position: Some(position),
-            kind: DecodeErrorKind::InvalidString,
+            position: Some(0),
+            kind: kind,
         });
+    }
+    belement_decode(bytes)
+}
 
+fn conversion_error() -> DecodeError {
+    DecodeError {
+        position: Some(0),
+        kind: DecodeErrorKind::ConversionError,
     }
+}
 
-    let len_string = try!(String::from_utf8(bytes[0..position]
-        .iter()
-        .map(|&x| x)
-        .collect::<Vec<u8>>()));
-    let len = len_string.parse::<usize>().unwrap();
-    position += 1;
-    let str_bytes = bytes[position..(position + len)].iter().map(|&x| x).collect::<Vec<u8>>();
-    position = position + len;
-    Ok(DecodeResult(BString(str_bytes), position))
+// Walks the input with a single cursor so every error can report the
+// absolute offset it happened at. Never indexes without a bounds check.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
 }
 
-pub fn bint_decode(bytes: &[u8]) -> Result<DecodeResult<BInt>, DecodeError> {
-    let mut position = 0;
-    let mut number_string = String::new();
-    if bytes.len() > 1 {
-        if (bytes[position] as char) == 'i' {
-            position += 1;
-            match bytes[position] as char {
-                '0' => {
-                    if bytes.len() >= 2 && (bytes[position + 1] as char) == 'e' {
-                        position = position + 2;
-                        return Ok(DecodeResult(BInt::new(0i64), position));
-                    } else {
-                        return Err(DecodeError {
-                            position: Some(position),
-                            kind: DecodeErrorKind::ExpectedByte('e'),
-                        });
-                    }
-                }
-                _ => {
-                    for i in &bytes[position..bytes.len()] {
-                        if (*i as char) != 'e' {
-                            number_string.push(*i as char);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-            }
-        } else {
-            return Err(DecodeError {
-                position: None,
-                kind: DecodeErrorKind::ExpectedByte('i'),
-            });
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder {
+            bytes: bytes,
+            position: 0,
+            depth: 0,
         }
-    } else {
-        return Err(DecodeError {
-            position: None,
-            kind: DecodeErrorKind::EndOfStream,
-        });
     }
 
-    if &number_string == "-0" {
-        return Err(DecodeError {
-            position: None,
-            kind: DecodeErrorKind::IntNegativeZero,
-        });
+    fn error(&self, position: usize, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            position: Some(position),
+            kind: kind,
+        }
     }
 
-    let parsint = try!(number_string.parse::<i64>());
-    let number = BInt::new(parsint);
-    position += number_string.len() + 1;
-    Ok(DecodeResult(number, position))
-}
+    fn peek(&self) -> Result<u8, DecodeError> {
+        match self.bytes.get(self.position) {
+            Some(&byte) => Ok(byte),
+            None => Err(self.error(self.position, DecodeErrorKind::EndOfStream)),
+        }
+    }
 
-pub fn blist_decode(bytes: &[u8]) -> Result<DecodeResult<BList>, DecodeError> {
-    let mut position = 0;
-    let mut list = Vec::new();
-    if bytes.len() > 1 {
-        if bytes[position] != 'l' as u8 {
-            return Err(DecodeError {
-                position: None,
-                kind: DecodeErrorKind::ExpectedByte('l'),
-            });
+    fn expect(&mut self, expected: u8) -> Result<(), DecodeError> {
+        if try!(self.peek()) != expected {
+            return Err(self.error(self.position, DecodeErrorKind::ExpectedByte(expected as char)));
         }
-        position += 1;
-        loop {
-            if position >= bytes.len() {
-                return Err(DecodeError {
-                    position: None,
-                    kind: DecodeErrorKind::EndOfStream,
-                });
-            } else if bytes[position] == 'e' as u8 {
-                position += 1;
+        self.position += 1;
+        Ok(())
+    }
+
+    fn element(&mut self) -> Result<(), DecodeError> {
+        match try!(self.peek()) {
+            b'i' => self.bint(),
+            b'l' => self.blist(),
+            b'd' => self.bdict(),
+            b'0'...b'9' => self.bstring().map(|_| ()),
+            _ => Err(self.error(self.position, DecodeErrorKind::UnknownType)),
+        }
+    }
+
+    // Reads a run of ASCII digits, rejecting leading zeros.
+    fn digits(&mut self) -> Result<&'a [u8], DecodeError> {
+        let start = self.position;
+        while let Some(&byte) = self.bytes.get(self.position) {
+            if byte < b'0' || byte > b'9' {
                 break;
-            } else {
-                let result = try!(belement_decode(&bytes[position..bytes.len()]));
-                position += result.1;
-                list.push(result.0);
             }
+            self.position += 1;
         }
-        Ok(DecodeResult(BList(list), position))
-    } else {
-        return Err(DecodeError {
-            position: None,
-            kind: DecodeErrorKind::EndOfStream,
-        });
+        let digits = &self.bytes[start..self.position];
+        if digits.is_empty() {
+            return match self.bytes.get(self.position) {
+                Some(_) => Err(self.error(self.position, DecodeErrorKind::ExpectedDigit)),
+                None => Err(self.error(self.position, DecodeErrorKind::EndOfStream)),
+            };
+        }
+        if digits.len() > 1 && digits[0] == b'0' {
+            return Err(self.error(start, DecodeErrorKind::LeadingZero));
+        }
+        Ok(digits)
     }
-}
 
-pub fn bdict_decode(bytes: &[u8]) -> Result<DecodeResult<BDict>, DecodeError> {
-    let mut position = 0;
-    if bytes[position] != 'd' as u8 {
-        return Err(DecodeError {
-            position: Some(0),
-            kind: DecodeErrorKind::ExpectedByte('d'),
-        });
-    } else if bytes.len() < 2 {
-        return Err(DecodeError {
-            position: Some(1),
-            kind: DecodeErrorKind::EndOfStream,
-        });
+    fn bstring(&mut self) -> Result<&'a [u8], DecodeError> {
+        let start = self.position;
+        match try!(self.peek()) {
+            b'0'...b'9' => (),
+            _ => return Err(self.error(start, DecodeErrorKind::InvalidString)),
+        }
+        let len_digits = try!(self.digits());
+        // only ASCII digits were accepted, so this is valid UTF-8
+        let len_string = String::from_utf8_lossy(len_digits);
+        let len = try!(len_string.parse::<usize>()
+            .map_err(|e| self.error(start, DecodeErrorKind::IntParsingErr(e))));
+        try!(self.expect(b':'));
+
+        let remaining = self.bytes.len() - self.position;
+        if len > remaining {
+            return Err(self.error(self.bytes.len(), DecodeErrorKind::EndOfStream));
+        }
+        let str_bytes = &self.bytes[self.position..(self.position + len)];
+        self.position += len;
+        Ok(str_bytes)
     }
-    let mut map = BTreeMap::new();
-    position += 1;
-    loop {
-        if position >= bytes.len() {
-            return Err(DecodeError {
-                position: None,
-                kind: DecodeErrorKind::EndOfStream,
-            });
-        } else if bytes[position] == 'e' as u8 {
-            position += 1;
-            break;
-        } else {
-            let key = try!(bstring_decode(&bytes[position..bytes.len()]));
-            position += key.1;
-            match belement_decode(&bytes[position..bytes.len()]) {
-                Ok(value) => {
-                    position += value.1;
-                    map.insert(key.0, value.0);
-                }
-                Err(_) => {
-                    let mut peers: Vec<BDict> = Vec::new();
-                    let original_pos = position;
-                    let mut peer_map = BTreeMap::new();
-                    while position < bytes.len() {
-                        if position + 6 >= bytes.len() {
-                            return Err(DecodeError {
-                                position: None,
-                                kind: DecodeErrorKind::EndOfStream,
-                            });
-                        } else if position != bytes.len() {
-                            let ip = &bytes[position..position + 4];
-                            let ip_string = format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
-                            let port = &bytes[position + 4..position + 6];
-                            let port_u16 = BigEndian::read_u16(port);
-
-                            let port_element = Bencode::BInt(BInt::new(port_u16 as i64));
-                            let ip_element = Bencode::BString(BString::from_str(&ip_string));
-
-                            let ip_bstr = BString::from_str("ip");
-                            let port_bstr = BString::from_str("port");
-
-                            position += 6;
-
-                            peer_map.insert(ip_bstr, ip_element);
-                            peer_map.insert(port_bstr, port_element);
-                        } else {
-                            let orig = Vec::from(&bytes[original_pos..position]);
-                            let dict = BDict(peer_map, orig);
-                            map.insert(BString::from_str("peers"), Bencode::BDict(dict));
-                            break;
-                        }
-                    }
+
+    fn bint(&mut self) -> Result<(), DecodeError> {
+        let start = self.position;
+        try!(self.expect(b'i'));
+        let negative = try!(self.peek()) == b'-';
+        if negative {
+            self.position += 1;
+        }
+        let digits = try!(self.digits());
+        if negative && digits == b"0" {
+            return Err(self.error(start, DecodeErrorKind::IntNegativeZero));
+        }
+        let number_string = String::from_utf8_lossy(&self.bytes[(start + 1)..self.position]);
+        try!(number_string.parse::<i64>()
+            .map_err(|e| self.error(start, DecodeErrorKind::IntParsingErr(e))));
+        self.expect(b'e')
+    }
+
+    fn enter(&mut self) -> Result<(), DecodeError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(self.error(self.position, DecodeErrorKind::NestingTooDeep));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn blist(&mut self) -> Result<(), DecodeError> {
+        try!(self.enter());
+        try!(self.expect(b'l'));
+        while try!(self.peek()) != b'e' {
+            try!(self.element());
+        }
+        self.position += 1;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn bdict(&mut self) -> Result<(), DecodeError> {
+        try!(self.enter());
+        try!(self.expect(b'd'));
+        let mut last_key: Option<&'a [u8]> = None;
+        while try!(self.peek()) != b'e' {
+            let key_position = self.position;
+            let key = try!(self.bstring());
+            if let Some(last) = last_key {
+                if key == last {
+                    return Err(self.error(key_position, DecodeErrorKind::DuplicateKey));
+                } else if key < last {
+                    return Err(self.error(key_position, DecodeErrorKind::UnsortedKeys));
                 }
             }
+            try!(self.element());
+            last_key = Some(key);
         }
+        self.position += 1;
+        self.depth -= 1;
+        Ok(())
     }
-    let mut sha1 = Sha1::new();
-    sha1.update(&bytes[0..position]);
-    let hash = sha1.digest().bytes().iter().map(|&x| x).collect::<Vec<u8>>();
-    Ok(DecodeResult(BDict(map, hash), position))
 }
@@ -1 +1,91 @@
+use std::io;
+use std::io::Write;
+use bencode::{Bencode, BString, BInt, BList, BDict};
 
+pub fn belement_encode(element: &Bencode) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // writing into a Vec<u8> cannot fail
+    belement_encode_to(element, &mut bytes).unwrap();
+    bytes
+}
+
+pub fn belement_encode_to<W: Write>(element: &Bencode, out: &mut W) -> io::Result<()> {
+    match *element {
+        Bencode::BString(ref bstring) => bstring_encode_to(bstring, out),
+        Bencode::BInt(ref bint) => bint_encode_to(bint, out),
+        Bencode::BList(ref blist) => blist_encode_to(blist, out),
+        Bencode::BDict(ref bdict) => bdict_encode_to(bdict, out),
+    }
+}
+
+pub fn bstring_encode_to<W: Write>(bstring: &BString, out: &mut W) -> io::Result<()> {
+    try!(write!(out, "{}:", bstring.0.len()));
+    out.write_all(&bstring.0)
+}
+
+pub fn bint_encode_to<W: Write>(bint: &BInt, out: &mut W) -> io::Result<()> {
+    write!(out, "i{}e", bint.0)
+}
+
+pub fn blist_encode_to<W: Write>(blist: &BList, out: &mut W) -> io::Result<()> {
+    try!(out.write_all(b"l"));
+    for element in blist.0.iter() {
+        try!(belement_encode_to(element, out));
+    }
+    out.write_all(b"e")
+}
+
+// Keys are written in the BTreeMap's order, which is the raw byte order of
+// the key strings, so the output is always canonical.
+pub fn bdict_encode_to<W: Write>(bdict: &BDict, out: &mut W) -> io::Result<()> {
+    try!(out.write_all(b"d"));
+    for (key, value) in bdict.0.iter() {
+        try!(bstring_encode_to(key, out));
+        try!(belement_encode_to(value, out));
+    }
+    out.write_all(b"e")
+}
+
+// Like `bdict_encode`, with values that are already encoded written under
+// their keys as they are. Keys in `raw` take the place of those in `bdict`.
+pub fn bdict_encode_with_raw(bdict: &BDict, raw: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut entries: Vec<(&[u8], Vec<u8>)> = bdict.0
+        .iter()
+        .filter(|&(key, _)| !raw.iter().any(|&(raw_key, _)| raw_key.as_bytes() == &key.0[..]))
+        .map(|(key, value)| (&key.0[..], belement_encode(value)))
+        .collect();
+    entries.extend(raw.iter().map(|&(key, value)| (key.as_bytes(), value.to_vec())));
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut bytes = b"d".to_vec();
+    for (key, value) in entries {
+        bstring_encode_to(&BString::new(key), &mut bytes).unwrap();
+        bytes.extend(value);
+    }
+    bytes.push(b'e');
+    bytes
+}
+
+pub fn bstring_encode(bstring: &BString) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bstring_encode_to(bstring, &mut bytes).unwrap();
+    bytes
+}
+
+pub fn bint_encode(bint: &BInt) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bint_encode_to(bint, &mut bytes).unwrap();
+    bytes
+}
+
+pub fn blist_encode(blist: &BList) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    blist_encode_to(blist, &mut bytes).unwrap();
+    bytes
+}
+
+pub fn bdict_encode(bdict: &BDict) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bdict_encode_to(bdict, &mut bytes).unwrap();
+    bytes
+}
@@ -0,0 +1,541 @@
+use std::collections::BTreeMap;
+use std::{char, error, fmt, str};
+use bencode::{Bencode, BString, BInt, BList, BDict};
+
+// A lossless mapping between bencode and JSON:
+//
+// - integers become JSON numbers and strings that are valid UTF-8 become JSON
+//   strings,
+// - any other string becomes `{"$hex": "..."}` or `{"$base64": "..."}`,
+// - dicts become objects. Keys that are not valid UTF-8 are written as
+//   "$hex:..." or "$base64:...", and keys that already start with '$' get a
+//   second one so they cannot be mistaken for either.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binary {
+    Hex,
+    Base64,
+}
+
+// Writes `element` as indented JSON.
+pub fn to_json(element: &Bencode, binary: Binary) -> String {
+    let mut out = String::new();
+    write_element(element, binary, 0, &mut out);
+    out
+}
+
+pub fn from_json(json: &str) -> Result<Bencode, JsonError> {
+    let mut parser = Parser {
+        bytes: json.as_bytes(),
+        position: 0,
+        depth: 0,
+    };
+    let element = try!(parser.element());
+    parser.skip_whitespace();
+    if parser.position != parser.bytes.len() {
+        return Err(parser.error(JsonErrorKind::TrailingData));
+    }
+    Ok(element)
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    pub position: usize,
+    pub kind: JsonErrorKind,
+}
+
+#[derive(Debug)]
+pub enum JsonErrorKind {
+    UnexpectedChar(char),
+    EndOfInput,
+    InvalidNumber,
+    InvalidEscape,
+    InvalidBinary,
+    InvalidKey(String),
+    DuplicateKey(String),
+    NestingTooDeep,
+    TrailingData,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            JsonErrorKind::UnexpectedChar(c) => {
+                write!(f, "unexpected {:?} at position {}", c, self.position)
+            }
+            JsonErrorKind::InvalidKey(ref key) |
+            JsonErrorKind::DuplicateKey(ref key) => {
+                write!(f,
+                       "{} {:?} at position {}",
+                       error::Error::description(self),
+                       key,
+                       self.position)
+            }
+            _ => write!(f, "{} at position {}", error::Error::description(self), self.position),
+        }
+    }
+}
+
+impl error::Error for JsonError {
+    fn description(&self) -> &str {
+        match self.kind {
+            JsonErrorKind::UnexpectedChar(_) => "unexpected character",
+            JsonErrorKind::EndOfInput => "unexpected end of input",
+            JsonErrorKind::InvalidNumber => "number is not an integer bencode can hold",
+            JsonErrorKind::InvalidEscape => "invalid escape sequence",
+            JsonErrorKind::InvalidBinary => "invalid hex or base64 data",
+            JsonErrorKind::InvalidKey(_) => "invalid key",
+            JsonErrorKind::DuplicateKey(_) => "duplicate key",
+            JsonErrorKind::NestingTooDeep => "nesting too deep",
+            JsonErrorKind::TrailingData => "trailing data after value",
+        }
+    }
+}
+
+const HEX_TAG: &'static str = "$hex";
+const BASE64_TAG: &'static str = "$base64";
+const MAX_DEPTH: usize = 128;
+
+fn write_indent(depth: usize, out: &mut String) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+fn write_element(element: &Bencode, binary: Binary, depth: usize, out: &mut String) {
+    match *element {
+        Bencode::BInt(ref bint) => out.push_str(&bint.0.to_string()),
+        Bencode::BString(ref bstring) => {
+            match str::from_utf8(&bstring.0) {
+                Ok(string) => write_string(string, out),
+                Err(_) => {
+                    let (tag, data) = encode_binary(&bstring.0, binary);
+                    out.push_str("{");
+                    write_string(tag, out);
+                    out.push_str(": ");
+                    write_string(&data, out);
+                    out.push_str("}");
+                }
+            }
+        }
+        Bencode::BList(ref blist) => {
+            if blist.0.is_empty() {
+                return out.push_str("[]");
+            }
+            out.push_str("[\n");
+            for (i, item) in blist.0.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                write_indent(depth + 1, out);
+                write_element(item, binary, depth + 1, out);
+            }
+            out.push_str("\n");
+            write_indent(depth, out);
+            out.push_str("]");
+        }
+        Bencode::BDict(ref bdict) => {
+            if bdict.0.is_empty() {
+                return out.push_str("{}");
+            }
+            out.push_str("{\n");
+            for (i, (key, value)) in bdict.0.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                write_indent(depth + 1, out);
+                write_string(&encode_key(&key.0, binary), out);
+                out.push_str(": ");
+                write_element(value, binary, depth + 1, out);
+            }
+            out.push_str("\n");
+            write_indent(depth, out);
+            out.push_str("}");
+        }
+    }
+}
+
+fn encode_binary(bytes: &[u8], binary: Binary) -> (&'static str, String) {
+    match binary {
+        Binary::Hex => (HEX_TAG, hex_encode(bytes)),
+        Binary::Base64 => (BASE64_TAG, base64_encode(bytes)),
+    }
+}
+
+fn encode_key(key: &[u8], binary: Binary) -> String {
+    match str::from_utf8(key) {
+        Ok(key) if key.starts_with('$') => format!("${}", key),
+        Ok(key) => key.to_string(),
+        Err(_) => {
+            let (tag, data) = encode_binary(key, binary);
+            format!("{}:{}", tag, data)
+        }
+    }
+}
+
+fn decode_key(key: &str) -> Option<Vec<u8>> {
+    if !key.starts_with('$') {
+        return Some(key.as_bytes().to_vec());
+    }
+    if key.starts_with("$$") {
+        return Some(key[1..].as_bytes().to_vec());
+    }
+    let mut parts = key.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(tag), Some(data)) => decode_binary(tag, data),
+        _ => None,
+    }
+}
+
+fn decode_binary(tag: &str, data: &str) -> Option<Vec<u8>> {
+    match tag {
+        HEX_TAG => hex_decode(data),
+        BASE64_TAG => base64_decode(data),
+        _ => None,
+    }
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+const HEX_DIGITS: &'static [u8; 16] = b"0123456789abcdef";
+const BASE64_DIGITS: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for &byte in bytes {
+        out.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        out.push(HEX_DIGITS[(byte & 0xf) as usize] as char);
+    }
+    out
+}
+
+pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.as_bytes();
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(hex.len() / 2);
+    for pair in hex.chunks(2) {
+        match ((pair[0] as char).to_digit(16), (pair[1] as char).to_digit(16)) {
+            (Some(high), Some(low)) => out.push((high * 16 + low) as u8),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for group in bytes.chunks(3) {
+        let mut bits = 0u32;
+        for (i, &byte) in group.iter().enumerate() {
+            bits |= (byte as u32) << (16 - 8 * i);
+        }
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64_DIGITS[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn base64_decode(base64: &str) -> Option<Vec<u8>> {
+    let base64 = base64.as_bytes();
+    if base64.len() % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(base64.len() / 4 * 3);
+    for (n, group) in base64.chunks(4).enumerate() {
+        let last = n == base64.len() / 4 - 1;
+        let padding = group.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut bits = 0u32;
+        for (i, &c) in group[..(4 - padding)].iter().enumerate() {
+            let value = match BASE64_DIGITS.iter().position(|&d| d == c) {
+                Some(value) => value,
+                None => return None,
+            };
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        for i in 0..(3 - padding) {
+            out.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: JsonErrorKind) -> JsonError {
+        JsonError {
+            position: self.position,
+            kind: kind,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&byte) = self.bytes.get(self.position) {
+            match byte {
+                b' ' | b'\t' | b'\n' | b'\r' => self.position += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8, JsonError> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(&byte) => Ok(byte),
+            None => Err(self.error(JsonErrorKind::EndOfInput)),
+        }
+    }
+
+    fn unexpected(&self) -> JsonError {
+        // the input came from a &str and is only ever split at ASCII bytes
+        let rest = str::from_utf8(&self.bytes[self.position..]).ok();
+        match rest.and_then(|rest| rest.chars().next()) {
+            Some(c) => self.error(JsonErrorKind::UnexpectedChar(c)),
+            None => self.error(JsonErrorKind::EndOfInput),
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), JsonError> {
+        if try!(self.peek()) != expected {
+            return Err(self.unexpected());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn element(&mut self) -> Result<Bencode, JsonError> {
+        match try!(self.peek()) {
+            b'"' => Ok(Bencode::BString(BString(try!(self.string()).into_bytes()))),
+            b'-' | b'0'...b'9' => self.number(),
+            b'[' => self.list(),
+            b'{' => self.object(),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), JsonError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(JsonErrorKind::NestingTooDeep));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<Bencode, JsonError> {
+        let start = self.position;
+        if self.bytes[self.position] == b'-' {
+            self.position += 1;
+        }
+        while let Some(&byte) = self.bytes.get(self.position) {
+            match byte {
+                b'0'...b'9' => self.position += 1,
+                b'.' | b'e' | b'E' => return Err(self.error(JsonErrorKind::InvalidNumber)),
+                _ => break,
+            }
+        }
+        let digits = str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        digits.parse::<i64>()
+            .map(|number| Bencode::BInt(BInt(number)))
+            .map_err(|_| {
+                JsonError {
+                    position: start,
+                    kind: JsonErrorKind::InvalidNumber,
+                }
+            })
+    }
+
+    fn list(&mut self) -> Result<Bencode, JsonError> {
+        try!(self.enter());
+        try!(self.expect(b'['));
+        let mut list = Vec::new();
+        if try!(self.peek()) == b']' {
+            self.position += 1;
+        } else {
+            loop {
+                list.push(try!(self.element()));
+                match try!(self.peek()) {
+                    b',' => self.position += 1,
+                    b']' => {
+                        self.position += 1;
+                        break;
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(Bencode::BList(BList(list)))
+    }
+
+    fn object(&mut self) -> Result<Bencode, JsonError> {
+        try!(self.enter());
+        let start = self.position;
+        try!(self.expect(b'{'));
+        let mut entries = Vec::new();
+        if try!(self.peek()) == b'}' {
+            self.position += 1;
+        } else {
+            loop {
+                if try!(self.peek()) != b'"' {
+                    return Err(self.unexpected());
+                }
+                let key_position = self.position;
+                let key = try!(self.string());
+                try!(self.expect(b':'));
+                let value = try!(self.element());
+                entries.push((key_position, key, value));
+                match try!(self.peek()) {
+                    b',' => self.position += 1,
+                    b'}' => {
+                        self.position += 1;
+                        break;
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+            }
+        }
+        self.depth -= 1;
+
+        // a lone "$hex" or "$base64" key is a tagged binary string
+        if entries.len() == 1 && (entries[0].1 == HEX_TAG || entries[0].1 == BASE64_TAG) {
+            let invalid = JsonError {
+                position: start,
+                kind: JsonErrorKind::InvalidBinary,
+            };
+            return match entries[0].2 {
+                Bencode::BString(ref data) => {
+                    let data = try!(str::from_utf8(&data.0).map_err(|_| invalid));
+                    decode_binary(&entries[0].1, data)
+                        .map(|bytes| Bencode::BString(BString(bytes)))
+                        .ok_or(JsonError {
+                            position: start,
+                            kind: JsonErrorKind::InvalidBinary,
+                        })
+                }
+                _ => Err(invalid),
+            };
+        }
+
+        let mut map = BTreeMap::new();
+        for (key_position, key, value) in entries.into_iter() {
+            let bytes = try!(decode_key(&key).ok_or(JsonError {
+                position: key_position,
+                kind: JsonErrorKind::InvalidKey(key.clone()),
+            }));
+            if map.insert(BString(bytes), value).is_some() {
+                return Err(JsonError {
+                    position: key_position,
+                    kind: JsonErrorKind::DuplicateKey(key),
+                });
+            }
+        }
+        Ok(Bencode::BDict(BDict(map)))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        try!(self.expect(b'"'));
+        let mut out = String::new();
+        loop {
+            let start = self.position;
+            while let Some(&byte) = self.bytes.get(self.position) {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.position += 1;
+            }
+            // only ASCII bytes were stopped at, so the run is still valid UTF-8
+            out.push_str(str::from_utf8(&self.bytes[start..self.position]).unwrap());
+            match self.bytes.get(self.position) {
+                Some(&b'"') => {
+                    self.position += 1;
+                    return Ok(out);
+                }
+                Some(&b'\\') => {
+                    self.position += 1;
+                    out.push(try!(self.escape()));
+                }
+                Some(_) => return Err(self.unexpected()),
+                None => return Err(self.error(JsonErrorKind::EndOfInput)),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, JsonError> {
+        let escaped = match self.bytes.get(self.position) {
+            Some(&byte) => byte,
+            None => return Err(self.error(JsonErrorKind::EndOfInput)),
+        };
+        self.position += 1;
+        match escaped {
+            b'"' => Ok('"'),
+            b'\\' => Ok('\\'),
+            b'/' => Ok('/'),
+            b'b' => Ok('\u{8}'),
+            b'f' => Ok('\u{c}'),
+            b'n' => Ok('\n'),
+            b'r' => Ok('\r'),
+            b't' => Ok('\t'),
+            b'u' => {
+                let high = try!(self.code_unit());
+                if high < 0xd800 || high > 0xdfff {
+                    return char::from_u32(high).ok_or(self.error(JsonErrorKind::InvalidEscape));
+                }
+                // a surrogate pair spans two escapes
+                if high > 0xdbff || !self.bytes[self.position..].starts_with(b"\\u") {
+                    return Err(self.error(JsonErrorKind::InvalidEscape));
+                }
+                self.position += 2;
+                let low = try!(self.code_unit());
+                if low < 0xdc00 || low > 0xdfff {
+                    return Err(self.error(JsonErrorKind::InvalidEscape));
+                }
+                char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+                    .ok_or(self.error(JsonErrorKind::InvalidEscape))
+            }
+            _ => Err(self.error(JsonErrorKind::InvalidEscape)),
+        }
+    }
+
+    fn code_unit(&mut self) -> Result<u32, JsonError> {
+        if self.bytes.len() < self.position + 4 {
+            return Err(self.error(JsonErrorKind::EndOfInput));
+        }
+        let mut unit = 0;
+        for &byte in &self.bytes[self.position..(self.position + 4)] {
+            let digit = try!((byte as char)
+                .to_digit(16)
+                .ok_or(self.error(JsonErrorKind::InvalidEscape)));
+            unit = unit * 16 + digit;
+        }
+        self.position += 4;
+        Ok(unit)
+    }
+}
@@ -0,0 +1,353 @@
+use std::str;
+use bencode::{Bencode, BString, BInt, BList, BDict, DecodeError, DecodeErrorKind};
+use bencode::borrowed::{BencodeRef, BDictRef};
+use bencode::decode::belement_decode;
+
+// Conversions between Rust values and bencode, used by `bencode_dict!` to
+// map struct fields onto dictionary keys.
+pub trait FromBencode<'a>: Sized {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<Self, DecodeError>;
+}
+
+pub trait ToBencode {
+    fn to_bencode(&self) -> Bencode;
+}
+
+// Declares a struct together with how each of its fields maps onto a key of a
+// bencoded dict, and implements `FromBencode` and `ToBencode` for it.
+//
+//     bencode_dict! {
+//         pub struct FileEntry {
+//             pub length: u64 => required "length",
+//             pub md5_sum: Option<String> => optional "md5sum",
+//             pub name: Option<String> => lossy "name",
+//             pub path_utf8: Option<Vec<String>> => optional "path.utf-8",
+//             pub info: Vec<u8> => raw "info",
+//         }
+//     }
+//
+// Keys need not be Rust names. `required` fields fail the conversion when
+// missing, `optional` ones must be an `Option` and are left out of the output
+// when `None`, `lossy` ones are optional strings that may hold anything, with
+// invalid UTF-8 replaced, and `raw` ones hold the value's encoded bytes
+// exactly as they appeared in the input.
+#[macro_export]
+macro_rules! bencode_dict {
+    ($(#[$attr:meta])* pub struct $name:ident {
+        $($(#[$field_attr:meta])* pub $field:ident : $ty:ty => $kind:ident $key:expr),* $(,)*
+    }) => {
+        $(#[$attr])*
+        pub struct $name {
+            $($(#[$field_attr])* pub $field: $ty),*
+        }
+        bencode_dict!(@impl $name { $($field => $kind $key),* });
+    };
+    ($(#[$attr:meta])* struct $name:ident {
+        $($(#[$field_attr:meta])* pub $field:ident : $ty:ty => $kind:ident $key:expr),* $(,)*
+    }) => {
+        $(#[$attr])*
+        struct $name {
+            $($(#[$field_attr])* pub $field: $ty),*
+        }
+        bencode_dict!(@impl $name { $($field => $kind $key),* });
+    };
+    (@impl $name:ident { $($field:ident => $kind:ident $key:expr),* }) => {
+        impl<'a> $crate::bencode::mapping::FromBencode<'a> for $name {
+            fn from_bencode(element: $crate::bencode::borrowed::BencodeRef<'a>)
+                            -> Result<$name, $crate::bencode::DecodeError> {
+                let dict = try!($crate::bencode::mapping::expect_dict(element));
+                Ok($name {
+                    $($field: try!(bencode_dict!(@get $kind dict $key))),*
+                })
+            }
+        }
+
+        impl $crate::bencode::mapping::ToBencode for $name {
+            fn to_bencode(&self) -> $crate::bencode::Bencode {
+                let mut dict = $crate::bencode::BDict::new();
+                $(bencode_dict!(@put $kind dict, $key, self.$field);)*
+                $crate::bencode::Bencode::BDict(dict)
+            }
+        }
+    };
+    (@get required $dict:ident $key:expr) => {
+        $crate::bencode::mapping::get_required(&$dict, $key)
+    };
+    (@get optional $dict:ident $key:expr) => {
+        $crate::bencode::mapping::get_optional(&$dict, $key)
+    };
+    (@get lossy $dict:ident $key:expr) => {
+        $crate::bencode::mapping::get_lossy(&$dict, $key)
+    };
+    (@get raw $dict:ident $key:expr) => {
+        $crate::bencode::mapping::get_raw(&$dict, $key)
+    };
+    (@put required $dict:ident, $key:expr, $value:expr) => {
+        $dict.insert($key, $crate::bencode::mapping::ToBencode::to_bencode(&$value));
+    };
+    (@put optional $dict:ident, $key:expr, $value:expr) => {
+        if let Some(ref value) = $value {
+            $dict.insert($key, $crate::bencode::mapping::ToBencode::to_bencode(value));
+        }
+    };
+    (@put lossy $dict:ident, $key:expr, $value:expr) => {
+        bencode_dict!(@put optional $dict, $key, $value)
+    };
+    (@put raw $dict:ident, $key:expr, $value:expr) => {
+        if let Some(value) = $crate::bencode::mapping::from_raw(&$value) {
+            $dict.insert($key, value);
+        }
+    };
+}
+
+pub fn expect_dict<'a>(element: BencodeRef<'a>) -> Result<BDictRef<'a>, DecodeError> {
+    element.as_dict().ok_or(DecodeError {
+        position: None,
+        kind: DecodeErrorKind::ConversionError,
+    })
+}
+
+// Type mismatches are blamed on the field, anything more specific from a
+// nested struct is passed through untouched.
+fn field_error(dict: &BDictRef, key: &str, error: DecodeError) -> DecodeError {
+    match error.kind {
+        DecodeErrorKind::ConversionError => {
+            DecodeError {
+                position: dict.get_span(key).map(|span| span.start),
+                kind: DecodeErrorKind::InvalidField(key.to_string()),
+            }
+        }
+        _ => error,
+    }
+}
+
+pub fn get_required<'a, A: FromBencode<'a>>(dict: &BDictRef<'a>,
+                                            key: &str)
+                                            -> Result<A, DecodeError> {
+    match dict.get(key) {
+        Some(element) => A::from_bencode(element).map_err(|e| field_error(dict, key, e)),
+        None => {
+            Err(DecodeError {
+                position: Some(dict.span().start),
+                kind: DecodeErrorKind::MissingField(key.to_string()),
+            })
+        }
+    }
+}
+
+pub fn get_optional<'a, A: FromBencode<'a>>(dict: &BDictRef<'a>,
+                                            key: &str)
+                                            -> Result<Option<A>, DecodeError> {
+    match dict.get(key) {
+        Some(element) => {
+            A::from_bencode(element).map(Some).map_err(|e| field_error(dict, key, e))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn get_lossy(dict: &BDictRef, key: &str) -> Result<Option<String>, DecodeError> {
+    match dict.get(key) {
+        Some(element) => {
+            let bytes = try!(element.as_bytes()
+                .ok_or_else(|| field_error(dict, key, conversion_error())));
+            Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn get_raw<'a>(dict: &BDictRef<'a>, key: &str) -> Result<Vec<u8>, DecodeError> {
+    match dict.get_span(key) {
+        Some(span) => {
+            let start = dict.span().start;
+            Ok(dict.raw()[(span.start - start)..(span.end - start)].to_vec())
+        }
+        None => {
+            Err(DecodeError {
+                position: Some(dict.span().start),
+                kind: DecodeErrorKind::MissingField(key.to_string()),
+            })
+        }
+    }
+}
+
+// Raw fields are kept as encoded bytes, and are only written back out if they
+// still hold a single valid value.
+pub fn from_raw(bytes: &[u8]) -> Option<Bencode> {
+    match belement_decode(bytes) {
+        Ok(result) if result.1 == bytes.len() => Some(result.0),
+        _ => None,
+    }
+}
+
+fn conversion_error() -> DecodeError {
+    DecodeError {
+        position: None,
+        kind: DecodeErrorKind::ConversionError,
+    }
+}
+
+impl<'a> FromBencode<'a> for BencodeRef<'a> {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<BencodeRef<'a>, DecodeError> {
+        Ok(element)
+    }
+}
+
+impl<'a> FromBencode<'a> for Bencode {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<Bencode, DecodeError> {
+        Ok(element.to_bencode())
+    }
+}
+
+impl<'a> FromBencode<'a> for BDict {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<BDict, DecodeError> {
+        element.as_dict().map(|dict| dict.to_bdict()).ok_or(conversion_error())
+    }
+}
+
+impl<'a> FromBencode<'a> for BList {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<BList, DecodeError> {
+        element.as_list().map(|list| list.to_blist()).ok_or(conversion_error())
+    }
+}
+
+impl<'a> FromBencode<'a> for BString {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<BString, DecodeError> {
+        element.as_bytes().map(BString::new).ok_or(conversion_error())
+    }
+}
+
+impl<'a> FromBencode<'a> for &'a [u8] {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<&'a [u8], DecodeError> {
+        element.as_bytes().ok_or(conversion_error())
+    }
+}
+
+impl<'a> FromBencode<'a> for &'a str {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<&'a str, DecodeError> {
+        let bytes = try!(element.as_bytes().ok_or(conversion_error()));
+        str::from_utf8(bytes).map_err(|_| conversion_error())
+    }
+}
+
+impl<'a> FromBencode<'a> for String {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<String, DecodeError> {
+        <&str as FromBencode>::from_bencode(element).map(|s| s.to_string())
+    }
+}
+
+impl<'a> FromBencode<'a> for i64 {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<i64, DecodeError> {
+        element.as_int().ok_or(conversion_error())
+    }
+}
+
+macro_rules! from_bencode_int {
+    ($($ty:ty),*) => {
+        $(impl<'a> FromBencode<'a> for $ty {
+            fn from_bencode(element: BencodeRef<'a>) -> Result<$ty, DecodeError> {
+                let number = try!(element.as_int().ok_or(conversion_error()));
+                if number < 0 || (number as u64) > (<$ty>::max_value() as u64) {
+                    return Err(conversion_error());
+                }
+                Ok(number as $ty)
+            }
+        })*
+    }
+}
+
+from_bencode_int!(u64, u32, u16);
+
+impl<'a> FromBencode<'a> for bool {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<bool, DecodeError> {
+        match element.as_int() {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(conversion_error()),
+        }
+    }
+}
+
+impl<'a, A: FromBencode<'a>> FromBencode<'a> for Vec<A> {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<Vec<A>, DecodeError> {
+        let list = try!(element.as_list().ok_or(conversion_error()));
+        let mut result = Vec::new();
+        for item in list.iter() {
+            result.push(try!(A::from_bencode(item)));
+        }
+        Ok(result)
+    }
+}
+
+impl ToBencode for Bencode {
+    fn to_bencode(&self) -> Bencode {
+        self.clone()
+    }
+}
+
+impl<'a> ToBencode for BencodeRef<'a> {
+    fn to_bencode(&self) -> Bencode {
+        BencodeRef::to_bencode(self)
+    }
+}
+
+impl ToBencode for BDict {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BDict(self.clone())
+    }
+}
+
+impl ToBencode for BList {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BList(self.clone())
+    }
+}
+
+impl ToBencode for BString {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BString(self.clone())
+    }
+}
+
+impl<'a> ToBencode for &'a [u8] {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BString(BString::new(self))
+    }
+}
+
+impl<'a> ToBencode for &'a str {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BString(BString::from_str(self))
+    }
+}
+
+impl ToBencode for String {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BString(BString::from_str(self))
+    }
+}
+
+macro_rules! to_bencode_int {
+    ($($ty:ty),*) => {
+        $(impl ToBencode for $ty {
+            fn to_bencode(&self) -> Bencode {
+                Bencode::BInt(BInt::new(*self as i64))
+            }
+        })*
+    }
+}
+
+to_bencode_int!(i64, u64, u32, u16);
+
+impl ToBencode for bool {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BInt(BInt::new(if *self { 1 } else { 0 }))
+    }
+}
+
+impl<A: ToBencode> ToBencode for Vec<A> {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BList(BList::from(self.iter().map(|item| item.to_bencode()).collect()))
+    }
+}
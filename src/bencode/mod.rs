@@ -5,10 +5,14 @@ use std::num::ParseIntError;
 use self::DecodeErrorKind::*;
 use std::convert::From;
 use convert::TryFrom;
-use metainfo::SHA1Hash20b;
 
+#[macro_use]
+pub mod mapping;
 pub mod decode;
 pub mod encode;
+pub mod borrowed;
+pub mod stream;
+pub mod json;
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone)]
 pub struct BString(Vec<u8>);
@@ -17,7 +21,7 @@ pub struct BInt(i64);
 #[derive(Eq, PartialEq, Clone)]
 pub struct BList(Vec<Bencode>);
 #[derive(Eq, PartialEq, Debug, Clone)]
-pub struct BDict(BTreeMap<BString, Bencode>, SHA1Hash20b);
+pub struct BDict(BTreeMap<BString, Bencode>);
 
 impl TryFrom<Bencode> for BInt {
     type Err = DecodeError;
@@ -144,6 +148,23 @@ impl<A: TryFrom<Bencode>> TryFrom<Bencode> for Vec<A> {
 
 // Makes it easier to access elements of BDict
 impl BDict {
+    pub fn new() -> BDict {
+        BDict(BTreeMap::new())
+    }
+
+    pub fn insert(&mut self, key: &str, value: Bencode) -> Option<Bencode> {
+        self.0.insert(BString::from_str(key), value)
+    }
+
+    // For keys that are not text, like the hashes keying `piece layers`.
+    pub fn insert_bytes(&mut self, key: &[u8], value: Bencode) -> Option<Bencode> {
+        self.0.insert(BString::new(key), value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Bencode> {
+        self.0.remove(&BString::from_str(key))
+    }
+
     pub fn get<'b>(&'b self, _key: &str) -> Option<&'b Bencode> {
         let s_bytes = _key.to_string().into_bytes();
         let _key = BString::new(&s_bytes);
@@ -156,10 +177,6 @@ impl BDict {
             _ => None,
         }
     }
-
-    pub fn hash(&self) -> SHA1Hash20b {
-        self.1.clone()
-    }
 }
 
 impl BString {
@@ -216,23 +233,30 @@ pub enum Bencode {
     BDict(BDict),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DecodeError {
     pub position: Option<usize>,
     pub kind: DecodeErrorKind,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DecodeErrorKind {
     ExpectedByte(char),
+    ExpectedDigit,
     EndOfStream,
     InvalidString,
     UnknownType,
     IntParsingErr(ParseIntError),
     IntNegativeZero,
+    LeadingZero,
+    UnsortedKeys,
+    DuplicateKey,
+    NestingTooDeep,
+    TooLarge,
     Utf8Err(FromUtf8Error),
     ConversionError,
     MissingField(String),
+    InvalidField(String),
 }
 
 impl fmt::Display for Bencode {
@@ -241,7 +265,7 @@ impl fmt::Display for Bencode {
             Bencode::BString(ref s) => write!(f, "{}", s),
             Bencode::BInt(BInt(bint)) => write!(f, "{}", bint),
             Bencode::BList(ref l) => write!(f, "{:?}", l),
-            Bencode::BDict(BDict(ref bdict_map, _)) => write!(f, "{:?}", bdict_map),
+            Bencode::BDict(BDict(ref bdict_map)) => write!(f, "{:?}", bdict_map),
         }
     }
 }
@@ -291,16 +315,25 @@ impl fmt::Display for DecodeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(match self.kind {
             ExpectedByte(ref ch) => write!(f, "expected `{}`", ch),
+            ExpectedDigit => write!(f, "expected a digit"),
             EndOfStream => write!(f, "reached end of input"),
             InvalidString => write!(f, "not a valid string"),
             UnknownType => write!(f, "type not recognised as bencoded"),
             IntParsingErr(ref intpe) => write!(f, "{}", intpe), 
             IntNegativeZero => write!(f, "-0 is not a valid integer"),
+            LeadingZero => write!(f, "numbers must not have leading zeros"),
+            UnsortedKeys => write!(f, "dictionary keys are not sorted"),
+            DuplicateKey => write!(f, "dictionary key appears more than once"),
+            NestingTooDeep => write!(f, "values are nested too deeply"),
+            TooLarge => write!(f, "value is larger than allowed"),
             Utf8Err(ref u8e) => write!(f, "{}", u8e),
             ConversionError => write!(f, "cannot convert type"),
             MissingField(ref field) => {
                 write!(f, "required field '{}' is missing on dictionary", field)
             }
+            InvalidField(ref field) => {
+                write!(f, "field '{}' has the wrong type or an invalid value", field)
+            }
         });
         match self.position {
             Some(ref l) => write!(f, " at byte `{}` of the input stream", l),
@@ -313,14 +346,21 @@ impl error::Error for DecodeError {
     fn description(&self) -> &str {
         match self.kind {
             ExpectedByte(..) => "unexpected input byte",
+            ExpectedDigit => "expected a digit",
             EndOfStream => "end of input, no more bytes",
             InvalidString => "failed to parse bytes as a string",
             UnknownType => "cannot parse as a valid bencoded type",
             IntParsingErr(..) => "failed to parse integer",
             IntNegativeZero => "-0 is not a valid integer",
+            LeadingZero => "number has leading zeros",
+            UnsortedKeys => "dictionary keys are not sorted",
+            DuplicateKey => "duplicate dictionary key",
+            NestingTooDeep => "maximum nesting depth exceeded",
+            TooLarge => "maximum value size exceeded",
             Utf8Err(..) => "failed with an utf8error",
             ConversionError => "failed to convert type",
             MissingField(..) => "required field is missing",
+            InvalidField(..) => "field has the wrong type or value",
         }
     }
 }
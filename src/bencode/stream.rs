@@ -0,0 +1,299 @@
+use std::str;
+use bencode::{DecodeError, DecodeErrorKind};
+use bencode::decode::MAX_NESTING_DEPTH;
+
+// How many bytes a decoder holds on to by default before giving up on a
+// value ever finishing.
+pub const DEFAULT_MAX_BUFFERED: usize = 4 * 1024 * 1024;
+
+// What the decoder made of the bytes it has been given so far.
+#[derive(Debug)]
+pub enum Progress {
+    // Everything so far is a valid start of a value, but it is not finished.
+    NeedMore,
+    // The encoded bytes of one complete, valid value.
+    Done(Vec<u8>),
+    // The input can never become valid, however many bytes follow.
+    Error(DecodeError),
+}
+
+// A push-based decoder for values that arrive in pieces, e.g. from a socket.
+// Every byte is looked at once, so feeding a value in many small chunks costs
+// no more than decoding it in one go, and it applies exactly the same rules as
+// `belement_decode`. Error positions count from the start of the value.
+//
+// Bytes past the end of a finished value stay buffered; push an empty chunk
+// to look for the next value in them. Holding more than the maximum buffered
+// size is an error, so a peer cannot make it grow without end.
+pub struct StreamDecoder {
+    buffer: Vec<u8>,
+    max_buffered: usize,
+    position: usize,
+    state: State,
+    stack: Vec<Frame>,
+    error: Option<DecodeError>,
+}
+
+enum State {
+    Value,
+    Int { start: usize },
+    StrLen { start: usize, key: bool },
+    StrBody { start: usize, body: usize, end: usize, key: bool },
+}
+
+enum Frame {
+    List,
+    Dict { expect_key: bool, last_key: Option<(usize, usize)> },
+}
+
+impl StreamDecoder {
+    pub fn new() -> StreamDecoder {
+        StreamDecoder {
+            buffer: Vec::new(),
+            max_buffered: DEFAULT_MAX_BUFFERED,
+            position: 0,
+            state: State::Value,
+            stack: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Progress {
+        if let Some(ref error) = self.error {
+            return Progress::Error(error.clone());
+        }
+        if self.buffer.len().saturating_add(chunk.len()) > self.max_buffered {
+            let error = self.error(self.max_buffered, DecodeErrorKind::TooLarge);
+            self.error = Some(error.clone());
+            return Progress::Error(error);
+        }
+        self.buffer.extend_from_slice(chunk);
+        match self.advance() {
+            Ok(true) => {
+                let rest = self.buffer.split_off(self.position);
+                let value = ::std::mem::replace(&mut self.buffer, rest);
+                self.position = 0;
+                Progress::Done(value)
+            }
+            Ok(false) => Progress::NeedMore,
+            Err(error) => {
+                self.error = Some(error.clone());
+                Progress::Error(error)
+            }
+        }
+    }
+
+    // Bytes received but not yet returned as part of a finished value.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn reset(&mut self) {
+        *self = StreamDecoder::new().with_max_buffered(self.max_buffered);
+    }
+
+    fn error(&self, position: usize, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            position: Some(position),
+            kind: kind,
+        }
+    }
+
+    // Returns whether a top level value has been completed.
+    fn advance(&mut self) -> Result<bool, DecodeError> {
+        while self.position < self.buffer.len() {
+            let position = self.position;
+            let byte = self.buffer[position];
+            match self.state {
+                State::Value => {
+                    if try!(self.value(position, byte)) {
+                        return Ok(true);
+                    }
+                }
+                State::Int { start } => {
+                    if try!(self.int(start, position, byte)) {
+                        return Ok(true);
+                    }
+                }
+                State::StrLen { start, key } => {
+                    match byte {
+                        b'0'...b'9' => {
+                            if self.buffer[start] == b'0' {
+                                return Err(self.error(start, DecodeErrorKind::LeadingZero));
+                            }
+                            self.position += 1;
+                        }
+                        b':' => {
+                            // only ASCII digits were accepted, so this is valid UTF-8
+                            let len = try!(str::from_utf8(&self.buffer[start..position])
+                                .unwrap()
+                                .parse::<usize>()
+                                .map_err(|e| self.error(start, DecodeErrorKind::IntParsingErr(e))));
+                            self.position += 1;
+                            self.state = State::StrBody {
+                                start: start,
+                                body: self.position,
+                                end: self.position.saturating_add(len),
+                                key: key,
+                            };
+                        }
+                        _ => {
+                            return Err(self.error(position, DecodeErrorKind::ExpectedByte(':')))
+                        }
+                    }
+                }
+                State::StrBody { .. } => {}
+            }
+            // strings are skipped over whole rather than byte by byte
+            if let State::StrBody { start, body, end, key } = self.state {
+                if end > self.buffer.len() {
+                    self.position = self.buffer.len();
+                    return Ok(false);
+                }
+                self.position = end;
+                self.state = State::Value;
+                if key {
+                    try!(self.key(start, body, end));
+                } else if self.finish() {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn value(&mut self, position: usize, byte: u8) -> Result<bool, DecodeError> {
+        let (in_list, expect_key) = match self.stack.last() {
+            Some(&Frame::List) => (true, false),
+            Some(&Frame::Dict { expect_key, .. }) => (false, expect_key),
+            None => (false, false),
+        };
+        if (in_list || expect_key) && byte == b'e' {
+            self.stack.pop();
+            self.position += 1;
+            return Ok(self.finish());
+        }
+        if expect_key {
+            return match byte {
+                b'0'...b'9' => {
+                    self.state = State::StrLen {
+                        start: position,
+                        key: true,
+                    };
+                    self.position += 1;
+                    Ok(false)
+                }
+                _ => Err(self.error(position, DecodeErrorKind::InvalidString)),
+            };
+        }
+        match byte {
+            b'i' => {
+                self.state = State::Int { start: position };
+                self.position += 1;
+            }
+            b'l' | b'd' => {
+                if self.stack.len() >= MAX_NESTING_DEPTH {
+                    return Err(self.error(position, DecodeErrorKind::NestingTooDeep));
+                }
+                self.stack.push(if byte == b'l' {
+                    Frame::List
+                } else {
+                    Frame::Dict {
+                        expect_key: true,
+                        last_key: None,
+                    }
+                });
+                self.position += 1;
+            }
+            b'0'...b'9' => {
+                self.state = State::StrLen {
+                    start: position,
+                    key: false,
+                };
+                self.position += 1;
+            }
+            _ => return Err(self.error(position, DecodeErrorKind::UnknownType)),
+        }
+        Ok(false)
+    }
+
+    fn int(&mut self, start: usize, position: usize, byte: u8) -> Result<bool, DecodeError> {
+        let negative = self.buffer.get(start + 1) == Some(&b'-');
+        let digits_start = if negative { start + 2 } else { start + 1 };
+        if byte == b'-' && position == start + 1 {
+            self.position += 1;
+            return Ok(false);
+        }
+        match byte {
+            b'0'...b'9' => {
+                if position > digits_start && self.buffer[digits_start] == b'0' {
+                    return Err(self.error(digits_start, DecodeErrorKind::LeadingZero));
+                }
+                self.position += 1;
+                Ok(false)
+            }
+            _ => {
+                if position == digits_start {
+                    return Err(self.error(position, DecodeErrorKind::ExpectedDigit));
+                }
+                if negative && &self.buffer[digits_start..position] == b"0" {
+                    return Err(self.error(start, DecodeErrorKind::IntNegativeZero));
+                }
+                try!(str::from_utf8(&self.buffer[(start + 1)..position])
+                    .unwrap()
+                    .parse::<i64>()
+                    .map_err(|e| self.error(start, DecodeErrorKind::IntParsingErr(e))));
+                if byte != b'e' {
+                    return Err(self.error(position, DecodeErrorKind::ExpectedByte('e')));
+                }
+                self.position += 1;
+                self.state = State::Value;
+                Ok(self.finish())
+            }
+        }
+    }
+
+    // Checks a finished dict key against the one before it.
+    fn key(&mut self, start: usize, body: usize, end: usize) -> Result<(), DecodeError> {
+        let buffer = &self.buffer;
+        if let Some(&mut Frame::Dict { ref mut expect_key, ref mut last_key }) = self.stack
+            .last_mut() {
+            if let Some((last_start, last_end)) = *last_key {
+                let last = &buffer[last_start..last_end];
+                let key = &buffer[body..end];
+                if key == last {
+                    return Err(DecodeError {
+                        position: Some(start),
+                        kind: DecodeErrorKind::DuplicateKey,
+                    });
+                } else if key < last {
+                    return Err(DecodeError {
+                        position: Some(start),
+                        kind: DecodeErrorKind::UnsortedKeys,
+                    });
+                }
+            }
+            *last_key = Some((body, end));
+            *expect_key = false;
+        }
+        Ok(())
+    }
+
+    // Called once a value has been read. Returns whether it was the top level
+    // one, otherwise a dict that just got its value wants a key next.
+    fn finish(&mut self) -> bool {
+        match self.stack.last_mut() {
+            None => true,
+            Some(&mut Frame::Dict { ref mut expect_key, .. }) => {
+                *expect_key = true;
+                false
+            }
+            Some(&mut Frame::List) => false,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::cmp;
+use std::ops::Range;
+use metainfo::{FileInfo, ModeInfo, FPath};
+
+// Where the torrent's files sit in its data, which pieces run across without
+// regard for file boundaries.
+//
+//     let layout = Layout::new(&metainfo.info);
+//     for span in layout.map(piece, begin, block.len() as u64).unwrap() {
+//         // write block[span.block_offset..][..span.length] to span.path at span.offset
+//     }
+#[derive(Debug, Clone)]
+pub struct Layout {
+    piece_length: u64,
+    total_length: u64,
+    files: Vec<LayoutFile>,
+    // Whether the padding is only there to start v2 files on piece
+    // boundaries, so that no piece runs on past the end of its file.
+    aligned: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutFile {
+    pub path: FPath,
+    // Where the file starts in the torrent's data.
+    pub start: u64,
+    pub length: u64,
+    // BEP 47 padding is only zeros and is never stored.
+    pub padding: bool,
+}
+
+// Part of a block that falls in one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSpan {
+    // The index in `Layout::files`.
+    pub file: usize,
+    pub path: FPath,
+    // Where the span starts in the file.
+    pub offset: u64,
+    pub length: u64,
+    // Where the span starts in the block that was mapped.
+    pub block_offset: u64,
+}
+
+impl Layout {
+    // v1 files follow each other as listed, padding files included. v2-only
+    // torrents start every file on a piece boundary, as if padded.
+    pub fn new(info: &FileInfo) -> Layout {
+        let mut layout = Layout {
+            piece_length: info.piece_length,
+            total_length: 0,
+            files: Vec::new(),
+            aligned: !info.is_v1(),
+        };
+        if info.is_v1() {
+            match info.mode_info {
+                ModeInfo::Single(ref single) => {
+                    layout.push(info.name.iter().cloned().collect(), single.length, false)
+                }
+                ModeInfo::Multi(ref multi) => {
+                    for file in multi.files.iter() {
+                        layout.push(file.path.clone(), file.length, file.is_padding());
+                    }
+                }
+            }
+        } else {
+            for file in info.file_tree.iter() {
+                let padding = (info.piece_length - layout.total_length % info.piece_length) %
+                              info.piece_length;
+                if padding > 0 {
+                    layout.push(Vec::new(), padding, true);
+                }
+                layout.push(file.path.clone(), file.length, false);
+            }
+        }
+        layout
+    }
+
+    fn push(&mut self, path: FPath, length: u64, padding: bool) {
+        self.files.push(LayoutFile {
+            path: path,
+            start: self.total_length,
+            length: length,
+            padding: padding,
+        });
+        self.total_length += length;
+    }
+
+    pub fn files(&self) -> &[LayoutFile] {
+        &self.files
+    }
+
+    pub fn piece_length(&self) -> u64 {
+        self.piece_length
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    pub fn piece_count(&self) -> usize {
+        if self.piece_length == 0 {
+            return 0;
+        }
+        ((self.total_length + self.piece_length - 1) / self.piece_length) as usize
+    }
+
+    // The last piece may be short. In v2-only torrents so is the last piece
+    // of every file, as the padding after it is not part of any piece.
+    pub fn piece_size(&self, piece: usize) -> Option<u64> {
+        let start = piece as u64 * self.piece_length;
+        if piece >= self.piece_count() || start >= self.total_length {
+            return None;
+        }
+        let mut end = cmp::min(start + self.piece_length, self.total_length);
+        if self.aligned {
+            let file = self.files.iter().find(|file| {
+                !file.padding && file.start <= start && start < file.start + file.length
+            });
+            if let Some(file) = file {
+                end = cmp::min(end, file.start + file.length);
+            }
+        }
+        Some(end - start)
+    }
+
+    // The parts of files a block of a piece covers, in order. Padding has no
+    // spans, so they may add up to less than `length`. None when the block
+    // does not fit in the piece.
+    pub fn map(&self, piece: usize, offset: u64, length: u64) -> Option<Vec<FileSpan>> {
+        match self.piece_size(piece) {
+            Some(size) if offset + length <= size => (),
+            _ => return None,
+        }
+        let start = piece as u64 * self.piece_length + offset;
+        let end = start + length;
+        let mut spans = Vec::new();
+        for (index, file) in self.files.iter().enumerate() {
+            let file_end = file.start + file.length;
+            if file_end <= start || file.padding {
+                continue;
+            }
+            if file.start >= end {
+                break;
+            }
+            let span_start = cmp::max(start, file.start);
+            let span_end = cmp::min(end, file_end);
+            spans.push(FileSpan {
+                file: index,
+                path: file.path.clone(),
+                offset: span_start - file.start,
+                length: span_end - span_start,
+                block_offset: span_start - start,
+            });
+        }
+        Some(spans)
+    }
+
+    // The piece and the offset in it of a position in a file.
+    pub fn position(&self, file: usize, offset: u64) -> Option<(usize, u64)> {
+        match self.files.get(file) {
+            Some(file) if offset < file.length => {
+                let position = file.start + offset;
+                Some(((position / self.piece_length) as usize, position % self.piece_length))
+            }
+            _ => None,
+        }
+    }
+
+    // Every piece holding some of a file, which is what selecting the file
+    // or changing its priority has to act on. Empty files have none.
+    pub fn file_pieces(&self, file: usize) -> Range<usize> {
+        match self.files.get(file) {
+            Some(file) if file.length > 0 => {
+                let first = file.start / self.piece_length;
+                let last = (file.start + file.length - 1) / self.piece_length;
+                (first as usize)..(last as usize + 1)
+            }
+            _ => 0..0,
+        }
+    }
+}
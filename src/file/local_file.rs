@@ -1,18 +1,25 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
 use std::ops::{Index, IndexMut};
-use sha1::{Sha1, Digest};
-use metainfo::FileInfo;
-use metainfo::SHA1Hash20b;
+use std::path::{Path, PathBuf};
+use metainfo::MetaInfo;
 use bit_vec::BitVec;
 use file::PartialFileTrait;
+use file::layout::Layout;
 
 pub struct PartialFile {
     collection: PieceCollection,
-    info: FileInfo,
+    layout: Layout,
+    metainfo: MetaInfo,
+    // Where verified pieces are written, if anywhere.
+    directory: Option<PathBuf>,
+    // File bytes of the pieces not yet verified.
+    left: u64,
 }
 
 impl PartialFileTrait for PartialFile {
     fn is_complete(&self) -> bool {
-        for i in 0..self.info.pieces.len() {
+        for i in 0..self.metainfo.info.piece_count() {
             if !self._is_piece_complete(i) {
                 return false;
             }
@@ -25,32 +32,40 @@ impl PartialFileTrait for PartialFile {
     }
 
     fn bit_array(&self) -> BitVec {
-        let mut bit_vec = BitVec::from_elem(self.info.pieces.len(), false);
+        let mut bit_vec = BitVec::from_elem(self.metainfo.info.piece_count(), false);
         for (i, ref piece) in self.collection.pieces.iter().enumerate() {
-            bit_vec.set(i, piece.definitely_complete);
+            bit_vec.set(i, piece.complete);
         }
         bit_vec
     }
 
     fn length(&self) -> usize {
-        self.info.pieces.len()
+        self.metainfo.info.piece_count()
     }
 }
 
 impl PartialFile {
-    pub fn new(info: &FileInfo) -> PartialFile {
+    pub fn new(metainfo: &MetaInfo) -> PartialFile {
+        let layout = Layout::new(&metainfo.info);
+        let left = (0..layout.piece_count()).fold(0, |left, i| left + file_bytes(&layout, i));
         PartialFile {
-            info: info.clone(),
-            collection: PieceCollection::new(&info.pieces, info.pieces.len() as u64),
+            metainfo: metainfo.clone(),
+            collection: PieceCollection::new(&layout),
+            layout: layout,
+            directory: None,
+            left: left,
         }
     }
 
+    // Verified pieces are written into the torrent's files, with the paths
+    // the layout gives them taken from `directory`.
+    pub fn with_directory(mut self, directory: &Path) -> PartialFile {
+        self.directory = Some(directory.to_path_buf());
+        self
+    }
+
     fn _is_piece_complete(&self, i: usize) -> bool {
-        let mut sha1: Sha1 = Sha1::new();
-        sha1.update(&self.collection[i].data);
-        let ref bytes1 = sha1.digest().bytes();
-        let ref bytes2 = self.info.pieces[i];
-        bytes1 == bytes2.as_slice()
+        self.collection.pieces.get(i).map_or(false, |piece| piece.complete)
     }
 
     pub fn get_piece<'a>(&'a self, i: usize) -> &'a Piece {
@@ -62,37 +77,109 @@ impl PartialFile {
         &mut self.collection.pieces[i]
     }
 
+    // Once a piece has all its bytes it is checked, against both the v1 and
+    // v2 hashes of a hybrid torrent, and written out. A piece that fails
+    // either is thrown away to be fetched again.
     pub fn add_piece(&mut self, index: usize, offset: usize, block: Vec<u8>) -> bool {
-        self.collection.add(index as usize, offset as usize, block)
+        if !self.collection.add(index as usize, offset as usize, block) {
+            return false;
+        }
+        let full = {
+            let piece = &self.collection.pieces[index];
+            piece.data.len() == piece.length as usize
+        };
+        if full {
+            let stored = self.metainfo.verify_piece(index, &self.collection.pieces[index].data) &&
+                         self._write_piece(index);
+            if stored {
+                self.collection.pieces[index].complete = true;
+                self.left -= file_bytes(&self.layout, index);
+            } else {
+                self.collection.pieces[index].data.clear();
+            }
+        }
+        true
+    }
+
+    fn _write_piece(&self, index: usize) -> bool {
+        let directory = match self.directory {
+            Some(ref directory) => directory,
+            None => return true,
+        };
+        let piece = &self.collection.pieces[index];
+        let spans = match self.layout.map(index, 0, piece.length as u64) {
+            Some(spans) => spans,
+            None => return false,
+        };
+        for span in spans {
+            let path = span.path.iter().fold(directory.clone(), |path, part| path.join(part));
+            let start = span.block_offset as usize;
+            let data = &piece.data[start..(start + span.length as usize)];
+            if let Err(e) = write_at(&path, span.offset, data) {
+                info!("Could not write piece {} to {}: {}", index, path.display(), e);
+                return false;
+            }
+        }
+        true
     }
 
     pub fn piece_length(&self) -> u64 {
-        self.info.piece_length
+        self.metainfo.info.piece_length
+    }
+
+    // Bytes of the pieces still missing, what trackers are told is left.
+    // Padding is not counted, as it is never in any file.
+    pub fn bytes_left(&self) -> u64 {
+        self.left
+    }
+
+    // Where each piece's data goes in the torrent's files.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
     }
 }
 
+// The bytes of piece `index` that are in files, leaving out padding.
+fn file_bytes(layout: &Layout, index: usize) -> u64 {
+    layout.piece_size(index)
+        .and_then(|size| layout.map(index, 0, size))
+        .map_or(0, |spans| spans.iter().fold(0, |sum, span| sum + span.length))
+}
+
+fn write_at(path: &Path, offset: u64, data: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        try!(fs::create_dir_all(parent));
+    }
+    let mut file = try!(OpenOptions::new().write(true).create(true).open(path));
+    try!(file.seek(SeekFrom::Start(offset)));
+    file.write_all(data)
+}
+
 pub struct Piece {
     data: Vec<u8>,
     length: u32,
-    hash: SHA1Hash20b,
-    definitely_complete: bool,
+    // Whether the data is all there and matches the torrent's hashes.
+    complete: bool,
 }
 
 impl Piece {
-    pub fn new(length: u32, hash: SHA1Hash20b) -> Piece {
+    pub fn new(length: u32) -> Piece {
         Piece {
             data: Vec::new(),
             length: length,
-            hash: hash,
-            definitely_complete: false,
+            complete: false,
         }
     }
+
+    // A complete piece is never written to again.
     pub fn add(&mut self, offset: usize, block: &[u8]) -> bool {
-        if offset as u32 > self.length || self.is_complete() {
+        if offset as u32 > self.length || self.complete {
             return false;
         }
         let existing_block = &mut self.data;
-        existing_block.resize(offset + block.len(), 0);
+        if existing_block.len() < offset + block.len() {
+            existing_block.resize(offset + block.len(), 0);
+        }
         println!("Adding {} bytes to file at offset {}", block.len(), offset);
         for i in 0..block.len() {
             existing_block[offset + i] = block[i];
@@ -102,49 +189,41 @@ impl Piece {
 
     pub fn get_offset<'a>(&'a mut self, begin: usize, offset: usize) -> Option<&'a [u8]> {
         let len = self.data.len();
-        if begin + offset < len && self.is_complete() {
+        if begin + offset <= len && self.complete {
             Some(&self.data[begin..(begin + offset)])
         } else {
             None
         }
     }
 
-    pub fn is_complete(&mut self) -> bool {
-        let mut sha1: Sha1 = Sha1::new();
-        sha1.update(&self.data);
-        let ref bytes1 = sha1.digest().bytes();
-        let ref bytes2 = self.hash;
-        bytes1 == bytes2.as_slice()
+    pub fn is_complete(&self) -> bool {
+        self.complete
     }
 }
 
 struct PieceCollection {
     pieces: Vec<Piece>,
-    piece_size: u64,
 }
 
 impl PieceCollection {
-    pub fn new(pieces: &[SHA1Hash20b], size: u64) -> PieceCollection {
+    pub fn new(layout: &Layout) -> PieceCollection {
         let mut vec = Vec::new();
-        for hash in pieces {
-            vec.push(Piece::new(size as u32, hash.clone()));
-        }
-        PieceCollection {
-            pieces: vec,
-            piece_size: size,
+        for index in 0..layout.piece_count() {
+            let size = layout.piece_size(index).unwrap();
+            vec.push(Piece::new(size as u32));
         }
+        PieceCollection { pieces: vec }
     }
 
     pub fn add(&mut self, index: usize, offset: usize, block: Vec<u8>) -> bool {
         if index >= self.pieces.len() {
             return false;
         }
-        if offset + block.len() > self.piece_size as usize {
+        if offset + block.len() > self.pieces[index].length as usize {
             return false;
         }
 
-        self.pieces[index].add(offset, &block);
-        true
+        self.pieces[index].add(offset, &block)
     }
 }
 
@@ -0,0 +1,109 @@
+use openssl::sha::{Sha256, sha256};
+use metainfo::SHA256Hash32b;
+
+type Digest = SHA256Hash32b;
+
+// BEP 52 hashes every file on its own, as a binary merkle tree over 16 KiB
+// blocks. The leaf count is padded to a power of two with all-zero hashes,
+// and a piece's hash is the root of the subtree covering its blocks.
+pub const BLOCK_SIZE: usize = 16 * 1024;
+
+pub fn block_hashes(data: &[u8]) -> Vec<Digest> {
+    data.chunks(BLOCK_SIZE).map(sha256).collect()
+}
+
+fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finish()
+}
+
+// The root of a tree with `width` leaves, `width` being a power of two of at
+// least `leaves.len()`, where the missing leaves are `pad`. At each level up
+// the padding becomes the hash of two padding nodes below.
+pub fn root_with_padding(leaves: &[Digest], width: usize, pad: Digest) -> Digest {
+    let mut level = leaves.to_vec();
+    let mut pad = pad;
+    let mut width = width;
+    while width > 1 {
+        if level.len() % 2 == 1 {
+            level.push(pad);
+        }
+        level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        pad = hash_pair(&pad, &pad);
+        width /= 2;
+    }
+    level.get(0).cloned().unwrap_or(pad)
+}
+
+// The hash of a subtree of `width` all-zero leaves.
+pub fn padding_hash(width: usize) -> Digest {
+    root_with_padding(&[], width, [0; 32])
+}
+
+fn blocks_per_piece(piece_length: u64) -> usize {
+    (piece_length / BLOCK_SIZE as u64) as usize
+}
+
+// The `pieces root` of a file from the hashes of its blocks. Empty files have
+// no root.
+pub fn pieces_root(block_hashes: &[Digest], piece_length: u64) -> Option<Digest> {
+    if block_hashes.is_empty() {
+        return None;
+    }
+    let per_piece = blocks_per_piece(piece_length);
+    if block_hashes.len() <= per_piece {
+        let width = block_hashes.len().next_power_of_two();
+        return Some(root_with_padding(block_hashes, width, [0; 32]));
+    }
+    Some(layer_root(&piece_layer(block_hashes, piece_length), piece_length))
+}
+
+// The hashes a file contributes to `piece layers`, one per piece. Only files
+// longer than a piece have them.
+pub fn piece_layer(block_hashes: &[Digest], piece_length: u64) -> Vec<Digest> {
+    let per_piece = blocks_per_piece(piece_length);
+    block_hashes.chunks(per_piece)
+        .map(|blocks| root_with_padding(blocks, per_piece, [0; 32]))
+        .collect()
+}
+
+// The `pieces root` a piece layer hashes up to.
+pub fn layer_root(layer: &[Digest], piece_length: u64) -> Digest {
+    let pad = padding_hash(blocks_per_piece(piece_length));
+    root_with_padding(layer, layer.len().next_power_of_two(), pad)
+}
+
+// Checks one piece of a v2 file. `layer` is the file's entry in `piece
+// layers` and is only needed for files longer than a piece.
+pub fn verify_piece(data: &[u8],
+                    index: usize,
+                    file_length: u64,
+                    piece_length: u64,
+                    pieces_root: &Digest,
+                    layer: Option<&[Digest]>)
+                    -> bool {
+    let blocks = block_hashes(data);
+    if file_length <= piece_length {
+        let width = blocks.len().next_power_of_two();
+        return index == 0 && data.len() as u64 == file_length &&
+               root_with_padding(&blocks, width, [0; 32]) == *pieces_root;
+    }
+    match layer.and_then(|layer| layer.get(index)) {
+        Some(expected) => {
+            root_with_padding(&blocks, blocks_per_piece(piece_length), [0; 32]) == *expected
+        }
+        None => false,
+    }
+}
+
+// Checks a file's piece layer against its `pieces root`.
+pub fn verify_layer(layer: &[Digest],
+                    file_length: u64,
+                    piece_length: u64,
+                    pieces_root: &Digest)
+                    -> bool {
+    let pieces = (file_length + piece_length - 1) / piece_length;
+    layer.len() as u64 == pieces && layer_root(layer, piece_length) == *pieces_root
+}
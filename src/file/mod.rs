@@ -2,9 +2,12 @@ use bit_vec::BitVec;
 
 mod local_file;
 mod peer_file;
+pub mod merkle;
+pub mod layout;
 
 pub use file::local_file::{PartialFile, Piece};
 pub use file::peer_file::*;
+pub use file::layout::{Layout, LayoutFile, FileSpan};
 
 pub trait PartialFileTrait {
     fn length(&self) -> usize;
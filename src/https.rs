@@ -0,0 +1,86 @@
+// HTTPS for hyper's client through OpenSSL, as hyper leaves TLS to whoever
+// builds the client. Servers are checked against the system's roots and the
+// URL's host name, like a browser would.
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use hyper;
+use hyper::client::Client;
+use hyper::net::{HttpStream, HttpsConnector, NetworkStream, SslClient};
+use openssl::ssl::{SslConnector, SslMethod, SslStream};
+
+// A client for both http:// and https:// URLs. Without a TLS context only
+// plain HTTP is left.
+pub fn client() -> Client {
+    match SslConnector::builder(SslMethod::tls()) {
+        Ok(builder) => Client::with_connector(HttpsConnector::new(Openssl(builder.build()))),
+        Err(e) => {
+            warn!("No TLS, https URLs will fail: {}", e);
+            Client::new()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Openssl(SslConnector);
+
+impl SslClient for Openssl {
+    type Stream = OpensslStream;
+
+    fn wrap_client(&self, stream: HttpStream, host: &str) -> hyper::Result<OpensslStream> {
+        match self.0.connect(host, stream) {
+            Ok(stream) => Ok(OpensslStream(Arc::new(Mutex::new(stream)))),
+            Err(e) => Err(hyper::Error::Ssl(Box::new(e))),
+        }
+    }
+}
+
+// hyper clones a stream to read and write it, an `SslStream` is one state
+// machine, so the clones share it.
+#[derive(Clone)]
+pub struct OpensslStream(Arc<Mutex<SslStream<HttpStream>>>);
+
+impl OpensslStream {
+    fn lock<'a>(&'a self) -> MutexGuard<'a, SslStream<HttpStream>> {
+        // a panic halfway through a read leaves nothing worse than a broken
+        // connection
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Read for OpensslStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock().read(buf)
+    }
+}
+
+impl Write for OpensslStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+impl NetworkStream for OpensslStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.lock().get_mut().peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.lock().get_ref().0.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.lock().get_ref().0.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        let mut stream = self.lock();
+        let _ = stream.shutdown();
+        stream.get_mut().close(how)
+    }
+}
@@ -3,9 +3,13 @@ extern crate byteorder;
 extern crate sha1;
 extern crate mio;
 extern crate bit_vec;
+extern crate openssl;
+extern crate openssl_sys;
+extern crate foreign_types;
 #[macro_use]
 extern crate log;
 
+#[macro_use]
 pub mod bencode;
 pub mod metainfo;
 
@@ -14,6 +18,10 @@ pub mod convert;
 pub mod tests;
 pub mod wire;
 pub mod file;
+pub mod rsa;
+pub mod x509;
+pub mod https;
+pub mod seed;
 
 use log::*;
 struct SimpleLogger;
@@ -6,46 +6,476 @@ extern crate hyper;
 extern crate log;
 
 use rustorrent::init;
-use rustorrent::bencode::decode::{belement_decode, DecodeResult};
+use rustorrent::bencode::decode::{DecodeResult, belement_decode};
+use rustorrent::bencode::json::{Binary, to_json};
+use rustorrent::bencode::borrowed::{BencodeRef, BDictRef, belement_decode_ref};
+use rustorrent::metainfo::{MetaInfo, SHA1Hash20b, TorrentBuilder, TorrentEditor, ModeInfo};
 use rustorrent::bencode::BDict;
-use rustorrent::metainfo::{MetaInfo, SHA1Hash20b};
-use rustorrent::wire::{Protocol, ChanMsg};
+use rustorrent::bencode::encode::bdict_encode;
+use rustorrent::wire::{Protocol, ChanMsg, PieceStore};
 use rustorrent::convert::TryFrom;
-use rustorrent::bencode::Bencode;
 use rustorrent::bencode::DecodeError;
-use rustorrent::metainfo::MetaInfoError;
+use rustorrent::metainfo::{MetaInfoError, MagnetLink, MagnetError, PathPolicy, SignaturePolicy,
+                           SignatureError};
+use rustorrent::x509::{Certificate, private_key_from_pem};
+use rustorrent::wire::metadata::fetch_metadata;
 use rustorrent::tracker::HttpTrackerHandler;
-use rustorrent::tracker::{TrackerResp, TrackerError, TrackerEvent};
+use rustorrent::file::{PartialFile, PartialFileTrait};
+use rustorrent::seed::{Seeder, WebSeed, HttpSeed, PieceSource, SeedError};
+use rustorrent::tracker::{TrackerResp, TrackerError, TrackerEvent, TrackerTiers, TrackerState,
+                          Announcer, Transfer, TransferCounters};
 
 use std::env;
 use std::fs::File;
 use std::io;
 use std::time::Duration;
-use std::time::SystemTime;
+use std::time::Instant;
 use std::thread::{sleep, spawn};
-use std::io::Read;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process;
 use std::thread;
 use std::thread::JoinHandle;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use hyper::Url;
 use rustorrent::tracker::http::TrackerHandler;
 use rustorrent::tracker::TrackerReq;
 
 use mio::channel::{Sender, Receiver};
-use sha1::Sha1;
 
 const DEFAULT_PORT: u32 = 12001;
 const DEFAULT_PEER_ID: &'static str = "-RT0001-048230984201";
+// Downloads go in the directory rustorrent is run from.
+const DOWNLOAD_DIRECTORY: &'static str = ".";
 
 pub fn main() {
     init();
-    let mut args = env::args();
-    if let Some(path_string) = args.nth(1) {
-        info!("Starting up");
-        let result = _begin_with_path(path_string);
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("bencode") => _bencode_command(&args[2..]),
+        Some("create") => _create_command(&args[2..]),
+        Some("info") => _info_command(&args[2..]),
+        Some("edit") => _edit_command(&args[2..]),
+        Some(_) => _start_command(&args[1..]),
+        None => _usage(),
+    }
+}
+
+// `[--trust <cert>]... [--require-signed | --require-trusted] <torrent | magnet>`
+// downloads. Tampered torrents are always refused; the flags also refuse
+// unsigned ones, or ones no trusted certificate vouches for.
+fn _start_command(args: &[String]) {
+    let mut target = None;
+    let mut policy = SignaturePolicy::RejectInvalid;
+    let mut trusted = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--require-signed" => policy = SignaturePolicy::RequireValid,
+            "--require-trusted" => policy = SignaturePolicy::RequireTrusted,
+            "--trust" => {
+                let path = match args.next() {
+                    Some(path) => path,
+                    None => return _usage(),
+                };
+                match _read_certificate(path) {
+                    Ok(certificate) => trusted.push(certificate),
+                    Err(e) => return _fail(&e),
+                }
+            }
+            _ if target.is_none() && !arg.starts_with('-') => target = Some(arg.as_str()),
+            _ => return _usage(),
+        }
+    }
+    match target {
+        Some(uri) if uri.starts_with("magnet:") => {
+            // the signatures are outside the info dict a magnet link fetches
+            if policy != SignaturePolicy::RejectInvalid {
+                return _fail("Magnet links carry no signatures to require");
+            }
+            info!("Starting up");
+            if let Err(e) = _begin_with_magnet(uri) {
+                _fail(&format!("Could not start {}: {:?}", uri, e));
+            }
+        }
+        Some(path_string) => {
+            info!("Starting up");
+            if let Err(e) = _begin_with_path(path_string.to_string(), policy, &trusted) {
+                _fail(&format!("Could not start {}: {:?}", path_string, e));
+            }
+        }
+        None => _usage(),
+    }
+}
+
+// `bencode dump [--base64] <file>` prints any bencoded file as JSON.
+fn _bencode_command(args: &[String]) {
+    if args.get(0).map(|arg| arg.as_str()) != Some("dump") {
+        return _usage();
+    }
+    let (binary, path) = match args.len() {
+        2 => (Binary::Hex, &args[1]),
+        3 if args[1] == "--base64" => (Binary::Base64, &args[2]),
+        _ => return _usage(),
+    };
+    let mut bytes = Vec::new();
+    if let Err(e) = File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)) {
+        return _fail(&format!("Could not read {}: {}", path, e));
+    }
+    match belement_decode(&bytes) {
+        Ok(DecodeResult(element, length)) => {
+            println!("{}", to_json(&element, binary));
+            if length != bytes.len() {
+                _fail(&format!("{} trailing byte(s) after the value", bytes.len() - length));
+            }
+        }
+        Err(e) => _fail(&format!("Could not decode {}: {}", path, e)),
+    }
+}
+
+// `create [options] <path>` writes a .torrent for a file or a directory.
+fn _create_command(args: &[String]) {
+    let mut path = None;
+    let mut output = None;
+    let mut signing = (None, None, None);
+    let mut builder_args = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" | "--private" | "--v2" | "--hybrid" => builder_args.push((arg.as_str(), "")),
+            "-a" | "--announce" | "-w" | "--web-seed" | "-c" | "--comment" | "-s" |
+            "--source" | "-l" | "--piece-length" | "-t" | "--threads" | "-o" | "--output" |
+            "--sign" | "--certificate" | "--signer" => {
+                let value = match args.next() {
+                    Some(value) => value.as_str(),
+                    None => return _usage(),
+                };
+                match arg.as_str() {
+                    "-o" | "--output" => output = Some(value.to_string()),
+                    "--sign" => signing.0 = Some(value),
+                    "--certificate" => signing.1 = Some(value),
+                    "--signer" => signing.2 = Some(value.to_string()),
+                    _ => builder_args.push((arg.as_str(), value)),
+                }
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg.clone()),
+            _ => return _usage(),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => return _usage(),
+    };
+
+    let mut builder = TorrentBuilder::new(&path);
+    for (flag, value) in builder_args {
+        builder = match flag {
+            "-p" | "--private" => builder.private(true),
+            "--v2" => builder.v2(true),
+            "--hybrid" => builder.hybrid(true),
+            "-a" | "--announce" => builder.announce(value),
+            "-w" | "--web-seed" => builder.web_seed(value),
+            "-c" | "--comment" => builder.comment(value),
+            "-s" | "--source" => builder.source(value),
+            _ => {
+                let number = match value.parse() {
+                    Ok(number) => number,
+                    Err(_) => return _fail(&format!("{} needs a number, not {}", flag, value)),
+                };
+                if flag == "-l" || flag == "--piece-length" {
+                    builder.piece_length(number)
+                } else {
+                    builder.threads(number as usize)
+                }
+            }
+        };
+    }
+
+    let mut metainfo = match builder.build() {
+        Ok(metainfo) => metainfo,
+        Err(e) => return _fail(&format!("Could not create a torrent for {}: {}", path, e)),
+    };
+    if let (Some(key_path), certificate_path, signer) = signing {
+        if let Err(e) = _sign(&mut metainfo, key_path, certificate_path, signer) {
+            return _fail(&e);
+        }
+    }
+    let output = output.unwrap_or_else(|| {
+        format!("{}.torrent",
+                metainfo.info.name.clone().unwrap_or("out".to_string()))
+    });
+    let hash = _hex(&metainfo.info_hash);
+    let bdict: BDict = metainfo.into();
+    if let Err(e) = File::create(&output).and_then(|mut file| file.write_all(&bdict_encode(&bdict))) {
+        return _fail(&format!("Could not write {}: {}", output, e));
+    }
+    println!("{} {}", hash, output);
+}
+
+// `info [--trust <cert>]... <file>` prints what a torrent holds and whose
+// signatures it carries.
+fn _info_command(args: &[String]) {
+    let mut path = None;
+    let mut trusted = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trust" => {
+                let certificate = match args.next() {
+                    Some(certificate) => certificate,
+                    None => return _usage(),
+                };
+                match _read_certificate(certificate) {
+                    Ok(certificate) => trusted.push(certificate),
+                    Err(e) => return _fail(&e),
+                }
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return _usage(),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => return _usage(),
+    };
+    let metainfo = match _read_file(path).and_then(|bytes| _parse_torrent(path, &bytes)) {
+        Ok(metainfo) => metainfo,
+        Err(e) => return _fail(&e),
+    };
+    let info = &metainfo.info;
+    println!("name:         {}", info.name.as_ref().map_or("", |name| name.as_str()));
+    if info.is_v1() {
+        println!("info-hash:    {}", _hex(&metainfo.info_hash));
+    }
+    if let Some(ref hash) = metainfo.info_hash_v2 {
+        println!("info-hash v2: {}", _hex(hash));
+    }
+    println!("piece size:   {}", _size(info.piece_length));
+    println!("pieces:       {}", info.piece_count());
+
+    let files: Vec<(String, u64)> = match info.mode_info {
+        ModeInfo::Single(ref file) => vec![(String::new(), file.length)],
+        ModeInfo::Multi(ref multi) => {
+            multi.files
+                .iter()
+                .filter(|file| !file.is_padding())
+                .map(|file| (file.path.join("/"), file.length))
+                .collect()
+        }
+    };
+    let total = files.iter().map(|&(_, length)| length).sum::<u64>();
+    println!("size:         {} in {} file(s)", _size(total), files.len());
+    if let ModeInfo::Multi(_) = info.mode_info {
+        for (path, length) in files {
+            println!("  {:>12}  {}", _size(length), path);
+        }
+    }
+
+    let tiers = if metainfo.announce_list.is_empty() {
+        metainfo.announce.iter().map(|url| vec![url.clone()]).collect()
     } else {
-        _usage();
+        metainfo.announce_list.clone()
+    };
+    for (index, tier) in tiers.iter().enumerate() {
+        println!("tier {}:       {}", index + 1, tier.join(" "));
+    }
+    for url in metainfo.url_list.iter().chain(metainfo.httpseeds.iter()) {
+        println!("web seed:     {}", url);
+    }
+
+    let mut flags = Vec::new();
+    if info.private == Some(1) {
+        flags.push("private".to_string());
+    }
+    flags.push(match (info.is_v1(), info.is_v2()) {
+        (true, true) => "hybrid",
+        (false, true) => "v2",
+        _ => "v1",
+    }.to_string());
+    if let Some(ref source) = info.source {
+        flags.push(format!("source={}", source));
+    }
+    println!("flags:        {}", flags.join(", "));
+    if let Some(ref comment) = metainfo.comment {
+        println!("comment:      {}", comment);
+    }
+    if let Some(ref created_by) = metainfo.created_by {
+        println!("created by:   {}", created_by);
+    }
+    if let Some(date) = metainfo.creation_date {
+        println!("created:      {}", date);
+    }
+    for status in metainfo.verify_signatures(&trusted) {
+        println!("signed by:    {} ({})", status.signer, status.state);
+    }
+}
+
+// `edit [options] <file>` changes a torrent without touching its info dict.
+// Changing the source or private flag makes a new info-hash, a different
+// torrent to every peer and tracker, so it takes `--allow-rehash`.
+fn _edit_command(args: &[String]) {
+    let mut path = None;
+    let mut output = None;
+    let mut allow_rehash = false;
+    let mut editor = TorrentEditor::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        editor = match arg.as_str() {
+            "--clear-trackers" => editor.clear_trackers(),
+            "--no-comment" => editor.comment(None),
+            "--no-source" => editor.source(None),
+            "-p" | "--private" => editor.private(true),
+            "--public" => editor.private(false),
+            "--allow-rehash" => {
+                allow_rehash = true;
+                editor
+            }
+            "-a" | "--announce" | "--remove-tracker" | "-c" | "--comment" | "-s" | "--source" |
+            "-o" | "--output" => {
+                let value = match args.next() {
+                    Some(value) => value.as_str(),
+                    None => return _usage(),
+                };
+                match arg.as_str() {
+                    "-a" | "--announce" => editor.announce(value),
+                    "--remove-tracker" => editor.remove_tracker(value),
+                    "-c" | "--comment" => editor.comment(Some(value)),
+                    "-s" | "--source" => editor.source(Some(value)),
+                    _ => {
+                        output = Some(value.to_string());
+                        editor
+                    }
+                }
+            }
+            _ if path.is_none() && !arg.starts_with('-') => {
+                path = Some(arg.clone());
+                editor
+            }
+            _ => return _usage(),
+        };
     }
+    let path = match path {
+        Some(path) => path,
+        None => return _usage(),
+    };
+
+    let bytes = match _read_file(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => return _fail(&e),
+    };
+    let before = match _parse_torrent(&path, &bytes) {
+        Ok(metainfo) => metainfo,
+        Err(e) => return _fail(&e),
+    };
+    if editor.changes_info(&before) && !allow_rehash {
+        let signed = if before.signatures.is_empty() { "" } else { " and drop its signatures" };
+        return _fail(&format!("Editing {} this way would change its info-hash{}, pass \
+                               --allow-rehash to do so",
+                              path,
+                              signed));
+    }
+    let edited = match editor.edit(&bytes) {
+        Ok(edited) => edited,
+        Err(e) => return _fail(&format!("Could not edit {}: {}", path, e)),
+    };
+    let after = match _parse_torrent(&path, &edited) {
+        Ok(metainfo) => metainfo,
+        Err(e) => return _fail(&e),
+    };
+    if after.info_hash != before.info_hash {
+        let _ = writeln!(io::stderr(),
+                         "The info-hash changed from {} to {}",
+                         _hex(&before.info_hash),
+                         _hex(&after.info_hash));
+        if !before.signatures.is_empty() {
+            let _ = writeln!(io::stderr(), "Dropped the signatures over the old info dict");
+        }
+    }
+    let output = output.unwrap_or(path);
+    if let Err(e) = File::create(&output).and_then(|mut file| file.write_all(&edited)) {
+        return _fail(&format!("Could not write {}: {}", output, e));
+    }
+    println!("{} {}", _hex(&after.info_hash), output);
+}
+
+fn _read_file(path: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    try!(File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| format!("Could not read {}: {}", path, e)));
+    Ok(bytes)
+}
+
+fn _parse_torrent(path: &str, bytes: &[u8]) -> Result<MetaInfo, String> {
+    match belement_decode_ref(bytes) {
+        Ok(DecodeResult(BencodeRef::BDict(dict), _)) => {
+            MetaInfo::try_from(dict).map_err(|e| format!("Could not read {}: {}", path, e))
+        }
+        Ok(_) => Err(format!("{} is not a torrent", path)),
+        Err(e) => Err(format!("Could not decode {}: {}", path, e)),
+    }
+}
+
+fn _hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// In bytes and the largest binary unit it makes sense in.
+fn _size(bytes: u64) -> String {
+    const UNITS: [&'static str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = None;
+    for candidate in UNITS.iter() {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = Some(candidate);
+    }
+    match unit {
+        Some(unit) => format!("{:.1} {}", size, unit),
+        None => format!("{} B", bytes),
+    }
+}
+
+// Signs with a PEM key, embedding the certificate when given. The signer is
+// named after the certificate unless told otherwise.
+fn _sign(metainfo: &mut MetaInfo,
+         key_path: &str,
+         certificate_path: Option<&str>,
+         signer: Option<String>)
+         -> Result<(), String> {
+    let key = try!(private_key_from_pem(&try!(_read_text(key_path)))
+        .map_err(|e| format!("Could not read the key {}: {}", key_path, e)));
+    let certificate = match certificate_path {
+        Some(path) => Some(try!(_read_certificate(path))),
+        None => None,
+    };
+    let signer = try!(signer.or_else(|| certificate.as_ref().and_then(|c| c.subject_name()))
+        .ok_or("--sign needs --signer or a --certificate with a common name".to_string()));
+    metainfo.sign(&signer, &key, certificate.as_ref())
+        .map_err(|e| format!("Could not sign as {}: {}", signer, e))
+}
+
+fn _read_certificate(path: &str) -> Result<Certificate, String> {
+    Certificate::from_pem(&try!(_read_text(path)))
+        .map_err(|e| format!("Could not read the certificate {}: {}", path, e))
+}
+
+fn _read_text(path: &str) -> Result<String, String> {
+    let mut text = String::new();
+    try!(File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("Could not read {}: {}", path, e)));
+    Ok(text)
+}
+
+fn _fail(message: &str) {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1);
 }
 
 type SuccessType = ();
@@ -55,12 +485,17 @@ enum FatalError {
     IOError(io::Error),
     DecodeError(DecodeError),
     MetaInfoError(MetaInfoError),
+    MagnetError(MagnetError),
+    SignatureError(SignatureError),
+    NoMetadata,
 }
 
-fn _begin_with_path(path_string: String) -> Result<SuccessType, FatalError> {
+fn _begin_with_path(path_string: String,
+                    policy: SignaturePolicy,
+                    trusted: &[Certificate])
+                    -> Result<SuccessType, FatalError> {
     let mut bytes: Vec<u8> = Vec::new();
-    let belement: Bencode;
-    let bdict: BDict;
+    let bdict: BDictRef;
     let metainfo: MetaInfo;
 
     // read the file and change the result type if fail
@@ -74,14 +509,9 @@ fn _begin_with_path(path_string: String) -> Result<SuccessType, FatalError> {
         return Err(FatalError::IOError(file_open_result.err().unwrap()));
     }
 
-    // compute the very important metainfo hash
-    let mut sha1 = Sha1::new();
-    sha1.update(&bytes);
-    let hash = sha1.digest().bytes();
-
     // parse into a bencoded structure
-    let parse_result = belement_decode(&bytes);
-    if let Ok(DecodeResult(Bencode::BDict(dict), offset)) = parse_result {
+    let parse_result = belement_decode_ref(&bytes);
+    if let Ok(DecodeResult(BencodeRef::BDict(dict), offset)) = parse_result {
         bdict = dict;
     } else {
         return Err(FatalError::DecodeError(parse_result.err().unwrap()));
@@ -89,11 +519,8 @@ fn _begin_with_path(path_string: String) -> Result<SuccessType, FatalError> {
 
     let metainfo_result = MetaInfo::try_from(bdict);
     if let Ok(metainfo) = metainfo_result {
-        let mut hash_array = Vec::new();
-        for &b in hash.iter() {
-            hash_array.push(b);
-        }
-        _begin_protocol_session(&metainfo, hash_array);
+        try!(_check_signatures(&metainfo, policy, trusted));
+        _begin_protocol_session(&metainfo);
     } else {
         return Err(FatalError::MetaInfoError(metainfo_result.err().unwrap()));
     }
@@ -101,122 +528,317 @@ fn _begin_with_path(path_string: String) -> Result<SuccessType, FatalError> {
     return Ok(());
 }
 
+// Refuses the torrent if `policy` says so, and reports the signatures.
+fn _check_signatures(metainfo: &MetaInfo,
+                     policy: SignaturePolicy,
+                     trusted: &[Certificate])
+                     -> Result<(), FatalError> {
+    let statuses = try!(metainfo.check_signatures(policy, trusted)
+        .map_err(FatalError::SignatureError));
+    for status in statuses {
+        info!("Signed by {} ({})",
+              status.subject.as_ref().unwrap_or(&status.signer),
+              status.state);
+    }
+    Ok(())
+}
+
+// Magnet links only give the info-hash, so the info dict is asked for from
+// each peer in turn until one sends a copy that matches it.
+fn _begin_with_magnet(uri: &str) -> Result<SuccessType, FatalError> {
+    const METADATA_TIMEOUT_SECONDS: u64 = 20;
+    let link = try!(MagnetLink::try_from(uri).map_err(FatalError::MagnetError));
+    let peer_id = DEFAULT_PEER_ID.to_string().into_bytes();
 
-fn _begin_protocol_session(info: &MetaInfo, hash: SHA1Hash20b) {
-    let real_hash = match info.info.original {
-        Some(ref original_dict) => original_dict.hash(),
-        None => panic!("No freaking hash!"),
-    };
+    let trackers: Vec<TrackerState> =
+        link.trackers.iter().map(|tracker| TrackerState::new(tracker)).collect();
+    let mut peers: Vec<(SocketAddr, SHA1Hash20b)> = Vec::new();
+    for hash in link.info_hashes() {
+        for &(ref host, port) in link.peers.iter() {
+            if let Ok(addrs) = (host.as_str(), port).to_socket_addrs() {
+                peers.extend(addrs.map(|addr| (addr, hash.clone())));
+            }
+        }
+        for tracker in trackers.iter() {
+            // nothing is known to be left yet, but seeds are only handed to
+            // peers that still need something
+            let transfer = Transfer { left: 1, ..Default::default() };
+            match _announce(tracker, &hash, &peer_id, TrackerEvent::Started, transfer) {
+                Ok(response) => {
+                    peers.extend(response.peers
+                        .iter()
+                        .map(|peer| (SocketAddr::new(peer.ip, peer.port), hash.clone())))
+                }
+                Err(Some(e)) => info!("Querying tracker {} failed: {}", tracker.url, e),
+                Err(None) => info!("Could not query tracker {}", tracker.url),
+            }
+        }
+    }
+
+    for &(ref addr, ref hash) in peers.iter() {
+        info!("Asking {} for the metadata", addr);
+        let timeout = Duration::from_secs(METADATA_TIMEOUT_SECONDS);
+        let metadata = match fetch_metadata(addr, hash, DEFAULT_PEER_ID, timeout) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                info!("Could not get the metadata from {}: {}", addr, e);
+                continue;
+            }
+        };
+        match link.to_metainfo(&metadata) {
+            Ok(metainfo) => {
+                _begin_protocol_session(&metainfo);
+                return Ok(());
+            }
+            Err(e) => info!("{} sent bad metadata: {}", addr, e),
+        }
+    }
+    Err(FatalError::NoMetadata)
+}
+
+// Nothing from the torrent may name a file outside the download directory.
+fn _sanitize(metainfo: &mut MetaInfo) {
+    if let Ok(rewrites) = metainfo.sanitize_paths(PathPolicy::Rewrite) {
+        for rewrite in rewrites {
+            info!("Rewrote unsafe path {}", rewrite);
+        }
+    }
+}
 
-    match Protocol::new(info, real_hash.clone(), DEFAULT_PEER_ID) {
+// Files are written under their sanitized paths, while web seeds are asked
+// for them under the names the torrent gives.
+fn _begin_protocol_session(original: &MetaInfo) {
+    let mut sanitized = original.clone();
+    _sanitize(&mut sanitized);
+    let info = &sanitized;
+    let hash = info.info_hash.clone();
+    let counters = Arc::new(TransferCounters::new(0));
+    let partial_file = PartialFile::new(info).with_directory(&_download_directory(info));
+    let store = PieceStore::new(partial_file, counters.clone());
+    let running = _watch_for_quit();
+
+    match Protocol::new(info, hash.clone(), DEFAULT_PEER_ID, store.clone()) {
         (protocol, sender, receiver) => {
             let pwp = _start_peer_wire_protocol_thread(protocol);
-            _start_tracker(&hash,
+            let seeds = _start_seeds(original, store, running.clone());
+            let stop = sender.clone();
+            _start_tracker(&info.info_hashes(),
                            info,
                            &DEFAULT_PEER_ID.to_string().into_bytes(),
+                           counters,
+                           running.clone(),
                            sender,
                            receiver);
+
+            // the session ends with the trackers, take the other threads along
+            running.store(false, Ordering::SeqCst);
+            let _ = stop.send(ChanMsg::Shutdown);
+            let _ = pwp.join();
+            if let Some(seeds) = seeds {
+                let _ = seeds.join();
+            }
         }
     }
 }
 
+// Files of multi-file torrents go in a directory named after the torrent.
+fn _download_directory(info: &MetaInfo) -> PathBuf {
+    let directory = PathBuf::from(DOWNLOAD_DIRECTORY);
+    match (&info.info.mode_info, &info.info.name) {
+        (&ModeInfo::Multi(_), &Some(ref name)) => directory.join(name),
+        _ => directory,
+    }
+}
+
+// Typing "quit" ends the session, after the trackers are told.
+fn _watch_for_quit() -> Arc<AtomicBool> {
+    let running = Arc::new(AtomicBool::new(true));
+    let flag = running.clone();
+    info!("Type quit to stop");
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut line = String::new();
+        while stdin.read_line(&mut line).unwrap_or(0) > 0 {
+            if line.trim() == "quit" {
+                flag.store(false, Ordering::SeqCst);
+                return;
+            }
+            line.clear();
+        }
+    });
+    running
+}
+
 fn _start_peer_wire_protocol_thread(mut protocol: Protocol) -> JoinHandle<()> {
     thread::spawn(move || protocol.run())
 }
 
-fn _start_tracker(hash: &SHA1Hash20b,
+// BEP 19 web seeds and BEP 17 HTTP seeds fill in whatever pieces are
+// missing, each one left alone for a while after it fails and dropped once
+// it keeps failing.
+fn _start_seeds(info: &MetaInfo,
+                store: PieceStore,
+                running: Arc<AtomicBool>)
+                -> Option<JoinHandle<()>> {
+    const MAX_SEED_FAILURES: u32 = 8;
+    let mut sources: Vec<Result<Box<PieceSource + Send>, SeedError>> = Vec::new();
+    for url in info.url_list.iter() {
+        sources.push(WebSeed::new(url, info).map(|seed| Box::new(seed) as Box<_>));
+    }
+    for url in info.httpseeds.iter() {
+        sources.push(HttpSeed::new(url, info).map(|seed| Box::new(seed) as Box<_>));
+    }
+    let mut seeders = Vec::new();
+    for source in sources {
+        match source {
+            Ok(source) => seeders.push(Seeder::new(source)),
+            Err(e) => info!("Skipping seed: {}", e),
+        }
+    }
+    if seeders.is_empty() {
+        return None;
+    }
+    Some(thread::spawn(move || {
+        while running.load(Ordering::SeqCst) && !store.is_complete() && !seeders.is_empty() {
+            let now = Instant::now();
+            for seeder in seeders.iter_mut().filter(|seeder| seeder.backoff().is_ready(now)) {
+                let url = seeder.source().url().to_string();
+                match seeder.download(&store) {
+                    Ok(added) => info!("Seed {} sent {} piece(s)", url, added),
+                    Err(e) => info!("Seed {} failed: {}", url, e),
+                }
+            }
+            seeders.retain(|seeder| seeder.backoff().failures() < MAX_SEED_FAILURES);
+            thread::sleep(Duration::from_secs(1));
+        }
+    }))
+}
+
+// Hybrid torrents are announced under both their info-hashes, as their v1
+// and v2 swarms are separate. Each goes through the BEP 12 tiers on its own,
+// from `started` until the session ends with `stopped`.
+fn _start_tracker(hashes: &[SHA1Hash20b],
                   info: &MetaInfo,
                   peer_id: &SHA1Hash20b,
+                  counters: Arc<TransferCounters>,
+                  running: Arc<AtomicBool>,
                   sender: Sender<ChanMsg>,
                   recv: Receiver<ChanMsg>) {
-    const DEFAULT_TRACKER_INTERVAL_SECONDS: u64 = 10;
-    let SLEEP_DURATION: Duration = Duration::from_millis(100);
-    let mut last_request_time: SystemTime = SystemTime::now();
-    let mut result_response = _get_tracker_response(hash, info, peer_id);
-    let mut interval: u64 = DEFAULT_TRACKER_INTERVAL_SECONDS;
-
-    loop {
-        let response_result = _get_tracker_response(hash, info, peer_id);
-        match &result_response {
-            &Ok(ref r) => {
-                info!("Querying tracker...");
-                last_request_time = SystemTime::now();
-                for peer in r.peers.iter() {
-                    let msg = ChanMsg::NewPeer(peer.ip, peer.port);
-                    sender.send(msg);
-                }
-                info!("Tracker has {} peers", r.peers.len());
-                if let Some(i) = r.interval {
-                    interval = i as u64;
-                }
-            }
-            &Err(Some(ref e)) => {
-                info!("Querying tracker failed: {}", e);
-                continue;
-            }
-            &Err(_) => {
-                info!("Unknown error");
+    let mut announcers: Vec<Announcer> = hashes.iter()
+        .map(|_| Announcer::new(TrackerTiers::new(info), counters.clone()))
+        .collect();
+    if announcers.iter().all(|announcer| announcer.tiers().is_empty()) {
+        return; //TODO Trackerless torrents need DHT
+    }
+
+    while running.load(Ordering::SeqCst) {
+        for (hash, announcer) in hashes.iter().zip(announcers.iter_mut()) {
+            if !announcer.is_due(Instant::now()) {
                 continue;
             }
-
-        };
-
-        interval = 10000;
-
-        info!("Interval between requests is {} second(s)", interval);
-        thread::sleep(Duration::from_millis(interval * 1000));
+            let event = announcer.event();
+            let result = announcer.announce(Instant::now(), |tracker, event, transfer| {
+                _announce(tracker, hash, peer_id, event, transfer)
+                    .map_err(|e| e.unwrap_or(TrackerError::Unknown))
+            });
+            match result {
+                Ok((url, r)) => {
+                    info!("Announced {:?} to tracker {}", event, url);
+                    if let Some(ref warning) = r.warning_message {
+                        info!("Tracker {} warns: {}", url, warning);
+                    }
+                    for peer in r.peers.iter() {
+                        let msg = ChanMsg::NewPeer(peer.ip, peer.port, hash.clone());
+                        sender.send(msg);
+                    }
+                    info!("Tracker has {} peers", r.peers.len());
+                }
+                Err(e) => {
+                    info!("Querying every tracker failed {} time(s), the last with: {}",
+                          announcer.failures(),
+                          e);
+                }
+            };
+        }
+        thread::sleep(Duration::from_secs(1));
     }
 
-    // TODO Implement this - goal is that it queries that tracker at a defined period
-    // Sends list of peers to pwp using Sender
+    for (hash, announcer) in hashes.iter().zip(announcers.iter_mut()) {
+        let result = announcer.stop(Instant::now(), |tracker, event, transfer| {
+            _announce(tracker, hash, peer_id, event, transfer)
+                .map_err(|e| e.unwrap_or(TrackerError::Unknown))
+        });
+        if let Err(e) = result {
+            info!("Could not tell the trackers we stopped: {}", e);
+        }
+    }
 }
 
-fn _get_tracker_response(hash: &SHA1Hash20b,
-                         info: &MetaInfo,
-                         peer_id: &SHA1Hash20b)
-                         -> Result<TrackerResp, Option<TrackerError>> {
-    let url_result = Url::parse(&info.announce);
+fn _announce(tracker: &TrackerState,
+             hash: &SHA1Hash20b,
+             peer_id: &SHA1Hash20b,
+             event: TrackerEvent,
+             transfer: Transfer)
+             -> Result<TrackerResp, Option<TrackerError>> {
+    let mut request: TrackerReq = _get_request_obj(hash, peer_id, event, transfer);
+    request.trackerid = tracker.tracker_id.clone();
+    if let Some(ref udp) = tracker.udp {
+        return udp.request(&request).map_err(|e| Some(e));
+    }
+    let url_result = Url::parse(&tracker.url);
     if !url_result.is_ok() {
         return Err(None); //TODO Signal some kind of parse error
     }
-    let url = url_result.unwrap();
-    let mut handler = HttpTrackerHandler::new(url);
-    let request: TrackerReq = _get_request_obj(hash, peer_id, info);
-    handler.request(&request).map_err(|e| Some(e))
+    HttpTrackerHandler::new(url_result.unwrap()).request(&request).map_err(|e| Some(e))
 }
 
 fn _get_request_obj(hash: &SHA1Hash20b,
                     peer_id: &SHA1Hash20b,
-                    info: &MetaInfo)
+                    event: TrackerEvent,
+                    transfer: Transfer)
                     -> TrackerReq {
-    let mut info_hash = Vec::new();
-    info_hash.resize(20, 0);
-
-    match info.info.original {
-        Some(ref original_dict) => info_hash = original_dict.hash(),
-        None => info!("No hash!"),
-    };
-
     TrackerReq {
-        info_hash: info_hash,
+        info_hash: hash.clone(),
         peer_id: peer_id.clone(),
         port: DEFAULT_PORT,
-        uploaded: 0,
-        downloaded: 0,
-        left: info.info.pieces.len() as u64,
+        uploaded: transfer.uploaded,
+        downloaded: transfer.downloaded,
+        left: transfer.left,
         compact: false,
         no_peer_id: false,
-        event: TrackerEvent::Started,
+        event: event,
         ip: None,
         numwant: None,
         key: None,
         trackerid: None,
+        supportcrypto: false,
+        requirecrypto: false,
+        ipv4: None,
+        ipv6: None,
     }
 }
 
 fn _usage() {
     match env::current_exe() {
-        Ok(path) => info!("Usage: {} torrent_file", path.display()),
-        _ => info!("Invalid arguments. Format is: torrent_file"),
+        Ok(path) => {
+            info!("Usage: {} [--trust cert.pem]... [--require-signed | --require-trusted] \
+                   torrent_file | magnet_link",
+                  path.display());
+            info!("       {} bencode dump [--base64] file", path.display());
+            info!("       {} create [-a url]... [-w url]... [-c comment] [-p] \
+                   [--v2 | --hybrid] [-s source] [-l piece_length] [-t threads] [-o output] \
+                   [--sign key.pem [--certificate cert.pem] [--signer name]] path",
+                  path.display());
+            info!("       {} info [--trust cert.pem]... torrent_file", path.display());
+            info!("       {} edit [-a url]... [--remove-tracker url]... [--clear-trackers] \
+                   [-c comment | --no-comment] [-s source | --no-source] [-p | --public] \
+                   [--allow-rehash] [-o output] torrent_file",
+                  path.display());
+        }
+        _ => {
+            info!("Invalid arguments. Format is: [options] torrent_file | magnet_link | \
+                   bencode dump [--base64] file | create [options] path | \
+                   info [options] torrent_file | edit [options] torrent_file")
+        }
     }
 }
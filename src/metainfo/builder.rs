@@ -0,0 +1,493 @@
+use std::{error, fmt, fs, io, thread};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::BTreeMap;
+use sha1::Sha1;
+use openssl::sha::sha256;
+use bencode::encode::bdict_encode;
+use file::merkle::{self, BLOCK_SIZE};
+use metainfo::{MetaInfo, FileInfo, FileEntry, TreeFile, ModeInfo, SingleFileInfo, MultiFileInfo,
+               SHA1Hash20b, SHA256Hash32b, FPath};
+
+const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+// Aim for about this many pieces when choosing the piece length.
+const TARGET_PIECES: u64 = 1500;
+const DEFAULT_THREADS: usize = 4;
+
+// Creates a torrent for a file or a directory.
+//
+//     let metainfo = try!(TorrentBuilder::new("build/out")
+//         .announce("http://tracker.example.com/announce")
+//         .private(true)
+//         .build());
+//     let bdict: BDict = metainfo.into();
+pub struct TorrentBuilder {
+    path: PathBuf,
+    piece_length: Option<u64>,
+    trackers: Vec<Vec<String>>,
+    web_seeds: Vec<String>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+    private: bool,
+    source: Option<String>,
+    v2: bool,
+    hybrid: bool,
+    threads: usize,
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    IOError(io::Error),
+    NoFiles,
+    // The path has no last component to name the torrent after, like `/`.
+    NoName(PathBuf),
+    InvalidPath(PathBuf),
+    InvalidPieceLength(u64),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::IOError(ref e) => write!(f, "{}", e),
+            BuildError::NoFiles => f.write_str("there are no files to add"),
+            BuildError::NoName(ref path) => {
+                write!(f, "there is no name to give the torrent of {}", path.display())
+            }
+            BuildError::InvalidPath(ref path) => {
+                write!(f, "path is not valid UTF-8: {}", path.display())
+            }
+            BuildError::InvalidPieceLength(length) => {
+                write!(f, "piece length {} is not a power of two of at least 16 KiB", length)
+            }
+        }
+    }
+}
+
+impl error::Error for BuildError {
+    fn description(&self) -> &str {
+        match *self {
+            BuildError::IOError(_) => "could not read the files",
+            BuildError::NoFiles => "there are no files to add",
+            BuildError::NoName(_) => "path has no name",
+            BuildError::InvalidPath(_) => "path is not valid UTF-8",
+            BuildError::InvalidPieceLength(_) => "invalid piece length",
+        }
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(error: io::Error) -> BuildError {
+        BuildError::IOError(error)
+    }
+}
+
+impl TorrentBuilder {
+    pub fn new<P: AsRef<Path>>(path: P) -> TorrentBuilder {
+        TorrentBuilder {
+            path: path.as_ref().to_path_buf(),
+            piece_length: None,
+            trackers: Vec::new(),
+            web_seeds: Vec::new(),
+            comment: None,
+            created_by: Some(format!("rustorrent/{}", env!("CARGO_PKG_VERSION"))),
+            creation_date: None,
+            private: false,
+            source: None,
+            v2: false,
+            hybrid: false,
+            threads: DEFAULT_THREADS,
+        }
+    }
+
+    // Chosen from the total size when not set.
+    pub fn piece_length(mut self, piece_length: u64) -> TorrentBuilder {
+        self.piece_length = Some(piece_length);
+        self
+    }
+
+    // Adds a tracker in a tier of its own.
+    pub fn announce(mut self, url: &str) -> TorrentBuilder {
+        self.trackers.push(vec![url.to_string()]);
+        self
+    }
+
+    // Adds a tier of trackers that are tried in random order.
+    pub fn announce_tier(mut self, urls: Vec<String>) -> TorrentBuilder {
+        if !urls.is_empty() {
+            self.trackers.push(urls);
+        }
+        self
+    }
+
+    pub fn web_seed(mut self, url: &str) -> TorrentBuilder {
+        self.web_seeds.push(url.to_string());
+        self
+    }
+
+    pub fn comment(mut self, comment: &str) -> TorrentBuilder {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    pub fn created_by(mut self, created_by: &str) -> TorrentBuilder {
+        self.created_by = Some(created_by.to_string());
+        self
+    }
+
+    // Defaults to the time `build` is called.
+    pub fn creation_date(mut self, creation_date: i64) -> TorrentBuilder {
+        self.creation_date = Some(creation_date);
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> TorrentBuilder {
+        self.private = private;
+        self
+    }
+
+    pub fn source(mut self, source: &str) -> TorrentBuilder {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    // Makes a v2-only torrent (BEP 52) instead of a v1 one.
+    pub fn v2(mut self, v2: bool) -> TorrentBuilder {
+        self.v2 = v2;
+        self
+    }
+
+    // Makes a hybrid torrent, which has both the v1 and the v2 keys and can
+    // be shared with clients that only know either.
+    pub fn hybrid(mut self, hybrid: bool) -> TorrentBuilder {
+        self.hybrid = hybrid;
+        self
+    }
+
+    // How many threads hash pieces at the same time.
+    pub fn threads(mut self, threads: usize) -> TorrentBuilder {
+        self.threads = if threads == 0 { 1 } else { threads };
+        self
+    }
+
+    pub fn build(self) -> Result<MetaInfo, BuildError> {
+        // `.` and `..` only have a name once resolved
+        let root = try!(fs::canonicalize(&self.path));
+        let name = try!(root.file_name().ok_or(BuildError::NoName(root.clone())));
+        let name = try!(name.to_str().ok_or(BuildError::InvalidPath(root.clone())));
+        let metadata = try!(fs::metadata(&root));
+
+        // every file to hash, in the order their data is laid out, and its
+        // path in the torrent
+        let mut files: Vec<(PathBuf, u64)> = Vec::new();
+        let mut torrent_paths: Vec<FPath> = Vec::new();
+        if metadata.is_dir() {
+            let mut paths = Vec::new();
+            try!(collect_files(&root, &mut paths));
+            paths.sort();
+            for path in paths {
+                let length = try!(fs::metadata(&path)).len();
+                let relative = path.strip_prefix(&root).unwrap().to_path_buf();
+                let mut components = Vec::new();
+                for component in relative.iter() {
+                    let component = try!(component.to_str()
+                        .ok_or(BuildError::InvalidPath(path.clone())));
+                    components.push(component.to_string());
+                }
+                torrent_paths.push(components);
+                files.push((path, length));
+            }
+            if files.is_empty() {
+                return Err(BuildError::NoFiles);
+            }
+        } else {
+            files.push((root.clone(), metadata.len()));
+            torrent_paths.push(vec![name.to_string()]);
+        }
+
+        let total: u64 = files.iter().map(|&(_, length)| length).sum();
+        let piece_length = match self.piece_length {
+            Some(length) if length < MIN_PIECE_LENGTH || !length.is_power_of_two() => {
+                return Err(BuildError::InvalidPieceLength(length))
+            }
+            Some(length) => length,
+            None => choose_piece_length(total),
+        };
+        let v1 = !self.v2 || self.hybrid;
+        let v2 = self.v2 || self.hybrid;
+
+        // the v1 data, where padding files are None
+        let mut layout: Vec<(Option<PathBuf>, u64)> = Vec::new();
+        let mode_info = if metadata.is_dir() {
+            let mut entries = Vec::new();
+            for (index, (&(ref path, length), components)) in files.iter()
+                .zip(torrent_paths.iter())
+                .enumerate() {
+                entries.push(FileEntry {
+                    attr: None,
+                    length: length,
+                    md5sum: None,
+                    path: components.clone(),
+                    path_utf8: None,
+                });
+                layout.push((Some(path.clone()), length));
+                // hybrid torrents start every file on a piece boundary, so
+                // that v1 pieces line up with the v2 ones
+                let padding = (piece_length - length % piece_length) % piece_length;
+                if self.hybrid && padding > 0 && index + 1 < files.len() {
+                    entries.push(FileEntry {
+                        attr: Some("p".to_string()),
+                        length: padding,
+                        md5sum: None,
+                        path: vec![".pad".to_string(), padding.to_string()],
+                        path_utf8: None,
+                    });
+                    layout.push((None, padding));
+                }
+            }
+            ModeInfo::Multi(MultiFileInfo { files: entries })
+        } else {
+            layout.push((Some(root.clone()), metadata.len()));
+            ModeInfo::Single(SingleFileInfo {
+                length: metadata.len(),
+                md5_sum: None,
+                attr: None,
+            })
+        };
+
+        let mut info = FileInfo {
+            piece_length: piece_length,
+            pieces: Vec::new(),
+            private: if self.private { Some(1) } else { None },
+            name: Some(name.to_string()),
+            name_utf8: None,
+            source: self.source.clone(),
+            mode_info: mode_info,
+            meta_version: None,
+            file_tree: Vec::new(),
+        };
+        let mut piece_layers = BTreeMap::new();
+        if v2 {
+            let hashes = try!(hash_files_v2(files.clone(), self.threads));
+            for ((&(_, length), path), blocks) in files.iter().zip(torrent_paths).zip(hashes) {
+                let root = merkle::pieces_root(&blocks, piece_length);
+                if length > piece_length {
+                    piece_layers.insert(root.unwrap(), merkle::piece_layer(&blocks, piece_length));
+                }
+                info.file_tree.push(TreeFile {
+                    path: path,
+                    length: length,
+                    pieces_root: root,
+                    attr: None,
+                });
+            }
+            info.meta_version = Some(2);
+        }
+        if v1 {
+            info.pieces = try!(hash_pieces(layout, piece_length, self.threads));
+        }
+        let info_bytes = bdict_encode(&info.to_bdict());
+        let info_hash_v2 = if v2 { Some(sha256(&info_bytes)) } else { None };
+        let info_hash = match info_hash_v2 {
+            Some(hash) if !v1 => hash[..20].to_vec(),
+            _ => {
+                let mut sha1 = Sha1::new();
+                sha1.update(&info_bytes);
+                sha1.digest().bytes().to_vec()
+            }
+        };
+
+        let creation_date = self.creation_date.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+        });
+        Ok(MetaInfo {
+            announce: self.trackers.get(0).map(|tier| tier[0].clone()),
+            // a single tracker needs no list
+            announce_list: if self.trackers.len() > 1 || self.trackers.iter().any(|t| t.len() > 1) {
+                self.trackers.clone()
+            } else {
+                Vec::new()
+            },
+            url_list: self.web_seeds.clone(),
+            comment: self.comment.clone(),
+            created_by: self.created_by.clone(),
+            creation_date: Some(creation_date),
+            encoding: Some("UTF-8".to_string()),
+            info: info,
+            info_hash: info_hash,
+            info_hash_v2: info_hash_v2,
+            piece_layers: piece_layers,
+            info_bytes: info_bytes,
+            ..Default::default()
+        })
+    }
+}
+
+// Links to files are followed, links to directories are not, as they may
+// lead back up the tree.
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in try!(fs::read_dir(dir)) {
+        let path = try!(entry).path();
+        let file_type = try!(fs::symlink_metadata(&path)).file_type();
+        if file_type.is_dir() {
+            try!(collect_files(&path, paths));
+        } else if !file_type.is_symlink() || !try!(fs::metadata(&path)).is_dir() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+// The smallest power of two that keeps the piece count near the target.
+pub fn choose_piece_length(total: u64) -> u64 {
+    let mut piece_length = MIN_PIECE_LENGTH;
+    while piece_length < MAX_PIECE_LENGTH && total / piece_length > TARGET_PIECES {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+// Pieces run across file boundaries, so the files are treated as one stream.
+// Each thread hashes its own contiguous run of pieces.
+fn hash_pieces(files: Vec<(Option<PathBuf>, u64)>,
+               piece_length: u64,
+               threads: usize)
+               -> Result<Vec<SHA1Hash20b>, BuildError> {
+    let total: u64 = files.iter().map(|&(_, length)| length).sum();
+    let count = ((total + piece_length - 1) / piece_length) as usize;
+    let per_thread = (count + threads - 1) / threads;
+    let files = Arc::new(files);
+
+    let mut handles = Vec::new();
+    let mut first = 0;
+    while first < count {
+        let last = ::std::cmp::min(first + per_thread, count);
+        let files = files.clone();
+        handles.push(thread::spawn(move || -> io::Result<Vec<SHA1Hash20b>> {
+            let mut reader = StreamReader::new(&files);
+            let mut buffer = vec![0; piece_length as usize];
+            let mut hashes = Vec::new();
+            for index in first..last {
+                let start = index as u64 * piece_length;
+                let length = ::std::cmp::min(piece_length, total - start) as usize;
+                try!(reader.read_at(start, &mut buffer[..length]));
+                let mut sha1 = Sha1::new();
+                sha1.update(&buffer[..length]);
+                hashes.push(sha1.digest().bytes().to_vec());
+            }
+            Ok(hashes)
+        }));
+        first = last;
+    }
+
+    let mut pieces = Vec::with_capacity(count);
+    for handle in handles {
+        match handle.join() {
+            Ok(hashes) => pieces.extend(try!(hashes)),
+            Err(_) => {
+                return Err(BuildError::IOError(io::Error::new(io::ErrorKind::Other,
+                                                              "hashing thread panicked")))
+            }
+        }
+    }
+    Ok(pieces)
+}
+
+// v2 hashes each file on its own, so threads take whole files. Returns the
+// hashes of every file's blocks.
+fn hash_files_v2(files: Vec<(PathBuf, u64)>,
+                 threads: usize)
+                 -> Result<Vec<Vec<SHA256Hash32b>>, BuildError> {
+    let files = Arc::new(files);
+    let mut handles = Vec::new();
+    for first in 0..::std::cmp::min(threads, files.len()) {
+        let files = files.clone();
+        handles.push(thread::spawn(move || -> io::Result<Vec<(usize, Vec<SHA256Hash32b>)>> {
+            let mut hashes = Vec::new();
+            let mut buffer = vec![0; BLOCK_SIZE];
+            for index in (first..files.len()).filter(|index| index % threads == first) {
+                let (ref path, length) = files[index];
+                let mut file = try!(fs::File::open(path));
+                let mut blocks = Vec::new();
+                let mut remaining = length;
+                while remaining > 0 {
+                    let size = ::std::cmp::min(remaining, BLOCK_SIZE as u64) as usize;
+                    try!(file.read_exact(&mut buffer[..size]));
+                    blocks.push(sha256(&buffer[..size]));
+                    remaining -= size as u64;
+                }
+                hashes.push((index, blocks));
+            }
+            Ok(hashes)
+        }));
+    }
+
+    let mut all = vec![Vec::new(); files.len()];
+    for handle in handles {
+        match handle.join() {
+            Ok(hashes) => {
+                for (index, blocks) in try!(hashes) {
+                    all[index] = blocks;
+                }
+            }
+            Err(_) => {
+                return Err(BuildError::IOError(io::Error::new(io::ErrorKind::Other,
+                                                              "hashing thread panicked")))
+            }
+        }
+    }
+    Ok(all)
+}
+
+// Reads from the files as if they were concatenated. Padding files read as
+// zeros.
+struct StreamReader<'a> {
+    files: &'a [(Option<PathBuf>, u64)],
+    open: Option<(usize, fs::File)>,
+}
+
+impl<'a> StreamReader<'a> {
+    fn new(files: &'a [(Option<PathBuf>, u64)]) -> StreamReader<'a> {
+        StreamReader {
+            files: files,
+            open: None,
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        let mut file_start = 0;
+        let mut filled = 0;
+        for (index, &(ref path, length)) in self.files.iter().enumerate() {
+            let file_end = file_start + length;
+            let position = offset + filled as u64;
+            if filled < buffer.len() && position < file_end {
+                let wanted = ::std::cmp::min((file_end - position) as usize, buffer.len() - filled);
+                let target = &mut buffer[filled..(filled + wanted)];
+                match *path {
+                    Some(ref path) => {
+                        if self.open.as_ref().map(|&(open, _)| open) != Some(index) {
+                            self.open = Some((index, try!(fs::File::open(path))));
+                        }
+                        let file = &mut self.open.as_mut().unwrap().1;
+                        try!(file.seek(SeekFrom::Start(position - file_start)));
+                        try!(file.read_exact(target));
+                    }
+                    None => {
+                        for byte in target.iter_mut() {
+                            *byte = 0;
+                        }
+                    }
+                }
+                filled += wanted;
+            }
+            file_start = file_end;
+        }
+        if filled < buffer.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "files got shorter"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use bencode::BDict;
+use bencode::borrowed::{BencodeRef, BDictRef, belement_decode_ref};
+use bencode::decode::DecodeResult;
+use bencode::encode::{bdict_encode, bdict_encode_with_raw};
+use bencode::mapping::ToBencode;
+use convert::TryFrom;
+use metainfo::{MetaInfo, MetaInfoError};
+
+// Changes an encoded torrent in place of the scripts that used to. Keys it
+// does not know about are kept, and so are the info dict's bytes, which keeps
+// the info-hash. `source` and `private` are inside the info dict though, so
+// setting either to something new makes a new info-hash and drops any
+// signatures over the old one.
+//
+//     let bytes = try!(TorrentEditor::new()
+//         .clear_trackers()
+//         .announce("http://tracker.example.com/announce")
+//         .comment(None)
+//         .edit(&bytes));
+pub struct TorrentEditor {
+    clear_trackers: bool,
+    removed_trackers: Vec<String>,
+    added_trackers: Vec<Vec<String>>,
+    comment: Option<Option<String>>,
+    source: Option<Option<String>>,
+    private: Option<bool>,
+}
+
+impl TorrentEditor {
+    pub fn new() -> TorrentEditor {
+        TorrentEditor {
+            clear_trackers: false,
+            removed_trackers: Vec::new(),
+            added_trackers: Vec::new(),
+            comment: None,
+            source: None,
+            private: None,
+        }
+    }
+
+    // Drops every tracker the torrent had, before any are added.
+    pub fn clear_trackers(mut self) -> TorrentEditor {
+        self.clear_trackers = true;
+        self
+    }
+
+    pub fn remove_tracker(mut self, url: &str) -> TorrentEditor {
+        self.removed_trackers.push(url.to_string());
+        self
+    }
+
+    // Adds a tracker in a tier of its own, after the ones already there.
+    pub fn announce(mut self, url: &str) -> TorrentEditor {
+        self.added_trackers.push(vec![url.to_string()]);
+        self
+    }
+
+    // None removes the comment.
+    pub fn comment(mut self, comment: Option<&str>) -> TorrentEditor {
+        self.comment = Some(comment.map(|comment| comment.to_string()));
+        self
+    }
+
+    pub fn source(mut self, source: Option<&str>) -> TorrentEditor {
+        self.source = Some(source.map(|source| source.to_string()));
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> TorrentEditor {
+        self.private = Some(private);
+        self
+    }
+
+    // Whether the edit changes the info dict, and with it the info-hash.
+    pub fn changes_info(&self, metainfo: &MetaInfo) -> bool {
+        self.source.as_ref().map_or(false, |source| *source != metainfo.info.source) ||
+        self.private.map_or(false, |private| private != (metainfo.info.private == Some(1)))
+    }
+
+    pub fn edit(&self, torrent: &[u8]) -> Result<Vec<u8>, MetaInfoError> {
+        let dict = match belement_decode_ref(torrent) {
+            Ok(DecodeResult(BencodeRef::BDict(dict), _)) => dict,
+            _ => return Err(MetaInfoError::invalid_data("metainfo")),
+        };
+        let metainfo = try!(MetaInfo::try_from(dict));
+        let mut outer = dict.to_bdict();
+
+        if self.clear_trackers || !self.removed_trackers.is_empty() ||
+           !self.added_trackers.is_empty() {
+            self.edit_trackers(&metainfo, &mut outer);
+        }
+        match self.comment {
+            Some(Some(ref comment)) => {
+                outer.insert("comment", comment.to_bencode());
+            }
+            Some(None) => {
+                outer.remove("comment");
+            }
+            None => (),
+        }
+
+        let info = dict.get_dict("info").unwrap();
+        if !self.changes_info(&metainfo) {
+            return Ok(bdict_encode_with_raw(&outer, &[("info", info.raw())]));
+        }
+        outer.remove("signatures");
+        let info = self.edit_info(info);
+        Ok(bdict_encode_with_raw(&outer, &[("info", &bdict_encode(&info))]))
+    }
+
+    // A single tracker is written as `announce` alone, more as tiers in
+    // `announce-list` too, as `TorrentBuilder` does.
+    fn edit_trackers(&self, metainfo: &MetaInfo, outer: &mut BDict) {
+        let mut tiers = if self.clear_trackers {
+            Vec::new()
+        } else if !metainfo.announce_list.is_empty() {
+            metainfo.announce_list.clone()
+        } else {
+            metainfo.announce.iter().map(|url| vec![url.clone()]).collect()
+        };
+        for tier in tiers.iter_mut() {
+            tier.retain(|url| !self.removed_trackers.contains(url));
+        }
+        for tier in self.added_trackers.iter() {
+            if !tiers.iter().any(|existing| existing.contains(&tier[0])) {
+                tiers.push(tier.clone());
+            }
+        }
+        tiers.retain(|tier| !tier.is_empty());
+
+        outer.remove("announce");
+        outer.remove("announce-list");
+        if let Some(first) = tiers.get(0).map(|tier| tier[0].clone()) {
+            outer.insert("announce", first.to_bencode());
+        }
+        if tiers.len() > 1 || tiers.iter().any(|tier| tier.len() > 1) {
+            outer.insert("announce-list", tiers.to_bencode());
+        }
+    }
+
+    fn edit_info(&self, info: BDictRef) -> BDict {
+        let mut info = info.to_bdict();
+        match self.source {
+            Some(Some(ref source)) => {
+                info.insert("source", source.to_bencode());
+            }
+            Some(None) => {
+                info.remove("source");
+            }
+            None => (),
+        }
+        match self.private {
+            Some(true) => {
+                info.insert("private", 1u32.to_bencode());
+            }
+            Some(false) => {
+                info.remove("private");
+            }
+            None => (),
+        }
+        info
+    }
+}
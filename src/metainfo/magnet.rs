@@ -0,0 +1,268 @@
+use std::{error, fmt};
+use bencode::{Bencode, BDict};
+use bencode::borrowed::belement_decode_ref;
+use bencode::decode::{DecodeResult, belement_decode};
+use bencode::encode::bdict_encode;
+use bencode::json::hex_decode;
+use bencode::mapping::ToBencode;
+use convert::TryFrom;
+use metainfo::{MetaInfo, MetaInfoError, SHA1Hash20b, SHA256Hash32b};
+
+// A BEP 9 magnet link. It names the torrent by its info-hash, and the info
+// dict itself has to be fetched from peers.
+//
+//     let link = try!(MagnetLink::try_from("magnet:?xt=urn:btih:...&tr=..."));
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MagnetLink {
+    // From `urn:btih:`, hex or base32.
+    pub info_hash: Option<SHA1Hash20b>,
+    // From `urn:btmh:`, a SHA-256 multihash. Hybrid links have both.
+    pub info_hash_v2: Option<SHA256Hash32b>,
+    // `dn`, only a hint until the info dict arrives.
+    pub name: Option<String>,
+    // `tr`, in the order given.
+    pub trackers: Vec<String>,
+    // `ws`, BEP 19 web seeds.
+    pub web_seeds: Vec<String>,
+    // `x.pe`, peers to try before any tracker answers.
+    pub peers: Vec<(String, u16)>,
+    // `so`, BEP 53 file indices as inclusive ranges. Empty selects all.
+    pub select_only: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MagnetError {
+    NotMagnet,
+    MissingHash,
+    InvalidHash(String),
+    InvalidParameter(String),
+}
+
+impl fmt::Display for MagnetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MagnetError::NotMagnet => f.write_str("not a magnet link"),
+            MagnetError::MissingHash => f.write_str("magnet link has no BitTorrent info-hash"),
+            MagnetError::InvalidHash(ref hash) => write!(f, "invalid info-hash {}", hash),
+            MagnetError::InvalidParameter(ref key) => write!(f, "invalid magnet parameter {}", key),
+        }
+    }
+}
+
+impl error::Error for MagnetError {
+    fn description(&self) -> &str {
+        match *self {
+            MagnetError::NotMagnet => "not a magnet link",
+            MagnetError::MissingHash => "magnet link has no info-hash",
+            MagnetError::InvalidHash(_) => "invalid info-hash",
+            MagnetError::InvalidParameter(_) => "invalid magnet parameter",
+        }
+    }
+}
+
+// The multihash prefix of a 32 byte SHA-256 digest.
+const SHA256_MULTIHASH: &'static str = "1220";
+
+impl<'a> TryFrom<&'a str> for MagnetLink {
+    type Err = MagnetError;
+    fn try_from(uri: &'a str) -> Result<Self, Self::Err> {
+        if !uri.starts_with("magnet:?") {
+            return Err(MagnetError::NotMagnet);
+        }
+        let mut link: MagnetLink = Default::default();
+        for pair in uri["magnet:?".len()..].split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(equals) => (&pair[..equals], &pair[(equals + 1)..]),
+                None => (pair, ""),
+            };
+            // repeated parameters may be numbered, as in `tr.1`
+            let name = match key.find('.') {
+                Some(dot) if key != "x.pe" => &key[..dot],
+                _ => key,
+            };
+            // in a name `+` is a space, but `%2B` is a plus sign
+            let value = if name == "dn" {
+                value.replace('+', " ")
+            } else {
+                value.to_string()
+            };
+            let value = try!(percent_decode(&value)
+                .ok_or(MagnetError::InvalidParameter(key.to_string())));
+            match name {
+                "xt" => try!(link.exact_topic(&value)),
+                "dn" => link.name = Some(value),
+                "tr" => link.trackers.push(value),
+                "ws" => link.web_seeds.push(value),
+                "x.pe" => {
+                    let peer = try!(parse_peer(&value)
+                        .ok_or(MagnetError::InvalidParameter(key.to_string())));
+                    link.peers.push(peer);
+                }
+                "so" => {
+                    link.select_only = try!(parse_ranges(&value)
+                        .ok_or(MagnetError::InvalidParameter(key.to_string())));
+                }
+                _ => (),
+            }
+        }
+        if link.info_hash.is_none() && link.info_hash_v2.is_none() {
+            return Err(MagnetError::MissingHash);
+        }
+        Ok(link)
+    }
+}
+
+impl MagnetLink {
+    // Other kinds of `xt`, like ed2k hashes, are not ours to use.
+    fn exact_topic(&mut self, topic: &str) -> Result<(), MagnetError> {
+        let invalid = || MagnetError::InvalidHash(topic.to_string());
+        if topic.starts_with("urn:btih:") {
+            let hash = &topic["urn:btih:".len()..];
+            let bytes = match hash.len() {
+                40 => hex_decode(hash),
+                32 => base32_decode(hash),
+                _ => None,
+            };
+            self.info_hash = Some(try!(bytes.ok_or_else(&invalid)));
+        } else if topic.starts_with("urn:btmh:") {
+            let hash = &topic["urn:btmh:".len()..];
+            if !hash.starts_with(SHA256_MULTIHASH) || hash.len() != 4 + 64 {
+                return Err(invalid());
+            }
+            let bytes = try!(hex_decode(&hash[4..]).ok_or_else(&invalid));
+            let mut digest = [0; 32];
+            digest.copy_from_slice(&bytes);
+            self.info_hash_v2 = Some(digest);
+        }
+        Ok(())
+    }
+
+    // The hashes to announce and handshake with, v1 first.
+    pub fn info_hashes(&self) -> Vec<SHA1Hash20b> {
+        let mut hashes: Vec<SHA1Hash20b> = self.info_hash.iter().cloned().collect();
+        if let Some(v2) = self.info_hash_v2 {
+            hashes.push(v2[..20].to_vec());
+        }
+        hashes
+    }
+
+    pub fn selects(&self, file: usize) -> bool {
+        self.select_only.is_empty() ||
+        self.select_only.iter().any(|&(first, last)| first <= file && file <= last)
+    }
+
+    // Makes the torrent once the info dict has been fetched, checking it
+    // against every hash the link gave. The link's trackers and web seeds
+    // stand in for the keys outside the info dict.
+    pub fn to_metainfo(&self, info_bytes: &[u8]) -> Result<MetaInfo, MetaInfoError> {
+        let info = match belement_decode(info_bytes) {
+            Ok(DecodeResult(Bencode::BDict(info), _)) => info,
+            _ => return Err(MetaInfoError::field_type("info")),
+        };
+        let mut dict = BDict::new();
+        if let Some(tracker) = self.trackers.get(0) {
+            dict.insert("announce", tracker.to_bencode());
+        }
+        if self.trackers.len() > 1 {
+            let tiers: Vec<Vec<String>> = self.trackers.iter().map(|t| vec![t.clone()]).collect();
+            dict.insert("announce-list", tiers.to_bencode());
+        }
+        if !self.web_seeds.is_empty() {
+            dict.insert("url-list", self.web_seeds.to_bencode());
+        }
+        dict.insert("info", Bencode::BDict(info));
+
+        let bytes = bdict_encode(&dict);
+        let bdict = match belement_decode_ref(&bytes) {
+            Ok(DecodeResult(element, _)) => {
+                try!(element.as_dict().ok_or(MetaInfoError::field_type("info")))
+            }
+            Err(_) => return Err(MetaInfoError::field_type("info")),
+        };
+        let metainfo = try!(MetaInfo::try_from(bdict));
+        let v1_matches = self.info_hash.as_ref().map_or(true, |hash| {
+            metainfo.info.is_v1() && hash == &metainfo.info_hash
+        });
+        let v2_matches = self.info_hash_v2.map_or(true, |hash| metainfo.info_hash_v2 == Some(hash));
+        if !v1_matches || !v2_matches {
+            return Err(MetaInfoError::invalid_data("info"));
+        }
+        Ok(metainfo)
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if i + 3 > bytes.len() {
+                return None;
+            }
+            let byte = ::std::str::from_utf8(&bytes[(i + 1)..(i + 3)])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match byte {
+                Some(byte) => out.push(byte),
+                None => return None,
+            }
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+// RFC 4648 base32, which is how 32 character btih hashes are written.
+fn base32_decode(value: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in value.chars() {
+        let digit = match c.to_ascii_uppercase() {
+            c @ 'A'...'Z' => c as u32 - 'A' as u32,
+            c @ '2'...'7' => c as u32 - '2' as u32 + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | digit;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Some(out)
+}
+
+// `host:port`, with IPv6 hosts in brackets.
+fn parse_peer(value: &str) -> Option<(String, u16)> {
+    let colon = match value.rfind(':') {
+        Some(colon) => colon,
+        None => return None,
+    };
+    let host = value[..colon].trim_left_matches('[').trim_right_matches(']');
+    match value[(colon + 1)..].parse() {
+        Ok(port) if !host.is_empty() => Some((host.to_string(), port)),
+        _ => None,
+    }
+}
+
+// Comma separated indices and inclusive ranges, as in `0,2,4-6`.
+fn parse_ranges(value: &str) -> Option<Vec<(usize, usize)>> {
+    let mut ranges = Vec::new();
+    for part in value.split(',') {
+        let (first, last) = match part.find('-') {
+            Some(dash) => (&part[..dash], &part[(dash + 1)..]),
+            None => (part, part),
+        };
+        match (first.parse(), last.parse()) {
+            (Ok(first), Ok(last)) if first <= last => ranges.push((first, last)),
+            _ => return None,
+        }
+    }
+    Some(ranges)
+}
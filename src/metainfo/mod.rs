@@ -1,16 +1,58 @@
-use bencode::{BDict, BString, BInt};
+use bencode::{Bencode, BDict, BList, BString, DecodeError, DecodeErrorKind};
+use bencode::borrowed::{BencodeRef, BDictRef, belement_decode_ref};
+use bencode::decode::{DecodeResult, belement_decode};
+use bencode::encode::bdict_encode;
+use bencode::mapping::{FromBencode, ToBencode, expect_dict, get_required, get_optional};
+use sha1::Sha1;
+use openssl::sha::sha256;
+use file::merkle::{self, BLOCK_SIZE};
+use std::collections::BTreeMap;
 use std::{error, fmt};
 use convert::TryFrom;
 
+mod builder;
+mod edit;
+mod magnet;
+mod paths;
+mod signature;
+
+pub use metainfo::builder::{TorrentBuilder, BuildError};
+pub use metainfo::edit::TorrentEditor;
+pub use metainfo::magnet::{MagnetLink, MagnetError};
+pub use metainfo::paths::{PathPolicy, PathEntry, PathProblem, PathRewrite, sanitize_component};
+pub use metainfo::signature::{Signature, SignaturePolicy, SignatureState, SignatureStatus,
+                              SignatureError};
+
 #[derive(Default, Clone)]
 pub struct MetaInfo {
-    pub announce: String,
+    // Trackerless and magnet-derived torrents have no announce URL.
+    pub announce: Option<String>,
+    // BEP 12 tiers, empty tiers are dropped.
     pub announce_list: Vec<Vec<String>>,
+    // BEP 19 web seeds.
+    pub url_list: Vec<String>,
+    // BEP 17 HTTP seeds.
+    pub httpseeds: Vec<String>,
+    // BEP 5 DHT bootstrap nodes.
+    pub nodes: Vec<(String, u16)>,
     pub comment: Option<String>,
     pub created_by: Option<String>,
-    pub creation_date: Option<u32>,
+    pub creation_date: Option<i64>,
+    pub encoding: Option<String>,
     pub info: FileInfo,
-    pub original: Option<BDict>,
+    // The 20 byte hash used in handshakes and announces. SHA-1 of the info
+    // dict, except for v2-only torrents which use the truncated v2 hash.
+    pub info_hash: SHA1Hash20b,
+    // BEP 52 SHA-256 of the info dict, for v2 and hybrid torrents.
+    pub info_hash_v2: Option<SHA256Hash32b>,
+    // The hashes of every piece of each file longer than a piece, keyed by
+    // the file's `pieces root`.
+    pub piece_layers: BTreeMap<SHA256Hash32b, Vec<SHA256Hash32b>>,
+    // The info dict exactly as it was encoded, which is what `info_hash`
+    // covers. Keys this client does not know about are only kept here.
+    pub info_bytes: Vec<u8>,
+    // BEP 35 signatures by signer name.
+    pub signatures: BTreeMap<String, Signature>,
 }
 
 #[derive(Debug)]
@@ -18,9 +60,73 @@ pub struct MetaInfoError {
     kind: MetaInfoErrorKind,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub attr: Option<String>,
+    pub length: u64,
+    pub md5sum: Option<BString>,
+    // Components that are not UTF-8 are read lossily, `sanitize_paths` then
+    // takes `path_utf8` in their place.
+    pub path: FPath,
+    // `path.utf-8`, which some clients add when `path` is in another
+    // encoding.
+    pub path_utf8: Option<FPath>,
+}
+
+impl<'a> FromBencode<'a> for FileEntry {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<FileEntry, DecodeError> {
+        let dict = try!(expect_dict(element));
+        let path: Vec<&[u8]> = try!(get_required(&dict, "path"));
+        Ok(FileEntry {
+            attr: try!(get_optional(&dict, "attr")),
+            length: try!(get_required(&dict, "length")),
+            md5sum: try!(get_optional(&dict, "md5sum")),
+            path: path.iter().map(|name| String::from_utf8_lossy(name).into_owned()).collect(),
+            path_utf8: try!(get_optional(&dict, "path.utf-8")),
+        })
+    }
+}
+
+impl ToBencode for FileEntry {
+    fn to_bencode(&self) -> Bencode {
+        let mut dict = BDict::new();
+        if let Some(ref attr) = self.attr {
+            dict.insert("attr", attr.to_bencode());
+        }
+        dict.insert("length", self.length.to_bencode());
+        if let Some(ref md5sum) = self.md5sum {
+            dict.insert("md5sum", md5sum.to_bencode());
+        }
+        dict.insert("path", self.path.to_bencode());
+        if let Some(ref path_utf8) = self.path_utf8 {
+            dict.insert("path.utf-8", path_utf8.to_bencode());
+        }
+        Bencode::BDict(dict)
+    }
+}
+
+impl FileEntry {
+    // BEP 47 padding files only stand for zeros that align the next file to
+    // a piece boundary. Their data is never stored.
+    pub fn is_padding(&self) -> bool {
+        self.attr.as_ref().map_or(false, |attr| attr.contains('p'))
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.attr.as_ref().map_or(false, |attr| attr.contains('x'))
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.attr.as_ref().map_or(false, |attr| attr.contains('h'))
+    }
+}
+
 impl fmt::Display for MetaInfoError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error while parsing metainfo: {}", self._description())
+        write!(f,
+               "Error while parsing metainfo: {} ({})",
+               self._description(),
+               self.field())
     }
 }
 
@@ -37,6 +143,20 @@ impl MetaInfoError {
         MetaInfoError { kind: MetaInfoErrorKind::InvalidDataFieldValue(field.to_string()) }
     }
 
+    pub fn kind(&self) -> &MetaInfoErrorKind {
+        &self.kind
+    }
+
+    // The offending key, with the keys of the dicts it is nested in, e.g.
+    // "info.files.length".
+    pub fn field(&self) -> &str {
+        match self.kind {
+            MetaInfoErrorKind::MissingField(ref field) |
+            MetaInfoErrorKind::FieldIsWrongType(ref field) |
+            MetaInfoErrorKind::InvalidDataFieldValue(ref field) => field,
+        }
+    }
+
     fn _description(&self) -> &str {
         match self.kind {
             MetaInfoErrorKind::MissingField(_) => "required field on file is missing",
@@ -54,103 +174,646 @@ impl error::Error for MetaInfoError {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MetaInfoErrorKind {
     MissingField(String),
     FieldIsWrongType(String),
     InvalidDataFieldValue(String),
 }
 
-impl TryFrom<BDict> for MetaInfo {
+// Reads an optional key, naming it `field` in any error. Errors from inside
+// nested dicts name the inner key too.
+fn optional<'a, A: FromBencode<'a>>(dict: &BDictRef<'a>,
+                                    key: &str,
+                                    field: &str)
+                                    -> Result<Option<A>, MetaInfoError> {
+    match dict.get(key) {
+        Some(element) => A::from_bencode(element).map(Some).map_err(|e| decode_error(e, field)),
+        None => Ok(None),
+    }
+}
+
+fn required<'a, A: FromBencode<'a>>(dict: &BDictRef<'a>,
+                                    key: &str,
+                                    field: &str)
+                                    -> Result<A, MetaInfoError> {
+    try!(optional(dict, key, field)).ok_or(MetaInfoError::missing_field(field))
+}
+
+fn decode_error(error: DecodeError, field: &str) -> MetaInfoError {
+    match error.kind {
+        DecodeErrorKind::MissingField(key) => {
+            MetaInfoError::missing_field(&format!("{}.{}", field, key))
+        }
+        DecodeErrorKind::InvalidField(key) => {
+            MetaInfoError::field_type(&format!("{}.{}", field, key))
+        }
+        _ => MetaInfoError::field_type(field),
+    }
+}
+
+// Free-form text is shown to the user and nothing else, so a torrent made with
+// a legacy `encoding` is not rejected over it.
+fn text(dict: &BDictRef, key: &str, field: &str) -> Result<Option<String>, MetaInfoError> {
+    match dict.get(key) {
+        Some(element) => {
+            let bytes = try!(element.as_bytes().ok_or(MetaInfoError::field_type(field)));
+            Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+        }
+        None => Ok(None),
+    }
+}
+
+impl<'a> TryFrom<BDictRef<'a>> for MetaInfo {
     type Err = MetaInfoError;
 
-    fn try_from(bdict: BDict) -> Result<MetaInfo, MetaInfoError> {
-        let dict = &bdict;
+    fn try_from(dict: BDictRef<'a>) -> Result<MetaInfo, MetaInfoError> {
         let mut info: MetaInfo = Default::default();
 
-        let announce: Option<String> = dict.get_copy("announce");
-        let announce_list: Option<Vec<Vec<String>>> = dict.get_copy("announce_list");
-        let comment: Option<String> = dict.get_copy("comment");
-        let created_by: Option<String> = dict.get_copy("created_by");
-        let creation_date: Option<BInt> = dict.get_copy("creation date");
-
-        info.announce = try!(announce.ok_or(MetaInfoError::missing_field("annouce")));
-        info.announce_list = announce_list.unwrap_or(Vec::new());
-        info.comment = comment; //try!(comment.ok_or(MetaInfoError::missing_field("comment")));
-        info.created_by = created_by;
-        info.creation_date = creation_date.map(|bdict| bdict.to_i64() as u32);
+        info.announce = try!(optional(&dict, "announce", "announce"));
+        let announce_list: Vec<Vec<String>> =
+            try!(optional(&dict, "announce-list", "announce-list")).unwrap_or(Vec::new());
+        info.announce_list = announce_list.into_iter().filter(|tier| !tier.is_empty()).collect();
+        info.url_list = try!(MetaInfo::get_url_list(dict));
+        info.httpseeds = try!(optional(&dict, "httpseeds", "httpseeds")).unwrap_or(Vec::new());
+        info.nodes = try!(MetaInfo::get_nodes(dict));
+        info.comment = try!(text(&dict, "comment", "comment"));
+        info.created_by = try!(text(&dict, "created by", "created by"));
+        info.creation_date = try!(optional(&dict, "creation date", "creation date"));
+        info.encoding = try!(text(&dict, "encoding", "encoding"));
+        info.signatures = try!(signature::get_signatures(dict));
         info.info = try!(MetaInfo::get_info(dict));
+        info.info_bytes = dict.get_dict("info").unwrap().raw().to_vec();
+        if info.info.is_v2() {
+            info.piece_layers = try!(MetaInfo::get_piece_layers(dict, &info.info));
+            info.info_hash_v2 = Some(sha256(&info.info_bytes));
+        }
+        info.info_hash = if info.info.is_v1() {
+            try!(MetaInfo::get_info_hash(dict))
+        } else {
+            info.info_hash_v2.unwrap()[..20].to_vec()
+        };
 
         Ok(info)
     }
 }
 
+impl TryFrom<BDict> for MetaInfo {
+    type Err = MetaInfoError;
+
+    fn try_from(bdict: BDict) -> Result<MetaInfo, MetaInfoError> {
+        let bytes = bdict_encode(&bdict);
+        let dict = match belement_decode_ref(&bytes) {
+            Ok(DecodeResult(BencodeRef::BDict(dict), _)) => dict,
+            _ => return Err(MetaInfoError::invalid_data("metainfo")),
+        };
+        MetaInfo::try_from(dict)
+    }
+}
+
+// Writes the info dict back out from `info_bytes` when there are any, so the
+// info-hash of a parsed torrent survives the round trip.
 impl Into<BDict> for MetaInfo {
     fn into(self) -> BDict {
-        unimplemented!();
+        let mut dict = BDict::new();
+        if let Some(announce) = self.announce {
+            dict.insert("announce", announce.to_bencode());
+        }
+        if !self.announce_list.is_empty() {
+            dict.insert("announce-list", self.announce_list.to_bencode());
+        }
+        if let Some(comment) = self.comment {
+            dict.insert("comment", comment.to_bencode());
+        }
+        if let Some(created_by) = self.created_by {
+            dict.insert("created by", created_by.to_bencode());
+        }
+        if let Some(creation_date) = self.creation_date {
+            dict.insert("creation date", creation_date.to_bencode());
+        }
+        if let Some(encoding) = self.encoding {
+            dict.insert("encoding", encoding.to_bencode());
+        }
+        if !self.httpseeds.is_empty() {
+            dict.insert("httpseeds", self.httpseeds.to_bencode());
+        }
+        if !self.nodes.is_empty() {
+            let nodes = self.nodes
+                .iter()
+                .map(|&(ref host, port)| {
+                    Bencode::BList(BList::from(vec![host.to_bencode(), port.to_bencode()]))
+                })
+                .collect();
+            dict.insert("nodes", Bencode::BList(BList::from(nodes)));
+        }
+        if !self.piece_layers.is_empty() {
+            let mut layers = BDict::new();
+            for (root, layer) in self.piece_layers.iter() {
+                let hashes: Vec<u8> = layer.iter().flat_map(|hash| hash.iter().cloned()).collect();
+                layers.insert_bytes(root, Bencode::BString(BString::new(&hashes)));
+            }
+            dict.insert("piece layers", Bencode::BDict(layers));
+        }
+        if !self.signatures.is_empty() {
+            let mut signatures = BDict::new();
+            for (signer, signature) in self.signatures.iter() {
+                signatures.insert(signer, signature.to_bencode());
+            }
+            dict.insert("signatures", Bencode::BDict(signatures));
+        }
+        if !self.url_list.is_empty() {
+            dict.insert("url-list", self.url_list.to_bencode());
+        }
+        let info = match belement_decode(&self.info_bytes) {
+            Ok(DecodeResult(Bencode::BDict(info), _)) => info,
+            _ => self.info.to_bdict(),
+        };
+        dict.insert("info", Bencode::BDict(info));
+        dict
     }
 }
 
 
 impl MetaInfo {
-    fn get_info(dict: &BDict) -> Result<FileInfo, MetaInfoError> {
+    // The first 20 bytes of the v2 info-hash, which stand in for it wherever
+    // only 20 bytes fit.
+    pub fn info_hash_v2_truncated(&self) -> Option<SHA1Hash20b> {
+        self.info_hash_v2.map(|hash| hash[..20].to_vec())
+    }
+
+    // Every hash the torrent's swarms go by. Hybrid torrents have a v1 and a
+    // v2 swarm, and peers may announce and handshake in either.
+    pub fn info_hashes(&self) -> Vec<SHA1Hash20b> {
+        let mut hashes = vec![self.info_hash.clone()];
+        if let Some(v2) = self.info_hash_v2_truncated() {
+            if v2 != self.info_hash {
+                hashes.push(v2);
+            }
+        }
+        hashes
+    }
+
+    // Checks a piece against every hash set the torrent has, so a hybrid
+    // torrent whose v1 and v2 hashes disagree fails either way.
+    pub fn verify_piece(&self, index: usize, data: &[u8]) -> bool {
+        (!self.info.is_v1() || self.verify_piece_v1(index, data)) &&
+        (!self.info.is_v2() || self.verify_piece_v2(index, data))
+    }
+
+    pub fn verify_piece_v1(&self, index: usize, data: &[u8]) -> bool {
+        match self.info.pieces.get(index) {
+            Some(hash) => {
+                let mut sha1 = Sha1::new();
+                sha1.update(data);
+                &sha1.digest().bytes()[..] == &hash[..]
+            }
+            None => false,
+        }
+    }
+
+    // v2 pieces belong to a single file. The data of a file's last piece
+    // may run on into padding, which v2 does not hash.
+    pub fn verify_piece_v2(&self, index: usize, data: &[u8]) -> bool {
+        let (file, piece) = match self.info.v2_piece(index) {
+            Some(location) => location,
+            None => return false,
+        };
+        let root = match file.pieces_root {
+            Some(ref root) => root,
+            None => return false,
+        };
+        let left = file.length - piece as u64 * self.info.piece_length;
+        let data = &data[..::std::cmp::min(data.len() as u64, left) as usize];
+        let layer = self.piece_layers.get(root).map(|layer| &layer[..]);
+        merkle::verify_piece(data, piece, file.length, self.info.piece_length, root, layer)
+    }
+
+    // The info-hash covers the info dict exactly as it was encoded in the file.
+    fn get_info_hash(dict: BDictRef) -> Result<SHA1Hash20b, MetaInfoError> {
+        let info_dict = try!(dict.get_dict("info").ok_or(MetaInfoError::missing_field("info")));
+        let mut sha1 = Sha1::new();
+        sha1.update(info_dict.raw());
+        Ok(sha1.digest().bytes().to_vec())
+    }
+
+    // BEP 19 allows a single URL in place of the list, and some tools write
+    // an empty string when there are none.
+    fn get_url_list(dict: BDictRef) -> Result<Vec<String>, MetaInfoError> {
+        match dict.get("url-list") {
+            Some(BencodeRef::BString(b"")) => Ok(Vec::new()),
+            Some(BencodeRef::BString(_)) => {
+                let url: String = try!(required(&dict, "url-list", "url-list"));
+                Ok(vec![url])
+            }
+            Some(_) => required(&dict, "url-list", "url-list"),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Nodes are `[host, port]` pairs.
+    fn get_nodes(dict: BDictRef) -> Result<Vec<(String, u16)>, MetaInfoError> {
+        let list = match dict.get("nodes") {
+            Some(element) => try!(element.as_list().ok_or(MetaInfoError::field_type("nodes"))),
+            None => return Ok(Vec::new()),
+        };
+        let mut nodes = Vec::new();
+        for node in list.iter() {
+            let pair = try!(node.as_list().ok_or(MetaInfoError::field_type("nodes")));
+            let mut pair = pair.iter();
+            match (pair.next(), pair.next(), pair.next()) {
+                (Some(host), Some(port), None) => {
+                    let host = try!(String::from_bencode(host)
+                        .map_err(|_| MetaInfoError::field_type("nodes")));
+                    let port = try!(u16::from_bencode(port)
+                        .map_err(|_| MetaInfoError::invalid_data("nodes")));
+                    nodes.push((host, port));
+                }
+                _ => return Err(MetaInfoError::invalid_data("nodes")),
+            }
+        }
+        Ok(nodes)
+    }
+
+    fn get_info(dict: BDictRef) -> Result<FileInfo, MetaInfoError> {
         let mut info: FileInfo = Default::default();
-        let bdict: BDict = try!(dict.get_copy("info").ok_or(MetaInfoError::missing_field("info")));
-        info.piece_length = try!(bdict.get_copy("piece length")
-            .map(|pl: BInt| pl.to_i64() as u64)
-            .ok_or(MetaInfoError::missing_field("piece length")));
-        info.private = bdict.get_copy("private").map(|p: BInt| p.to_i64() as u32);
-        let pieces_bstr: BString = try!(bdict.get_copy("pieces")
-            .ok_or(MetaInfoError::missing_field("pieces")));
-        let pieces = pieces_bstr.to_bytes();
-        let mut pieces_vec = Vec::new();
-        let mut i = 0;
-        while i < pieces.len() {
-            let piece = (&pieces[i..(i + 20)]).iter().map(|&b| b).collect();
-            pieces_vec.push(piece);
-            i += 20;
-        }
-
-        info.pieces = pieces_vec;
-        info.name = bdict.get_copy("name");
-
-        let single_file_fields: (Option<BString>, Option<BInt>) = (bdict.get_copy("md5sum"),
-                                                                   bdict.get_copy("length"));
-        if let (md5sum, Some(length)) = single_file_fields {
-            info.mode_info = ModeInfo::Single(SingleFileInfo {
-                md5_sum: md5sum.map(|m| m.to_bytes()),
-                length: length.to_i64() as u64,
-            });
-            info.original = Some(bdict);
-            return Ok(info);
+        let bdict = match dict.get("info") {
+            Some(element) => try!(element.as_dict().ok_or(MetaInfoError::field_type("info"))),
+            None => return Err(MetaInfoError::missing_field("info")),
+        };
+        let piece_length: i64 = try!(required(&bdict, "piece length", "info.piece length"));
+        if piece_length <= 0 {
+            return Err(MetaInfoError::invalid_data("info.piece length"));
         }
+        info.piece_length = piece_length as u64;
+        info.private = try!(optional(&bdict, "private", "info.private"));
+        info.name = try!(text(&bdict, "name", "info.name"));
+        info.name_utf8 = try!(optional(&bdict, "name.utf-8", "info.name.utf-8"));
+        info.source = try!(text(&bdict, "source", "info.source"));
+        info.meta_version = try!(optional(&bdict, "meta version", "info.meta version"));
+
+        match info.meta_version {
+            None => MetaInfo::get_v1_files(bdict, info),
+            Some(2) => {
+                // v2 pieces are merkle subtrees, so they must cover whole blocks
+                if !info.piece_length.is_power_of_two() || info.piece_length < BLOCK_SIZE as u64 {
+                    return Err(MetaInfoError::invalid_data("info.piece length"));
+                }
+                let tree = match bdict.get("file tree") {
+                    Some(element) => {
+                        try!(element.as_dict().ok_or(MetaInfoError::field_type("info.file tree")))
+                    }
+                    None => return Err(MetaInfoError::missing_field("info.file tree")),
+                };
+                try!(MetaInfo::get_file_tree(tree, &mut Vec::new(), &mut info.file_tree));
+                if info.file_tree.is_empty() {
+                    return Err(MetaInfoError::invalid_data("info.file tree"));
+                }
+                // hybrid torrents carry the v1 keys as well
+                if bdict.get("pieces").is_some() {
+                    let info = try!(MetaInfo::get_v1_files(bdict, info));
+                    try!(MetaInfo::check_hybrid_layout(&info));
+                    return Ok(info);
+                }
+                info.mode_info = info.v2_mode_info();
+                Ok(info)
+            }
+            Some(_) => Err(MetaInfoError::invalid_data("info.meta version")),
+        }
+    }
 
-        let bdict_files = bdict.get_copy("files");
-        let bdict_list: Vec<BDict> = try!(bdict_files.ok_or(MetaInfoError::missing_field("files")));
-        let mut files = Vec::new();
-        for fdict in bdict_list.into_iter() {
-            let length: BInt = try!(fdict.get_copy("length")
-                .ok_or(MetaInfoError::missing_field("length")));
-            let md5_sum = fdict.get_copy("md5sum").map(|m: BString| m.to_bytes());
-            let path: Vec<String> = try!(fdict.get_copy("path")
-                .ok_or(MetaInfoError::missing_field("path")));
-            files.push((length.to_i64() as u64, md5_sum, path));
+    fn get_v1_files(bdict: BDictRef, mut info: FileInfo) -> Result<FileInfo, MetaInfoError> {
+        let pieces: &[u8] = try!(required(&bdict, "pieces", "info.pieces"));
+        if pieces.len() % 20 != 0 {
+            return Err(MetaInfoError::invalid_data("info.pieces"));
         }
+        info.pieces = pieces.chunks(20).map(|piece| piece.to_vec()).collect();
 
-        info.mode_info = ModeInfo::Multi(MultiFileInfo { files: files });
-        info.original = Some(bdict);
+        let total_length = match (bdict.get("length"), bdict.get("files")) {
+            (Some(_), Some(_)) => return Err(MetaInfoError::invalid_data("info.files")),
+            (Some(_), None) => {
+                let md5_sum: Option<&[u8]> = try!(optional(&bdict, "md5sum", "info.md5sum"));
+                let length = try!(required(&bdict, "length", "info.length"));
+                info.mode_info = ModeInfo::Single(SingleFileInfo {
+                    length: length,
+                    md5_sum: md5_sum.map(|m| m.to_vec()),
+                    attr: try!(optional(&bdict, "attr", "info.attr")),
+                });
+                length
+            }
+            (None, Some(_)) => {
+                let files: Vec<FileEntry> = try!(required(&bdict, "files", "info.files"));
+                if files.is_empty() {
+                    return Err(MetaInfoError::invalid_data("info.files"));
+                }
+                if files.iter().any(|file| file.path.is_empty()) {
+                    return Err(MetaInfoError::invalid_data("info.files.path"));
+                }
+                let length = try!(files.iter()
+                    .fold(Some(0u64), |total, file| total.and_then(|t| t.checked_add(file.length)))
+                    .ok_or(MetaInfoError::invalid_data("info.files.length")));
+                info.mode_info = ModeInfo::Multi(MultiFileInfo { files: files });
+                length
+            }
+            (None, None) => return Err(MetaInfoError::missing_field("info.length")),
+        };
+
+        // one hash for every piece the files span, the last one may be short
+        let piece_count = total_length / info.piece_length +
+                          if total_length % info.piece_length == 0 { 0 } else { 1 };
+        if info.pieces.len() as u64 != piece_count {
+            return Err(MetaInfoError::invalid_data("info.pieces"));
+        }
         Ok(info)
     }
+
+    // Hybrid torrents describe the same files twice. Without its padding
+    // files the v1 list has to match the file tree, and padding has to start
+    // every file on a piece boundary since v2 pieces never span files.
+    fn check_hybrid_layout(info: &FileInfo) -> Result<(), MetaInfoError> {
+        let v1_files: Vec<(FPath, u64, bool)> = match info.mode_info {
+            ModeInfo::Single(ref single) => {
+                vec![(info.name.iter().cloned().collect(), single.length, false)]
+            }
+            ModeInfo::Multi(ref multi) => {
+                multi.files
+                    .iter()
+                    .map(|file| (file.path.clone(), file.length, file.is_padding()))
+                    .collect()
+            }
+        };
+        let mut tree = info.file_tree.iter();
+        let mut offset = 0;
+        for (path, length, padding) in v1_files {
+            if !padding {
+                match tree.next() {
+                    Some(file) if file.path == path && file.length == length => (),
+                    _ => return Err(MetaInfoError::invalid_data("info.file tree")),
+                }
+                if length > 0 && offset % info.piece_length != 0 {
+                    return Err(MetaInfoError::invalid_data("info.files"));
+                }
+            }
+            offset += length;
+        }
+        if tree.next().is_some() {
+            return Err(MetaInfoError::invalid_data("info.file tree"));
+        }
+        Ok(())
+    }
+
+    // Flattens the `file tree` into its files, in the order their keys sort,
+    // which is the order of their data. A file is a dict with a single empty
+    // key.
+    fn get_file_tree(tree: BDictRef,
+                     path: &mut Vec<String>,
+                     files: &mut Vec<TreeFile>)
+                     -> Result<(), MetaInfoError> {
+        const FIELD: &'static str = "info.file tree";
+        for (name, node) in tree.iter() {
+            let node = try!(node.as_dict().ok_or(MetaInfoError::field_type(FIELD)));
+            if name.is_empty() {
+                return Err(MetaInfoError::invalid_data(FIELD));
+            }
+            let name = try!(String::from_utf8(name.to_vec())
+                .map_err(|_| MetaInfoError::field_type(FIELD)));
+            path.push(name);
+            match node.get("") {
+                Some(file) => {
+                    let file = try!(file.as_dict().ok_or(MetaInfoError::field_type(FIELD)));
+                    let length: u64 = try!(required(&file, "length", "info.file tree.length"));
+                    let pieces_root: Option<&[u8]> =
+                        try!(optional(&file, "pieces root", "info.file tree.pieces root"));
+                    let pieces_root = match pieces_root {
+                        Some(root) if root.len() == 32 && length > 0 => {
+                            let mut digest = [0; 32];
+                            digest.copy_from_slice(root);
+                            Some(digest)
+                        }
+                        None if length == 0 => None,
+                        _ => return Err(MetaInfoError::invalid_data("info.file tree.pieces root")),
+                    };
+                    files.push(TreeFile {
+                        path: path.clone(),
+                        length: length,
+                        pieces_root: pieces_root,
+                        attr: try!(optional(&file, "attr", "info.file tree.attr")),
+                    });
+                }
+                None => try!(MetaInfo::get_file_tree(node, path, files)),
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    // Every file longer than a piece needs a layer, and each layer has to
+    // hash up to its file's `pieces root`.
+    fn get_piece_layers(dict: BDictRef,
+                        info: &FileInfo)
+                        -> Result<BTreeMap<SHA256Hash32b, Vec<SHA256Hash32b>>, MetaInfoError> {
+        let mut layers = BTreeMap::new();
+        let layers_dict = match dict.get("piece layers") {
+            Some(element) => {
+                Some(try!(element.as_dict().ok_or(MetaInfoError::field_type("piece layers"))))
+            }
+            None => None,
+        };
+        for file in info.file_tree.iter().filter(|file| file.length > info.piece_length) {
+            let root = file.pieces_root.unwrap();
+            let entry = layers_dict.and_then(|layers| {
+                layers.iter().find(|&(key, _)| key == &root[..])
+            });
+            let layer = match entry {
+                Some((_, layer)) => {
+                    try!(layer.as_bytes().ok_or(MetaInfoError::field_type("piece layers")))
+                }
+                None => return Err(MetaInfoError::missing_field("piece layers")),
+            };
+            if layer.len() % 32 != 0 {
+                return Err(MetaInfoError::invalid_data("piece layers"));
+            }
+            let layer: Vec<SHA256Hash32b> = layer.chunks(32)
+                .map(|hash| {
+                    let mut digest = [0; 32];
+                    digest.copy_from_slice(hash);
+                    digest
+                })
+                .collect();
+            if !merkle::verify_layer(&layer, file.length, info.piece_length, &root) {
+                return Err(MetaInfoError::invalid_data("piece layers"));
+            }
+            layers.insert(root, layer);
+        }
+        Ok(layers)
+    }
 }
 
 #[derive(Default, Clone)]
 pub struct FileInfo {
     pub piece_length: u64,
+    // Empty for v2-only torrents.
     pub pieces: Vec<SHA1Hash20b>,
     pub private: Option<u32>,
+    // Read lossily, like `FileEntry::path`.
     pub name: Option<String>,
+    pub name_utf8: Option<String>,
+    pub source: Option<String>,
+    // For v2-only torrents this is derived from `file_tree`.
     pub mode_info: ModeInfo,
-    pub original: Option<BDict>,
+    // 2 for v2 and hybrid torrents.
+    pub meta_version: Option<u32>,
+    // The files of the BEP 52 `file tree`, in order.
+    pub file_tree: Vec<TreeFile>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeFile {
+    pub path: FPath,
+    pub length: u64,
+    // None for empty files.
+    pub pieces_root: Option<SHA256Hash32b>,
+    pub attr: Option<String>,
+}
+
+impl FileInfo {
+    // v1 pieces run across files, v2 ones start again with each file. The
+    // two agree for hybrid torrents.
+    pub fn piece_count(&self) -> usize {
+        if self.is_v1() {
+            return self.pieces.len();
+        }
+        self.file_tree
+            .iter()
+            .map(|file| ((file.length + self.piece_length - 1) / self.piece_length) as usize)
+            .sum()
+    }
+
+    // The file a v2 piece is in, and which of the file's pieces it is.
+    fn v2_piece(&self, index: usize) -> Option<(&TreeFile, usize)> {
+        let mut first = 0;
+        for file in self.file_tree.iter() {
+            let count = ((file.length + self.piece_length - 1) / self.piece_length) as usize;
+            if index < first + count {
+                return Some((file, index - first));
+            }
+            first += count;
+        }
+        None
+    }
+
+    pub fn is_v1(&self) -> bool {
+        self.meta_version != Some(2) || !self.pieces.is_empty()
+    }
+
+    pub fn is_v2(&self) -> bool {
+        self.meta_version == Some(2)
+    }
+
+    // A single file at the top of the tree makes a single file torrent.
+    fn v2_mode_info(&self) -> ModeInfo {
+        if self.file_tree.len() == 1 && self.file_tree[0].path.len() == 1 {
+            return ModeInfo::Single(SingleFileInfo {
+                length: self.file_tree[0].length,
+                md5_sum: None,
+                attr: self.file_tree[0].attr.clone(),
+            });
+        }
+        ModeInfo::Multi(MultiFileInfo {
+            files: self.file_tree
+                .iter()
+                .map(|file| {
+                    FileEntry {
+                        attr: file.attr.clone(),
+                        length: file.length,
+                        md5sum: None,
+                        path: file.path.clone(),
+                        path_utf8: None,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    fn file_tree_bdict(&self) -> BDict {
+        let files: Vec<(&[String], &TreeFile)> =
+            self.file_tree.iter().map(|file| (&file.path[..], file)).collect();
+        tree_bdict(&files)
+    }
+
+    pub fn to_bdict(&self) -> BDict {
+        let mut dict = BDict::new();
+        if self.is_v2() {
+            dict.insert("file tree", Bencode::BDict(self.file_tree_bdict()));
+            dict.insert("meta version", 2u32.to_bencode());
+        }
+        if !self.is_v1() {
+            return self.common_keys(dict);
+        }
+        match self.mode_info {
+            ModeInfo::Single(ref single) => {
+                dict.insert("length", single.length.to_bencode());
+                if let Some(ref md5_sum) = single.md5_sum {
+                    dict.insert("md5sum", Bencode::BString(BString::new(md5_sum)));
+                }
+                if let Some(ref attr) = single.attr {
+                    dict.insert("attr", attr.to_bencode());
+                }
+            }
+            ModeInfo::Multi(ref multi) => {
+                dict.insert("files", multi.files.to_bencode());
+            }
+        }
+        let pieces: Vec<u8> = self.pieces.iter().flat_map(|piece| piece.iter().cloned()).collect();
+        dict.insert("pieces", Bencode::BString(BString::new(&pieces)));
+        self.common_keys(dict)
+    }
+
+    fn common_keys(&self, mut dict: BDict) -> BDict {
+        if let Some(ref name) = self.name {
+            dict.insert("name", name.to_bencode());
+        }
+        if let Some(ref name_utf8) = self.name_utf8 {
+            dict.insert("name.utf-8", name_utf8.to_bencode());
+        }
+        dict.insert("piece length", self.piece_length.to_bencode());
+        if let Some(private) = self.private {
+            dict.insert("private", private.to_bencode());
+        }
+        if let Some(ref source) = self.source {
+            dict.insert("source", source.to_bencode());
+        }
+        dict
+    }
+}
+
+// Nests files by their path components, grouping those that share a
+// directory.
+fn tree_bdict(files: &[(&[String], &TreeFile)]) -> BDict {
+    let mut groups: BTreeMap<&str, Vec<(&[String], &TreeFile)>> = BTreeMap::new();
+    for &(path, file) in files {
+        groups.entry(&path[0]).or_insert(Vec::new()).push((&path[1..], file));
+    }
+    let mut tree = BDict::new();
+    for (name, children) in groups {
+        let node = match children.iter().find(|&&(path, _)| path.is_empty()) {
+            Some(&(_, file)) => {
+                let mut leaf = BDict::new();
+                leaf.insert("length", file.length.to_bencode());
+                if let Some(ref root) = file.pieces_root {
+                    leaf.insert("pieces root", Bencode::BString(BString::new(root)));
+                }
+                if let Some(ref attr) = file.attr {
+                    leaf.insert("attr", attr.to_bencode());
+                }
+                let mut node = BDict::new();
+                node.insert("", Bencode::BDict(leaf));
+                node
+            }
+            None => tree_bdict(&children),
+        };
+        tree.insert(name, Bencode::BDict(node));
+    }
+    tree
 }
 
 #[derive(Clone)]
@@ -169,13 +832,15 @@ impl Default for ModeInfo {
 pub struct SingleFileInfo {
     pub length: u64,
     pub md5_sum: Option<MD5Sum>,
+    pub attr: Option<String>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct MultiFileInfo {
-    pub files: Vec<(u64, Option<MD5Sum>, FPath)>,
+    pub files: Vec<FileEntry>,
 }
 
 pub type MD5Sum = Vec<u8>;
 pub type SHA1Hash20b = Vec<u8>;
+pub type SHA256Hash32b = [u8; 32];
 pub type FPath = Vec<String>;
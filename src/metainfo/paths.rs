@@ -0,0 +1,316 @@
+use std::fmt;
+use std::collections::HashSet;
+use metainfo::{MetaInfo, ModeInfo, FPath};
+
+// The longest file name most filesystems take, in bytes.
+const MAX_NAME_LENGTH: usize = 255;
+// Extensions up to this long are kept when a name is cut short.
+const MAX_EXTENSION_LENGTH: usize = 16;
+// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: [&'static str; 22] = ["CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3",
+                                            "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+                                            "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6",
+                                            "LPT7", "LPT8", "LPT9"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathPolicy {
+    // Refuse the torrent when any path could escape or break the download
+    // directory.
+    Reject,
+    // Make every path safe and report what was changed.
+    Rewrite,
+}
+
+// Which path of the torrent a rewrite is about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathEntry {
+    // `info.name`, the file or the directory holding the files.
+    Name,
+    // An index in `info.files`.
+    File(usize),
+    // An index in `FileInfo::file_tree`.
+    TreeFile(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathProblem {
+    // Not UTF-8, `path.utf-8` was taken when there is one.
+    Utf8,
+    // Empty components, or a path with none left.
+    Empty,
+    // `.` and `..`.
+    Dots,
+    // `/` or `\` inside a component, which also makes it absolute.
+    Separator,
+    // NUL, control characters and those Windows does not allow, or trailing
+    // dots and spaces Windows drops.
+    Character,
+    Reserved,
+    TooLong,
+    // The same path as an earlier file once rewritten, or only differing
+    // from it in case. The later file is renamed.
+    Collision,
+}
+
+// What was done to one path, or what made it be rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathRewrite {
+    pub entry: PathEntry,
+    pub original: FPath,
+    pub sanitized: FPath,
+    pub problems: Vec<PathProblem>,
+}
+
+impl PathProblem {
+    // Only hostile paths are rejected, names that were merely not UTF-8 are
+    // fixed either way.
+    pub fn is_hostile(&self) -> bool {
+        *self != PathProblem::Utf8
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            PathProblem::Utf8 => "not UTF-8",
+            PathProblem::Empty => "empty component",
+            PathProblem::Dots => "relative component",
+            PathProblem::Separator => "path separator",
+            PathProblem::Character => "invalid character",
+            PathProblem::Reserved => "reserved name",
+            PathProblem::TooLong => "name too long",
+            PathProblem::Collision => "same path as another file",
+        }
+    }
+}
+
+impl fmt::Display for PathEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathEntry::Name => f.write_str("info.name"),
+            PathEntry::File(index) => write!(f, "info.files[{}]", index),
+            PathEntry::TreeFile(index) => write!(f, "info.file tree[{}]", index),
+        }
+    }
+}
+
+impl fmt::Display for PathRewrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problems: Vec<&str> = self.problems.iter().map(|p| p.description()).collect();
+        write!(f,
+               "{} {:?} -> {:?} ({})",
+               self.entry,
+               self.original.join("/"),
+               self.sanitized.join("/"),
+               problems.join(", "))
+    }
+}
+
+// Makes one name safe to create on any platform. None when the component
+// should be dropped, as `..` is.
+pub fn sanitize_component(name: &str) -> (Option<String>, Vec<PathProblem>) {
+    let mut problems = Vec::new();
+    match name {
+        "" => return (None, vec![PathProblem::Empty]),
+        "." | ".." => return (None, vec![PathProblem::Dots]),
+        _ => (),
+    }
+
+    let mut clean = String::with_capacity(name.len());
+    for c in name.chars() {
+        let problem = match c {
+            '/' | '\\' => Some(PathProblem::Separator),
+            '\u{fffd}' => Some(PathProblem::Utf8),
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => Some(PathProblem::Character),
+            c if c.is_control() => Some(PathProblem::Character),
+            _ => None,
+        };
+        match problem {
+            Some(problem) => {
+                push_problem(&mut problems, problem);
+                clean.push('_');
+            }
+            None => clean.push(c),
+        }
+    }
+
+    let trimmed = clean.trim_right_matches(|c| c == '.' || c == ' ').len();
+    if trimmed < clean.len() {
+        push_problem(&mut problems, PathProblem::Character);
+        clean.truncate(trimmed);
+        if clean.is_empty() {
+            clean.push('_');
+        }
+    }
+
+    let stem = clean.split('.').next().unwrap_or("").to_uppercase();
+    if RESERVED_NAMES.iter().any(|reserved| *reserved == stem.trim_right()) {
+        problems.push(PathProblem::Reserved);
+        clean.insert(0, '_');
+    }
+
+    if clean.len() > MAX_NAME_LENGTH {
+        problems.push(PathProblem::TooLong);
+        clean = truncate_name(&clean);
+    }
+    (Some(clean), problems)
+}
+
+fn push_problem(problems: &mut Vec<PathProblem>, problem: PathProblem) {
+    if !problems.contains(&problem) {
+        problems.push(problem);
+    }
+}
+
+// Cuts the name at a char boundary, keeping a short extension.
+fn truncate_name(name: &str) -> String {
+    let extension = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= MAX_EXTENSION_LENGTH => &name[dot..],
+        _ => "",
+    };
+    let mut end = MAX_NAME_LENGTH - extension.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &name[..end], extension)
+}
+
+// Sanitizes every component of a path, preferring `alternative` when the
+// path was not UTF-8.
+fn sanitize_path(path: &FPath, alternative: Option<&FPath>) -> (FPath, Vec<PathProblem>) {
+    let mut problems = Vec::new();
+    let lossy = path.iter().any(|name| name.contains('\u{fffd}'));
+    let source = match alternative {
+        Some(alternative) if lossy => {
+            problems.push(PathProblem::Utf8);
+            alternative
+        }
+        _ => path,
+    };
+    let mut sanitized = Vec::with_capacity(source.len());
+    for name in source.iter() {
+        let (clean, found) = sanitize_component(name);
+        for problem in found {
+            push_problem(&mut problems, problem);
+        }
+        sanitized.extend(clean);
+    }
+    if sanitized.is_empty() {
+        push_problem(&mut problems, PathProblem::Empty);
+        sanitized.push("_".to_string());
+    }
+    (sanitized, problems)
+}
+
+impl MetaInfo {
+    // Checks every path before anything is written under the download
+    // directory. With `PathPolicy::Reject` the first hostile entry is
+    // returned and nothing is changed. Either way the result says exactly
+    // which entries were rewritten. The info-hash is not affected.
+    pub fn sanitize_paths(&mut self, policy: PathPolicy) -> Result<Vec<PathRewrite>, PathRewrite> {
+        let mut rewrites = Vec::new();
+        if let Some(ref name) = self.info.name {
+            let name = vec![name.clone()];
+            let alternative = self.info.name_utf8.as_ref().map(|name| vec![name.clone()]);
+            rewrites.extend(check(PathEntry::Name, &name, alternative.as_ref()));
+        }
+        if let ModeInfo::Multi(ref multi) = self.info.mode_info {
+            let mut taken = HashSet::new();
+            for (index, file) in multi.files.iter().enumerate() {
+                let entry = PathEntry::File(index);
+                let rewrite = check(entry, &file.path, file.path_utf8.as_ref());
+                // padding files are never written, and often share names
+                if file.is_padding() {
+                    rewrites.extend(rewrite);
+                } else {
+                    rewrites.extend(make_unique(entry, &file.path, rewrite, &mut taken));
+                }
+            }
+        }
+        let mut taken = HashSet::new();
+        for (index, file) in self.info.file_tree.iter().enumerate() {
+            let entry = PathEntry::TreeFile(index);
+            let rewrite = check(entry, &file.path, None);
+            rewrites.extend(make_unique(entry, &file.path, rewrite, &mut taken));
+        }
+
+        if policy == PathPolicy::Reject {
+            if let Some(hostile) = rewrites.iter()
+                .find(|rewrite| rewrite.problems.iter().any(|p| p.is_hostile())) {
+                return Err(hostile.clone());
+            }
+        }
+        for rewrite in rewrites.iter() {
+            match rewrite.entry {
+                PathEntry::Name => self.info.name = rewrite.sanitized.get(0).cloned(),
+                PathEntry::File(index) => {
+                    if let ModeInfo::Multi(ref mut multi) = self.info.mode_info {
+                        multi.files[index].path = rewrite.sanitized.clone();
+                    }
+                }
+                PathEntry::TreeFile(index) => {
+                    self.info.file_tree[index].path = rewrite.sanitized.clone()
+                }
+            }
+        }
+        Ok(rewrites)
+    }
+}
+
+fn check(entry: PathEntry, path: &FPath, alternative: Option<&FPath>) -> Option<PathRewrite> {
+    let (sanitized, problems) = sanitize_path(path, alternative);
+    if problems.is_empty() {
+        return None;
+    }
+    Some(PathRewrite {
+        entry: entry,
+        original: path.clone(),
+        sanitized: sanitized,
+        problems: problems,
+    })
+}
+
+// Renames a file whose path, as it will be written, is already `taken` by
+// an earlier one, ignoring case as some filesystems do.
+fn make_unique(entry: PathEntry,
+               path: &FPath,
+               rewrite: Option<PathRewrite>,
+               taken: &mut HashSet<String>)
+               -> Option<PathRewrite> {
+    let key = |path: &FPath| path.join("/").to_lowercase();
+    let sanitized = rewrite.as_ref().map_or(path, |rewrite| &rewrite.sanitized).clone();
+    if taken.insert(key(&sanitized)) {
+        return rewrite;
+    }
+    let mut rewrite = rewrite.unwrap_or_else(|| {
+        PathRewrite {
+            entry: entry,
+            original: path.clone(),
+            sanitized: sanitized.clone(),
+            problems: Vec::new(),
+        }
+    });
+    rewrite.problems.push(PathProblem::Collision);
+    let last = sanitized.len() - 1;
+    for number in 2.. {
+        let mut renamed = sanitized.clone();
+        renamed[last] = numbered_name(&sanitized[last], number);
+        if taken.insert(key(&renamed)) {
+            rewrite.sanitized = renamed;
+            break;
+        }
+    }
+    Some(rewrite)
+}
+
+// `name (2).ext`, still short enough to create.
+fn numbered_name(name: &str, number: usize) -> String {
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= MAX_EXTENSION_LENGTH => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let suffix = format!(" ({}){}", number, extension);
+    let mut end = ::std::cmp::min(stem.len(), MAX_NAME_LENGTH - suffix.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &stem[..end], suffix)
+}
@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::{error, fmt};
+use bencode::{Bencode, BDict, DecodeError};
+use bencode::borrowed::{BencodeRef, BDictRef};
+use bencode::encode::bdict_encode;
+use bencode::mapping::{FromBencode, ToBencode, expect_dict, get_optional, get_raw,
+                       get_required, from_raw};
+use metainfo::{MetaInfo, MetaInfoError};
+use rsa::{HashAlgorithm, RsaPrivateKey};
+use x509::Certificate;
+
+// One entry of the BEP 35 `signatures` dict, keyed by the signer's name.
+// It sits outside the info dict, so signing leaves the info-hash alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    // The signer's X.509 certificate as DER, for signers not already known.
+    pub certificate: Option<Vec<u8>>,
+    // Extra data covered by the signature, the dict exactly as it was
+    // encoded.
+    pub info: Option<Vec<u8>>,
+    // RSA with SHA-1 over the info dict followed by `info`.
+    pub signature: Vec<u8>,
+}
+
+impl<'a> FromBencode<'a> for Signature {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<Signature, DecodeError> {
+        let dict = try!(expect_dict(element));
+        let certificate: Option<&[u8]> = try!(get_optional(&dict, "certificate"));
+        let info = match dict.get("info") {
+            Some(element) => {
+                try!(expect_dict(element));
+                Some(try!(get_raw(&dict, "info")))
+            }
+            None => None,
+        };
+        let signature: &[u8] = try!(get_required(&dict, "signature"));
+        Ok(Signature {
+            certificate: certificate.map(|certificate| certificate.to_vec()),
+            info: info,
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+impl ToBencode for Signature {
+    fn to_bencode(&self) -> Bencode {
+        let mut dict = BDict::new();
+        if let Some(ref certificate) = self.certificate {
+            dict.insert("certificate", (&certificate[..]).to_bencode());
+        }
+        if let Some(info) = self.info.as_ref().and_then(|info| from_raw(info)) {
+            dict.insert("info", info);
+        }
+        dict.insert("signature", (&self.signature[..]).to_bencode());
+        Bencode::BDict(dict)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignaturePolicy {
+    // Load anything, the statuses are only reported.
+    Allow,
+    // Refuse torrents with a signature that does not match, which is what a
+    // torrent changed after signing has.
+    RejectInvalid,
+    // Also refuse torrents without a signature that can be checked.
+    RequireValid,
+    // Also refuse torrents no trusted certificate vouches for.
+    RequireTrusted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureState {
+    // Signed by a trusted certificate or one a trusted CA vouches for.
+    Trusted,
+    // The signature matches its certificate, which nothing trusted vouches
+    // for, or which has expired or may not sign.
+    Untrusted,
+    // No certificate was sent and none is known by the signer's name, so
+    // the signature cannot be checked.
+    NoCertificate,
+    // The signature does not match, or its certificate cannot be read.
+    Invalid,
+}
+
+// What was found out about one signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureStatus {
+    pub signer: String,
+    // The common name of the certificate the signature was checked with.
+    pub subject: Option<String>,
+    pub state: SignatureState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureError {
+    Unsigned,
+    // The signer whose signature does not match.
+    Invalid(String),
+    Untrusted,
+    // The certificate is not for the key signing with it.
+    KeyMismatch,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignatureError::Invalid(ref signer) => {
+                write!(f, "the signature of {} does not match", signer)
+            }
+            _ => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for SignatureError {
+    fn description(&self) -> &str {
+        match *self {
+            SignatureError::Unsigned => "no valid signature",
+            SignatureError::Invalid(_) => "signature does not match",
+            SignatureError::Untrusted => "no trusted signature",
+            SignatureError::KeyMismatch => "certificate does not match the key",
+        }
+    }
+}
+
+impl fmt::Display for SignatureState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            SignatureState::Trusted => "trusted",
+            SignatureState::Untrusted => "untrusted",
+            SignatureState::NoCertificate => "no certificate",
+            SignatureState::Invalid => "invalid",
+        })
+    }
+}
+
+// Reads the optional `signatures` dict of a torrent.
+pub fn get_signatures(dict: BDictRef) -> Result<BTreeMap<String, Signature>, MetaInfoError> {
+    let signatures = match dict.get("signatures") {
+        Some(element) => try!(element.as_dict().ok_or(MetaInfoError::field_type("signatures"))),
+        None => return Ok(BTreeMap::new()),
+    };
+    let mut result = BTreeMap::new();
+    for (signer, element) in signatures.iter() {
+        let signer = String::from_utf8_lossy(signer).into_owned();
+        let field = format!("signatures.{}", signer);
+        let signature = try!(Signature::from_bencode(element)
+            .map_err(|_| MetaInfoError::field_type(&field)));
+        result.insert(signer, signature);
+    }
+    Ok(result)
+}
+
+impl MetaInfo {
+    // Adds or replaces the signature of `signer`, embedding the certificate
+    // when there is one so that others can check it.
+    pub fn sign(&mut self,
+                signer: &str,
+                key: &RsaPrivateKey,
+                certificate: Option<&Certificate>)
+                -> Result<(), SignatureError> {
+        if let Some(certificate) = certificate {
+            if *certificate.public_key() != key.public_key() {
+                return Err(SignatureError::KeyMismatch);
+            }
+        }
+        let signature = key.sign(HashAlgorithm::Sha1, &self.signed_message(None));
+        self.signatures.insert(signer.to_string(),
+                               Signature {
+                                   certificate: certificate.map(|c| c.der().to_vec()),
+                                   info: None,
+                                   signature: signature,
+                               });
+        Ok(())
+    }
+
+    // Checks every signature. A signature without a certificate is checked
+    // with the trusted certificate named after its signer, if any.
+    pub fn verify_signatures(&self, trusted: &[Certificate]) -> Vec<SignatureStatus> {
+        self.signatures
+            .iter()
+            .map(|(signer, signature)| {
+                let (certificate, state) = self.verify_signature(signer, signature, trusted);
+                SignatureStatus {
+                    signer: signer.clone(),
+                    subject: certificate.and_then(|certificate| certificate.subject_name()),
+                    state: state,
+                }
+            })
+            .collect()
+    }
+
+    // Verifies the signatures and refuses the torrent if `policy` says so.
+    pub fn check_signatures(&self,
+                            policy: SignaturePolicy,
+                            trusted: &[Certificate])
+                            -> Result<Vec<SignatureStatus>, SignatureError> {
+        let statuses = self.verify_signatures(trusted);
+        if policy == SignaturePolicy::Allow {
+            return Ok(statuses);
+        }
+        if let Some(status) = statuses.iter().find(|s| s.state == SignatureState::Invalid) {
+            return Err(SignatureError::Invalid(status.signer.clone()));
+        }
+        let has = |state| statuses.iter().any(|status| status.state == state);
+        match policy {
+            SignaturePolicy::RequireValid if !has(SignatureState::Trusted) &&
+                                             !has(SignatureState::Untrusted) => {
+                Err(SignatureError::Unsigned)
+            }
+            SignaturePolicy::RequireTrusted if !has(SignatureState::Trusted) => {
+                Err(if has(SignatureState::Untrusted) {
+                    SignatureError::Untrusted
+                } else {
+                    SignatureError::Unsigned
+                })
+            }
+            _ => Ok(statuses),
+        }
+    }
+
+    fn verify_signature(&self,
+                        signer: &str,
+                        signature: &Signature,
+                        trusted: &[Certificate])
+                        -> (Option<Certificate>, SignatureState) {
+        let certificate = match signature.certificate {
+            Some(ref der) => {
+                match Certificate::from_der(der) {
+                    Ok(certificate) => certificate,
+                    Err(_) => return (None, SignatureState::Invalid),
+                }
+            }
+            None => {
+                let known = trusted.iter()
+                    .find(|certificate| certificate.subject_name().as_ref().map(|s| &s[..]) ==
+                                        Some(signer));
+                match known {
+                    Some(certificate) => certificate.clone(),
+                    None => return (None, SignatureState::NoCertificate),
+                }
+            }
+        };
+        let message = self.signed_message(signature.info.as_ref().map(|info| &info[..]));
+        let matches = certificate.public_key()
+            .verify(HashAlgorithm::Sha1, &message, &signature.signature);
+        // an expired certificate does not make the torrent any less intact,
+        // it only stops vouching for the signer
+        let state = if !matches {
+            SignatureState::Invalid
+        } else if certificate.may_sign() && certificate.is_trusted_by(trusted) {
+            SignatureState::Trusted
+        } else {
+            SignatureState::Untrusted
+        };
+        (Some(certificate), state)
+    }
+
+    // The info dict as it is hashed, followed by the signature's own info.
+    fn signed_message(&self, info: Option<&[u8]>) -> Vec<u8> {
+        let mut message = if self.info_bytes.is_empty() {
+            bdict_encode(&self.info.to_bdict())
+        } else {
+            self.info_bytes.clone()
+        };
+        message.extend_from_slice(info.unwrap_or(&[]));
+        message
+    }
+}
@@ -0,0 +1,114 @@
+// RSA signatures as PKCS#1 v1.5 (RFC 8017) has them, which BEP 35 torrent
+// signatures and the X.509 certificates behind them use. OpenSSL does the
+// arithmetic, which has to be constant-time with private keys.
+use std::fmt;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::{Signer, Verifier};
+
+// Smaller keys are refused, 1024 bit ones are within reach of factoring and
+// a signature made with one proves little.
+const MIN_KEY_BITS: u32 = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    fn message_digest(&self) -> MessageDigest {
+        match *self {
+            HashAlgorithm::Sha1 => MessageDigest::sha1(),
+            HashAlgorithm::Sha256 => MessageDigest::sha256(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RsaPublicKey {
+    key: PKey<Public>,
+}
+
+#[derive(Clone)]
+pub struct RsaPrivateKey {
+    key: PKey<Private>,
+}
+
+impl RsaPublicKey {
+    // From the big-endian modulus and exponent.
+    pub fn new(modulus: &[u8], exponent: &[u8]) -> Option<RsaPublicKey> {
+        let key = BigNum::from_slice(modulus)
+            .and_then(|n| BigNum::from_slice(exponent).map(|e| (n, e)))
+            .and_then(|(n, e)| Rsa::from_public_components(n, e))
+            .and_then(PKey::from_rsa);
+        key.ok().and_then(RsaPublicKey::from_pkey)
+    }
+
+    // None unless it is an RSA key of at least `MIN_KEY_BITS`.
+    pub fn from_pkey(key: PKey<Public>) -> Option<RsaPublicKey> {
+        if key.id() != Id::RSA || key.bits() < MIN_KEY_BITS {
+            return None;
+        }
+        Some(RsaPublicKey { key: key })
+    }
+
+    // The size of signatures, in bytes.
+    pub fn size(&self) -> usize {
+        self.key.size()
+    }
+
+    pub fn modulus(&self) -> Vec<u8> {
+        self.key.rsa().map(|rsa| rsa.n().to_vec()).unwrap_or(Vec::new())
+    }
+
+    pub fn verify(&self, hash: HashAlgorithm, message: &[u8], signature: &[u8]) -> bool {
+        let verified = Verifier::new(hash.message_digest(), &self.key).and_then(|mut verifier| {
+            try!(verifier.update(message));
+            verifier.verify(signature)
+        });
+        verified.unwrap_or(false)
+    }
+}
+
+impl PartialEq for RsaPublicKey {
+    fn eq(&self, other: &RsaPublicKey) -> bool {
+        self.key.public_eq(&other.key)
+    }
+}
+
+impl fmt::Debug for RsaPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RsaPublicKey({} bits)", self.key.bits())
+    }
+}
+
+impl RsaPrivateKey {
+    pub fn from_pkey(key: PKey<Private>) -> Option<RsaPrivateKey> {
+        if key.id() != Id::RSA || key.bits() < MIN_KEY_BITS {
+            return None;
+        }
+        Some(RsaPrivateKey { key: key })
+    }
+
+    pub fn public_key(&self) -> RsaPublicKey {
+        // the public half of a key that was read always encodes
+        let der = self.key.public_key_to_der().unwrap();
+        RsaPublicKey { key: PKey::public_key_from_der(&der).unwrap() }
+    }
+
+    pub fn sign(&self, hash: HashAlgorithm, message: &[u8]) -> Vec<u8> {
+        // keys too small for the digest are refused by `from_pkey`
+        let mut signer = Signer::new(hash.message_digest(), &self.key).unwrap();
+        signer.update(message).unwrap();
+        signer.sign_to_vec().unwrap()
+    }
+}
+
+impl fmt::Debug for RsaPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RsaPrivateKey({} bits)", self.key.bits())
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::Read;
+use std::str;
+use std::time::Duration;
+use hyper::client::Client;
+use hyper::status::StatusCode;
+use file::Layout;
+use metainfo::{MetaInfo, SHA1Hash20b};
+use seed::{PieceSource, SeedError};
+use tracker::data::url_encode;
+use https;
+
+const TIMEOUT_SECONDS: u64 = 30;
+// How long a busy server is left alone when it does not say.
+const DEFAULT_RETRY_SECONDS: u64 = 60;
+
+// A BEP 17 HTTP seed, a script that serves pieces by their index.
+//
+//     GET http://seed/script?info_hash=%12%34...&piece=3&ranges=0-16383,16384-32767
+//
+// A busy seed answers 503 with the seconds to wait as the body.
+pub struct HttpSeed {
+    url: String,
+    info_hash: SHA1Hash20b,
+    layout: Layout,
+    client: Client,
+}
+
+impl HttpSeed {
+    pub fn new(url: &str, metainfo: &MetaInfo) -> Result<HttpSeed, SeedError> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(SeedError::InvalidUrl(url.to_string()));
+        }
+        let mut client = https::client();
+        client.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        client.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        Ok(HttpSeed {
+            url: url.to_string(),
+            info_hash: metainfo.info_hash.clone(),
+            layout: Layout::new(&metainfo.info),
+            client: client,
+        })
+    }
+
+    // The URL asking for the given inclusive byte ranges of a piece.
+    pub fn piece_url(&self, piece: usize, ranges: &[(u64, u64)]) -> String {
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+        let ranges: Vec<String> =
+            ranges.iter().map(|&(first, last)| format!("{}-{}", first, last)).collect();
+        format!("{}{}info_hash={}&piece={}&ranges={}",
+                self.url,
+                separator,
+                url_encode(&self.info_hash),
+                piece,
+                ranges.join(","))
+    }
+
+    // The ranges of a piece one after the other, as the seed sends them.
+    pub fn fetch_ranges(&self, piece: usize, ranges: &[(u64, u64)]) -> Result<Vec<u8>, SeedError> {
+        let size = try!(self.layout.piece_size(piece).ok_or(SeedError::NoSuchPiece(piece)));
+        if ranges.iter().any(|&(first, last)| first > last || last >= size) {
+            return Err(SeedError::NoSuchPiece(piece));
+        }
+        let mut response = try!(self.client
+            .get(self.piece_url(piece, ranges).as_str())
+            .send()
+            .map_err(|e| SeedError::Http(e.to_string())));
+        match response.status {
+            StatusCode::Ok => (),
+            StatusCode::ServiceUnavailable => {
+                let mut body = String::new();
+                let _ = response.read_to_string(&mut body);
+                let header = response.headers
+                    .get_raw("Retry-After")
+                    .and_then(|values| values.get(0))
+                    .and_then(|value| str::from_utf8(value).ok().map(|value| value.to_string()));
+                return Err(SeedError::RetryAfter(retry_after(header.as_ref().unwrap_or(&body))));
+            }
+            status => return Err(SeedError::Status(status.to_u16())),
+        }
+        let length = ranges.iter().map(|&(first, last)| last - first + 1).sum::<u64>();
+        let mut data = vec![0; length as usize];
+        try!(response.read_exact(&mut data));
+        Ok(data)
+    }
+}
+
+impl PieceSource for HttpSeed {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn fetch_piece(&mut self, piece: usize) -> Result<Vec<u8>, SeedError> {
+        let size = try!(self.layout.piece_size(piece).ok_or(SeedError::NoSuchPiece(piece)));
+        self.fetch_ranges(piece, &[(0, size - 1)])
+    }
+}
+
+// The seconds to wait, from the `Retry-After` header or the body of a 503.
+fn retry_after(value: &str) -> Duration {
+    Duration::from_secs(value.trim().parse().unwrap_or(DEFAULT_RETRY_SECONDS))
+}
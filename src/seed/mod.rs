@@ -0,0 +1,194 @@
+use std::{cmp, error, fmt, io};
+use std::time::{Duration, Instant};
+use file::PartialFileTrait;
+use file::merkle::BLOCK_SIZE;
+use wire::PieceStore;
+
+mod web;
+mod http;
+
+pub use seed::web::WebSeed;
+pub use seed::http::HttpSeed;
+
+// How long a seed is left alone after its first failure, doubled with each
+// one after it.
+const INITIAL_BACKOFF_SECONDS: u64 = 5;
+const MAX_BACKOFF_SECONDS: u64 = 30 * 60;
+
+#[derive(Debug)]
+pub enum SeedError {
+    Http(String),
+    IOError(io::Error),
+    // A status other than the ones asked for.
+    Status(u16),
+    // The server wants to be asked again later, no sooner than this.
+    RetryAfter(Duration),
+    // The server sent data that did not hash to the piece.
+    BadPiece(usize),
+    NoSuchPiece(usize),
+    InvalidUrl(String),
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SeedError::Http(ref e) => f.write_str(e),
+            SeedError::IOError(ref e) => write!(f, "{}", e),
+            SeedError::Status(status) => write!(f, "unexpected HTTP status {}", status),
+            SeedError::RetryAfter(delay) => write!(f, "busy for {} second(s)", delay.as_secs()),
+            SeedError::BadPiece(piece) => write!(f, "piece {} failed the hash check", piece),
+            SeedError::NoSuchPiece(piece) => write!(f, "there is no piece {}", piece),
+            SeedError::InvalidUrl(ref url) => write!(f, "invalid seed URL {}", url),
+        }
+    }
+}
+
+impl error::Error for SeedError {
+    fn description(&self) -> &str {
+        match *self {
+            SeedError::Http(_) => "HTTP request failed",
+            SeedError::IOError(_) => "could not read the response",
+            SeedError::Status(_) => "unexpected HTTP status",
+            SeedError::RetryAfter(_) => "seed is busy",
+            SeedError::BadPiece(_) => "piece failed the hash check",
+            SeedError::NoSuchPiece(_) => "there is no such piece",
+            SeedError::InvalidUrl(_) => "invalid seed URL",
+        }
+    }
+}
+
+impl From<io::Error> for SeedError {
+    fn from(error: io::Error) -> SeedError {
+        SeedError::IOError(error)
+    }
+}
+
+// Somewhere whole pieces can be fetched from over HTTP, instead of from
+// peers.
+pub trait PieceSource {
+    fn url(&self) -> &str;
+    // The piece's data, with any padding in it as zeros.
+    fn fetch_piece(&mut self, piece: usize) -> Result<Vec<u8>, SeedError>;
+}
+
+impl<S: PieceSource + ?Sized> PieceSource for Box<S> {
+    fn url(&self) -> &str {
+        (**self).url()
+    }
+
+    fn fetch_piece(&mut self, piece: usize) -> Result<Vec<u8>, SeedError> {
+        (**self).fetch_piece(piece)
+    }
+}
+
+// Keeps a failing seed from being asked again straight away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new() -> Backoff {
+        Backoff {
+            failures: 0,
+            until: None,
+        }
+    }
+
+    pub fn is_ready(&self, now: Instant) -> bool {
+        self.until.map_or(true, |until| now >= until)
+    }
+
+    pub fn until(&self) -> Option<Instant> {
+        self.until
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    // Waits longer after each failure in a row, unless the server said how
+    // long to wait.
+    pub fn fail(&mut self, now: Instant, retry_after: Option<Duration>) {
+        self.failures += 1;
+        let delay = match retry_after {
+            Some(delay) => delay,
+            None => {
+                let exponent = cmp::min(self.failures - 1, 16);
+                let seconds = INITIAL_BACKOFF_SECONDS.saturating_mul(1 << exponent);
+                Duration::from_secs(cmp::min(seconds, MAX_BACKOFF_SECONDS))
+            }
+        };
+        self.until = Some(now + delay);
+    }
+
+    pub fn succeed(&mut self) {
+        self.failures = 0;
+        self.until = None;
+    }
+}
+
+// Fetches pieces from one source into the store that pieces from peers go
+// to, block by block as peers send them, so they are verified and counted
+// the same way.
+pub struct Seeder<S: PieceSource> {
+    source: S,
+    backoff: Backoff,
+}
+
+impl<S: PieceSource> Seeder<S> {
+    pub fn new(source: S) -> Seeder<S> {
+        Seeder {
+            source: source,
+            backoff: Backoff::new(),
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn backoff(&self) -> &Backoff {
+        &self.backoff
+    }
+
+    // Fetches and verifies one piece. A failure of any kind puts the seed
+    // off for a while.
+    pub fn download_piece(&mut self,
+                          store: &PieceStore,
+                          piece: usize)
+                          -> Result<(), SeedError> {
+        let result = self.source.fetch_piece(piece).and_then(|data| {
+            for (index, block) in data.chunks(BLOCK_SIZE).enumerate() {
+                if !store.receive(piece as u32, (index * BLOCK_SIZE) as u32, block.to_vec()) {
+                    return Err(SeedError::BadPiece(piece));
+                }
+            }
+            if !store.has_piece(piece) {
+                return Err(SeedError::BadPiece(piece));
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => self.backoff.succeed(),
+            Err(SeedError::RetryAfter(delay)) => self.backoff.fail(Instant::now(), Some(delay)),
+            Err(_) => self.backoff.fail(Instant::now(), None),
+        }
+        result
+    }
+
+    // Fetches every piece still missing, in order, until the first failure.
+    // Gives how many pieces were added.
+    pub fn download(&mut self, store: &PieceStore) -> Result<usize, SeedError> {
+        let mut added = 0;
+        for piece in 0..store.length() {
+            if store.has_piece(piece) {
+                continue;
+            }
+            try!(self.download_piece(store, piece));
+            added += 1;
+        }
+        Ok(added)
+    }
+}
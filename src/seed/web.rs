@@ -0,0 +1,123 @@
+use std::io::{self, Read};
+use std::time::Duration;
+use hyper::client::Client;
+use hyper::header::Range;
+use hyper::status::StatusCode;
+use file::Layout;
+use metainfo::{MetaInfo, ModeInfo};
+use seed::{PieceSource, SeedError};
+use tracker::data::url_encode;
+use https;
+
+const TIMEOUT_SECONDS: u64 = 30;
+
+// A BEP 19 web seed, an HTTP server holding the torrent's files under their
+// own names. Pieces are put together from byte ranges of those files.
+//
+//     let mut seeder = Seeder::new(try!(WebSeed::new(&metainfo.url_list[0], &metainfo)));
+//     try!(seeder.download(&mut partial_file));
+pub struct WebSeed {
+    url: String,
+    // One URL for each file of the layout, None for padding.
+    file_urls: Vec<Option<String>>,
+    layout: Layout,
+    client: Client,
+}
+
+impl WebSeed {
+    pub fn new(url: &str, metainfo: &MetaInfo) -> Result<WebSeed, SeedError> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(SeedError::InvalidUrl(url.to_string()));
+        }
+        let layout = Layout::new(&metainfo.info);
+        let file_urls = layout.files()
+            .iter()
+            .map(|file| {
+                if file.padding {
+                    return None;
+                }
+                let path: Vec<&str> = file.path.iter().map(|name| name.as_str()).collect();
+                Some(file_url(url, metainfo, &path))
+            })
+            .collect();
+        let mut client = https::client();
+        client.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        client.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        Ok(WebSeed {
+            url: url.to_string(),
+            file_urls: file_urls,
+            layout: layout,
+            client: client,
+        })
+    }
+
+    // Where the file at an index of the layout is served from.
+    pub fn file_url(&self, file: usize) -> Option<&str> {
+        self.file_urls.get(file).and_then(|url| url.as_ref()).map(|url| url.as_str())
+    }
+
+    // Reads `buffer.len()` bytes of a file from `offset` on.
+    fn fetch_range(&self, url: &str, offset: u64, buffer: &mut [u8]) -> Result<(), SeedError> {
+        let last = offset + buffer.len() as u64 - 1;
+        let mut response = try!(self.client
+            .get(url)
+            .header(Range::bytes(offset, last))
+            .send()
+            .map_err(|e| SeedError::Http(e.to_string())));
+        match response.status {
+            StatusCode::PartialContent => (),
+            // the server ignored the range and sends the whole file
+            StatusCode::Ok => {
+                let skipped = try!(io::copy(&mut (&mut response).take(offset), &mut io::sink()));
+                if skipped < offset {
+                    return Err(SeedError::IOError(io::ErrorKind::UnexpectedEof.into()));
+                }
+            }
+            status => return Err(SeedError::Status(status.to_u16())),
+        }
+        try!(response.read_exact(buffer));
+        Ok(())
+    }
+}
+
+impl PieceSource for WebSeed {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn fetch_piece(&mut self, piece: usize) -> Result<Vec<u8>, SeedError> {
+        let size = try!(self.layout.piece_size(piece).ok_or(SeedError::NoSuchPiece(piece)));
+        let spans = try!(self.layout.map(piece, 0, size).ok_or(SeedError::NoSuchPiece(piece)));
+        // padding is never asked for and stays zeros
+        let mut data = vec![0; size as usize];
+        for span in spans.into_iter().filter(|span| span.length > 0) {
+            let url = try!(self.file_url(span.file).ok_or(SeedError::NoSuchPiece(piece)));
+            let start = span.block_offset as usize;
+            let end = start + span.length as usize;
+            try!(self.fetch_range(url, span.offset, &mut data[start..end]));
+        }
+        Ok(data)
+    }
+}
+
+// Single-file torrents are at the URL itself, unless it names a directory.
+// Files of multi-file torrents are under the torrent's name.
+fn file_url(url: &str, metainfo: &MetaInfo, path: &[&str]) -> String {
+    let name = metainfo.info.name.as_ref().map(|name| name.as_str()).unwrap_or("");
+    let mut file_url = url.to_string();
+    match metainfo.info.mode_info {
+        ModeInfo::Single(_) if !url.ends_with('/') => return file_url,
+        ModeInfo::Single(_) => file_url.push_str(&url_encode(name.as_bytes())),
+        ModeInfo::Multi(_) => {
+            if !url.ends_with('/') {
+                file_url.push('/');
+            }
+            file_url.push_str(&url_encode(name.as_bytes()));
+            for component in path.iter() {
+                file_url.push('/');
+                file_url.push_str(&url_encode(component.as_bytes()));
+            }
+        }
+    }
+    file_url
+}
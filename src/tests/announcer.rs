@@ -0,0 +1,115 @@
+#[allow(unused_imports)]
+use tracker::{Announcer, Transfer, TransferCounters, TrackerTiers, TrackerResp, TrackerError,
+              TrackerEvent};
+#[allow(unused_imports)]
+use std::sync::Arc;
+#[allow(unused_imports)]
+use std::time::{Duration, Instant};
+#[cfg(test)]
+use tests::response;
+
+#[cfg(test)]
+fn announcer(left: u64) -> (Announcer, Arc<TransferCounters>) {
+    let counters = Arc::new(TransferCounters::new(left));
+    let tiers = TrackerTiers::from_tiers(vec![vec!["http://a".to_string()]]);
+    (Announcer::new(tiers, counters.clone()), counters)
+}
+
+// Announces, recording what was sent, with the tracker answering `answer`.
+#[cfg(test)]
+fn announce(announcer: &mut Announcer,
+            now: Instant,
+            sent: &mut Vec<(TrackerEvent, Transfer)>,
+            answer: Result<TrackerResp, TrackerError>)
+            -> bool {
+    let mut answer = Some(answer);
+    announcer.announce(now, |_, event, transfer| {
+            sent.push((event, transfer));
+            answer.take().unwrap()
+        })
+        .is_ok()
+}
+
+#[test]
+pub fn test_announcer_lifecycle() {
+    let (mut announcer, counters) = announcer(1000);
+    let start = Instant::now();
+    let mut sent = Vec::new();
+    assert!(announcer.is_due(start));
+    assert!(announce(&mut announcer, start, &mut sent, Ok(response(600, Some(60)))));
+    assert_eq!(sent[0],
+               (TrackerEvent::Started, Transfer { uploaded: 0, downloaded: 0, left: 1000 }));
+
+    // regular announces wait out the interval, with the counts as they are
+    counters.add_downloaded(400);
+    counters.add_uploaded(50);
+    counters.set_left(600);
+    assert!(!announcer.is_due(start + Duration::from_secs(599)));
+    assert!(announcer.is_due(start + Duration::from_secs(600)));
+    let second = start + Duration::from_secs(600);
+    assert!(announce(&mut announcer, second, &mut sent, Ok(response(600, Some(60)))));
+    assert_eq!(sent[1],
+               (TrackerEvent::Regular, Transfer { uploaded: 50, downloaded: 400, left: 600 }));
+
+    // finishing only has to wait for the min interval
+    counters.add_downloaded(600);
+    counters.set_left(0);
+    assert_eq!(announcer.event(), TrackerEvent::Completed);
+    assert!(!announcer.is_due(second + Duration::from_secs(59)));
+    let third = second + Duration::from_secs(60);
+    assert!(announcer.is_due(third));
+    assert!(announce(&mut announcer, third, &mut sent, Ok(response(1800, None))));
+    assert_eq!(sent[2].0, TrackerEvent::Completed);
+    assert_eq!(sent[2].1.left, 0);
+    assert_eq!(announcer.event(), TrackerEvent::Regular);
+    assert!(!announcer.is_due(third + Duration::from_secs(1799)));
+
+    let mut stopped = Vec::new();
+    announcer.stop(third, |_, event, transfer| {
+            stopped.push((event, transfer));
+            Ok(response(1800, None))
+        })
+        .unwrap();
+    assert_eq!(stopped,
+               vec![(TrackerEvent::Stopped, Transfer { uploaded: 50, downloaded: 1000, left: 0 })]);
+    assert!(!announcer.is_started());
+    // only once
+    announcer.stop(third, |_, _, _| panic!("Stopped twice")).unwrap();
+}
+
+#[test]
+pub fn test_announcer_backs_off_and_keeps_the_event() {
+    let (mut announcer, _) = announcer(1000);
+    let now = Instant::now();
+    let mut sent = Vec::new();
+    let down = || Err(TrackerError::Http("down".to_string()));
+    let mut retries = Vec::new();
+    let mut at = now;
+    for _ in 0..9 {
+        assert!(!announce(&mut announcer, at, &mut sent, down()));
+        let due = announcer.due_at().unwrap();
+        retries.push(due - at);
+        at = due;
+    }
+    let seconds: Vec<u64> = retries.iter().map(|delay| delay.as_secs()).collect();
+    assert_eq!(seconds, vec![15, 30, 60, 120, 240, 480, 960, 1800, 1800]);
+    assert!(sent.iter().all(|&(event, _)| event == TrackerEvent::Started));
+    assert_eq!(announcer.failures(), 9);
+
+    assert!(announce(&mut announcer, at, &mut sent, Ok(response(900, None))));
+    assert_eq!(announcer.failures(), 0);
+    assert_eq!(announcer.due_at(), Some(at + Duration::from_secs(900)));
+}
+
+#[test]
+pub fn test_announcer_seeding_from_start() {
+    let (mut announcer, _) = announcer(0);
+    let now = Instant::now();
+    let mut sent = Vec::new();
+    // stopping before starting tells no one
+    announcer.stop(now, |_, _, _| panic!("Not started yet")).unwrap();
+    // a min interval over the interval wins
+    assert!(announce(&mut announcer, now, &mut sent, Ok(response(10, Some(120)))));
+    assert_eq!(announcer.event(), TrackerEvent::Regular);
+    assert_eq!(announcer.due_at(), Some(now + Duration::from_secs(120)));
+}
@@ -0,0 +1,118 @@
+#[allow(unused_imports)]
+use bencode::{Bencode, BString};
+#[allow(unused_imports)]
+use bencode::borrowed::{BencodeRef, belement_decode_ref};
+#[allow(unused_imports)]
+use bencode::decode::belement_decode;
+#[allow(unused_imports)]
+use metainfo::MetaInfo;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::fs::File;
+
+#[cfg(test)]
+fn read_torrent(path: &str) -> Vec<u8> {
+    let mut file = File::open(path).unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+pub fn test_borrowed_strings_point_into_input() {
+    let bytes = read_torrent("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent");
+    let dict = belement_decode_ref(&bytes).unwrap().0.as_dict().unwrap();
+    let pieces = dict.get_dict("info").unwrap().get_bytes("pieces").unwrap();
+    let input_range = (bytes.as_ptr() as usize)..(bytes.as_ptr() as usize + bytes.len());
+    assert!(input_range.start <= pieces.as_ptr() as usize);
+    assert!(pieces.as_ptr() as usize + pieces.len() <= input_range.end);
+    assert_eq!(pieces.len() % 20, 0);
+}
+
+#[test]
+pub fn test_borrowed_converts_to_owned() {
+    let bytes = read_torrent("src/tests/data/adventures_holmes_archive.torrent");
+    let borrowed = belement_decode_ref(&bytes).unwrap();
+    let owned = belement_decode(&bytes).unwrap();
+    assert_eq!(borrowed.1, owned.1);
+    assert_eq!(borrowed.0.to_bencode(), owned.0);
+    assert_eq!(Bencode::from(borrowed.0), owned.0);
+}
+
+#[test]
+pub fn test_borrowed_typed_accessors() {
+    let input = b"d3:cowi-9223372036854775808e4:listl4:wordi7ee4:spam4:eggse";
+    let element = belement_decode_ref(input).unwrap().0;
+    assert_eq!(element.as_int(), None);
+    let dict = element.as_dict().unwrap();
+    assert_eq!(dict.get_int("cow"), Some(i64::min_value()));
+    assert_eq!(dict.get_str("spam"), Some("eggs"));
+    assert_eq!(dict.get_bytes("cow"), None);
+    assert_eq!(dict.get("aardvark"), None);
+    assert_eq!(dict.get("zebra"), None);
+    assert_eq!(dict.raw(), &input[..]);
+
+    let list = dict.get_list("list").unwrap();
+    assert_eq!(list.len(), 2);
+    assert!(!list.is_empty());
+    assert_eq!(list.raw(), b"l4:wordi7ee");
+    let items: Vec<BencodeRef> = list.iter().collect();
+    assert_eq!(items, vec![BencodeRef::BString(b"word"), BencodeRef::BInt(7)]);
+
+    let keys: Vec<&[u8]> = dict.iter().map(|(key, _)| key).collect();
+    assert_eq!(keys, vec![&b"cow"[..], b"list", b"spam"]);
+    let copied: Option<String> = dict.get_copy("spam");
+    assert_eq!(copied, Some("eggs".to_string()));
+}
+
+#[test]
+pub fn test_borrowed_handles_empty_containers() {
+    let element = belement_decode_ref(b"ld0:lee0:e").unwrap().0;
+    let items: Vec<BencodeRef> = element.as_list().unwrap().iter().collect();
+    assert_eq!(items.len(), 2);
+    let dict = items[0].as_dict().unwrap();
+    assert!(dict.get_list("").unwrap().is_empty());
+    assert_eq!(items[1], BencodeRef::BString(b""));
+}
+
+#[test]
+pub fn test_borrowed_reports_decode_errors() {
+    assert!(belement_decode_ref(b"d3:cow").is_err());
+    assert_eq!(belement_decode_ref(b"i1ei2e").unwrap().1, 3);
+}
+
+#[test]
+pub fn test_parses_metainfo_from_borrowed_dict() {
+    let bytes = read_torrent("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent");
+    let dict = belement_decode_ref(&bytes).unwrap().0.as_dict().unwrap();
+    let borrowed = MetaInfo::try_from(dict).ok().unwrap();
+    let owned = match belement_decode(&bytes).unwrap().0 {
+        Bencode::BDict(bdict) => MetaInfo::try_from(bdict).ok().unwrap(),
+        _ => panic!("Got wrong kind of object"),
+    };
+    assert_eq!(borrowed.announce, owned.announce);
+    assert_eq!(borrowed.info.pieces, owned.info.pieces);
+    assert_eq!(borrowed.info.piece_length, owned.info.piece_length);
+}
+
+#[test]
+pub fn test_borrowed_records_value_spans() {
+    let input = b"d3:cowi-3e4:listl4:wordd1:ai1eee4:spam4:eggse";
+    let dict = belement_decode_ref(input).unwrap().0.as_dict().unwrap();
+    assert_eq!(dict.span(), 0..input.len());
+    assert_eq!(dict.get_span("cow"), Some(6..10));
+    assert_eq!(dict.get_span("spam"), Some(38..44));
+    assert_eq!(dict.get_span("missing"), None);
+
+    let list = dict.get_list("list").unwrap();
+    assert_eq!(list.span(), dict.get_span("list").unwrap());
+    assert_eq!(&input[list.span()], b"l4:wordd1:ai1eee");
+    assert_eq!(list.get_span(0), Some(17..23));
+    assert_eq!(list.get_span(2), None);
+    let inner = list.iter().nth(1).unwrap().as_dict().unwrap();
+    assert_eq!(Some(inner.span()), list.get_span(1));
+    assert_eq!(&input[inner.span()], inner.raw());
+}
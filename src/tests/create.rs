@@ -0,0 +1,157 @@
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, TorrentBuilder, BuildError, ModeInfo};
+#[allow(unused_imports)]
+use bencode::BDict;
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use bencode::encode::bdict_encode;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use sha1::Sha1;
+#[allow(unused_imports)]
+use std::{env, fs};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::path::PathBuf;
+#[cfg(test)]
+use tests::{parse, encode, make_dir};
+
+#[test]
+pub fn test_writes_parsed_torrent_back_unchanged() {
+    let mut bytes = Vec::new();
+    fs::File::open("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent")
+        .unwrap()
+        .read_to_end(&mut bytes)
+        .unwrap();
+    assert_eq!(encode(parse(&bytes).unwrap()), bytes);
+
+    // unknown info keys survive, unknown top level ones do not
+    let mut bytes = Vec::new();
+    fs::File::open("src/tests/data/adventures_holmes_archive.torrent")
+        .unwrap()
+        .read_to_end(&mut bytes)
+        .unwrap();
+    let metainfo = parse(&bytes).unwrap();
+    let hash = metainfo.info_hash.clone();
+    assert_eq!(parse(&encode(metainfo)).unwrap().info_hash, hash);
+}
+
+#[test]
+pub fn test_builds_multi_file_torrent() {
+    // files are laid out sorted by path, whatever order they were made in
+    let (dir, _) = make_dir("multi", &[("b/c", 20000), ("a", 30000), ("b/a", 1)]);
+    let (_, data) = make_dir("multi-expected", &[("a", 30000), ("b/a", 1), ("b/c", 20000)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .announce("http://a/announce")
+        .announce("http://b/announce")
+        .web_seed("http://seed/")
+        .comment("comment")
+        .private(true)
+        .source("source")
+        .creation_date(1000)
+        .threads(3)
+        .build()
+        .unwrap();
+
+    let expected: Vec<Vec<u8>> = data.chunks(16384)
+        .map(|piece| {
+            let mut sha1 = Sha1::new();
+            sha1.update(piece);
+            sha1.digest().bytes().to_vec()
+        })
+        .collect();
+    assert_eq!(metainfo.info.pieces, expected);
+    assert_eq!(metainfo.info.name, Some("rustorrent-test-multi".to_string()));
+
+    let parsed = parse(&encode(metainfo.clone())).unwrap();
+    assert_eq!(parsed.info_hash, metainfo.info_hash);
+    assert_eq!(parsed.announce, Some("http://a/announce".to_string()));
+    assert_eq!(parsed.announce_list.len(), 2);
+    assert_eq!(parsed.url_list, vec!["http://seed/".to_string()]);
+    assert_eq!(parsed.comment, Some("comment".to_string()));
+    assert_eq!(parsed.creation_date, Some(1000));
+    assert_eq!(parsed.info.private, Some(1));
+    assert_eq!(parsed.info.source, Some("source".to_string()));
+    match parsed.info.mode_info {
+        ModeInfo::Multi(ref multi) => {
+            let paths: Vec<Vec<String>> = multi.files.iter().map(|f| f.path.clone()).collect();
+            assert_eq!(paths,
+                       vec![vec!["a".to_string()],
+                            vec!["b".to_string(), "a".to_string()],
+                            vec!["b".to_string(), "c".to_string()]]);
+        }
+        _ => panic!("Expected a multi file torrent"),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_thread_count_does_not_change_hashes() {
+    let (dir, _) = make_dir("threads", &[("x", 100000)]);
+    let path = dir.join("x");
+    let one = TorrentBuilder::new(&path).creation_date(0).threads(1).build().unwrap();
+    let many = TorrentBuilder::new(&path).creation_date(0).threads(8).build().unwrap();
+    assert_eq!(one.info.pieces.len(), 7);
+    assert_eq!(one.info.pieces, many.info.pieces);
+    assert_eq!(one.info_hash, many.info_hash);
+    match one.info.mode_info {
+        ModeInfo::Single(ref single) => assert_eq!(single.length, 100000),
+        _ => panic!("Expected a single file torrent"),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_rejects_bad_input() {
+    let (dir, _) = make_dir("bad", &[]);
+    fs::create_dir_all(&dir).unwrap();
+    match TorrentBuilder::new(&dir).build() {
+        Err(BuildError::NoFiles) => (),
+        _ => panic!("Built a torrent of nothing"),
+    }
+    match TorrentBuilder::new("src/tests/data").piece_length(1000).build() {
+        Err(BuildError::InvalidPieceLength(1000)) => (),
+        _ => panic!("Accepted a bad piece length"),
+    }
+    match TorrentBuilder::new("/").build() {
+        Err(BuildError::NoName(_)) => (),
+        _ => panic!("Named a torrent after nothing"),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_resolves_path_before_naming() {
+    let (dir, _) = make_dir("resolved", &[("a/x", 100)]);
+    for path in vec![dir.join("."), dir.join("a").join("..")] {
+        let metainfo = TorrentBuilder::new(&path).creation_date(0).build().unwrap();
+        assert_eq!(metainfo.info.name, Some("rustorrent-test-resolved".to_string()));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_does_not_follow_linked_directories() {
+    use std::os::unix::fs::symlink;
+    let (dir, _) = make_dir("links", &[("a/x", 100), ("y", 10)]);
+    // a link back up the tree would otherwise never end
+    symlink(&dir, dir.join("a").join("loop")).unwrap();
+    symlink(dir.join("y"), dir.join("z")).unwrap();
+    let metainfo = TorrentBuilder::new(&dir).creation_date(0).build().unwrap();
+    match metainfo.info.mode_info {
+        ModeInfo::Multi(ref multi) => {
+            let paths: Vec<Vec<String>> = multi.files.iter().map(|f| f.path.clone()).collect();
+            assert_eq!(paths,
+                       vec![vec!["a".to_string(), "x".to_string()],
+                            vec!["y".to_string()],
+                            vec!["z".to_string()]]);
+        }
+        _ => panic!("Expected a multi file torrent"),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,159 @@
+#[allow(unused_imports)]
+use bencode::{DecodeError, DecodeErrorKind};
+#[allow(unused_imports)]
+use bencode::decode::{belement_decode, bint_decode, bstring_decode, bdict_decode,
+                      MAX_NESTING_DEPTH};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::fs::File;
+
+#[cfg(test)]
+fn decode_error(s: &[u8]) -> DecodeError {
+    match belement_decode(s) {
+        Ok(_) => panic!("Decoded invalid input {:?}", s),
+        Err(e) => e,
+    }
+}
+
+#[test]
+pub fn test_truncated_inputs_fail_without_panic() {
+    let inputs: [&[u8]; 12] = [b"", b"i", b"i-", b"i12", b"5", b"5:", b"5:abc", b"l", b"li1e",
+                               b"d", b"d3:cow", b"d3:cow3:mo"];
+    for input in inputs.iter() {
+        let error = decode_error(input);
+        match error.kind {
+            DecodeErrorKind::EndOfStream => assert_eq!(error.position, Some(input.len())),
+            _ => panic!("Expected end of stream for {:?}, got {}", input, error),
+        }
+    }
+}
+
+#[test]
+pub fn test_every_prefix_of_torrent_fails_cleanly() {
+    let mut file = File::open("src/tests/data/adventures_holmes_archive.torrent").unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    // every byte of the header, then a sample across the pieces blob
+    let ends = (0..1024).chain((1024..bytes.len()).filter(|i| i % 97 == 0));
+    for end in ends {
+        let error = decode_error(&bytes[0..end]);
+        assert!(error.position.is_some());
+    }
+    assert_eq!(belement_decode(&bytes).unwrap().1, bytes.len());
+}
+
+#[test]
+pub fn test_corrupted_bytes_never_panic() {
+    let mut file = File::open("src/tests/data/adventures_holmes_archive.torrent").unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    // the header holds all the structure, the rest is the pieces blob
+    bytes.truncate(512);
+    let samples = [bytes, b"d1:ai-12e1:bl0:i0eld1:cleee1:d3:\xff\x00\x01e".to_vec()];
+    let replacements = [b'e', b'i', b'l', b'd', b':', b'0', b'9', b'-', 0xff];
+    for sample in samples.iter() {
+        for i in 0..sample.len() {
+            for &replacement in replacements.iter() {
+                let mut corrupted = sample.clone();
+                corrupted[i] = replacement;
+                let _ = belement_decode(&corrupted);
+                let _ = belement_decode(&corrupted[i..]);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_rejects_leading_zeros() {
+    let error = decode_error(b"i03e");
+    match error.kind {
+        DecodeErrorKind::LeadingZero => assert_eq!(error.position, Some(1)),
+        _ => panic!("Wrong error: {}", error),
+    }
+    let error = decode_error(b"l02:abe");
+    match error.kind {
+        DecodeErrorKind::LeadingZero => assert_eq!(error.position, Some(1)),
+        _ => panic!("Wrong error: {}", error),
+    }
+    assert!(bint_decode(b"i-01e").is_err());
+    assert!(bstring_decode(b"0:").is_ok());
+}
+
+#[test]
+pub fn test_rejects_malformed_ints() {
+    for input in [&b"ie"[..], b"i-e", b"i1-2e", b"i 1e"].iter() {
+        assert!(bint_decode(input).is_err());
+    }
+    let error = decode_error(b"i99999999999999999999e");
+    match error.kind {
+        DecodeErrorKind::IntParsingErr(_) => assert_eq!(error.position, Some(0)),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_rejects_unsorted_dict_keys() {
+    let error = decode_error(b"d4:spam4:eggs3:cow3:mooe");
+    match error.kind {
+        DecodeErrorKind::UnsortedKeys => assert_eq!(error.position, Some(13)),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_rejects_duplicate_dict_keys() {
+    let error = decode_error(b"d3:cow3:moo3:cow3:mooe");
+    match error.kind {
+        DecodeErrorKind::DuplicateKey => assert_eq!(error.position, Some(11)),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_rejects_non_string_dict_keys() {
+    let error = decode_error(b"di1ei2ee");
+    match error.kind {
+        DecodeErrorKind::InvalidString => assert_eq!(error.position, Some(1)),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_reports_absolute_position_of_nested_errors() {
+    let error = decode_error(b"d4:listli1ei2exee");
+    match error.kind {
+        DecodeErrorKind::UnknownType => assert_eq!(error.position, Some(14)),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_limits_nesting_depth() {
+    let mut deep = Vec::new();
+    for _ in 0..MAX_NESTING_DEPTH {
+        deep.push(b'l');
+    }
+    for _ in 0..MAX_NESTING_DEPTH {
+        deep.push(b'e');
+    }
+    assert!(belement_decode(&deep).is_ok());
+
+    let mut too_deep = vec![b'l'; MAX_NESTING_DEPTH + 1];
+    too_deep.extend(vec![b'e'; MAX_NESTING_DEPTH + 1]);
+    let error = decode_error(&too_deep);
+    match error.kind {
+        DecodeErrorKind::NestingTooDeep => assert_eq!(error.position, Some(MAX_NESTING_DEPTH)),
+        _ => panic!("Wrong error: {}", error),
+    }
+
+    let hostile = vec![b'l'; 1000000];
+    assert!(belement_decode(&hostile).is_err());
+}
+
+#[test]
+pub fn test_rejects_string_length_past_end() {
+    let error = decode_error(b"99999999999999999999999:a");
+    assert!(error.position.is_some());
+    assert!(bdict_decode(b"d3:cow10:mooe").is_err());
+}
@@ -0,0 +1,120 @@
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, TorrentEditor};
+#[allow(unused_imports)]
+use bencode::{BDict, Bencode, BString};
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use bencode::encode::bdict_encode_with_raw;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use x509::private_key_from_pem;
+#[allow(unused_imports)]
+use std::fs::File;
+#[allow(unused_imports)]
+use std::io::Read;
+#[cfg(test)]
+use tests::parse;
+
+// The info dict as it appears in the encoded torrent.
+#[cfg(test)]
+fn raw_info(bytes: &[u8]) -> Vec<u8> {
+    let dict = belement_decode_ref(bytes).unwrap().0.as_dict().unwrap();
+    dict.get_dict("info").unwrap().raw().to_vec()
+}
+
+// With keys no client knows about, in and out of the info dict.
+#[cfg(test)]
+const TORRENT: &'static [u8] = b"d8:announce14:http://a/annou7:comment3:old\
+                                 4:infod6:lengthi5e4:name1:f12:piece lengthi16e\
+                                 6:pieces20:aaaaaaaaaaaaaaaaaaaa1:xd1:ai0eee9:publisher3:me!e";
+
+#[test]
+pub fn test_encodes_raw_values_in_order() {
+    let mut dict = BDict::new();
+    dict.insert("b", Bencode::BString(BString::from_str("x")));
+    dict.insert("d", Bencode::BString(BString::from_str("y")));
+    dict.insert("c", Bencode::BString(BString::from_str("replaced")));
+    assert_eq!(bdict_encode_with_raw(&dict, &[("c", b"d1:zi0e1:ai0ee"), ("a", b"le")]),
+               b"d1:ale1:b1:x1:cd1:zi0e1:ai0ee1:d1:ye".to_vec());
+}
+
+#[test]
+pub fn test_edit_keeps_info_dict() {
+    let before = parse(TORRENT).unwrap();
+    let edited = TorrentEditor::new()
+        .clear_trackers()
+        .announce("http://b/announce")
+        .announce("http://c/announce")
+        .comment(None)
+        .edit(TORRENT)
+        .unwrap();
+    assert_eq!(raw_info(&edited), raw_info(TORRENT));
+    let after = parse(&edited).unwrap();
+    assert_eq!(after.info_hash, before.info_hash);
+    assert_eq!(after.announce, Some("http://b/announce".to_string()));
+    assert_eq!(after.announce_list,
+               vec![vec!["http://b/announce".to_string()], vec!["http://c/announce".to_string()]]);
+    assert_eq!(after.comment, None);
+    // keys the editor does not know about are kept
+    let dict = belement_decode_ref(&edited).unwrap().0.as_dict().unwrap();
+    assert_eq!(dict.get_str("publisher"), Some("me!"));
+
+    // trackers are removed and added around the ones already there
+    let edited = TorrentEditor::new()
+        .remove_tracker("http://b/announce")
+        .announce("http://c/announce")
+        .announce("http://d/announce")
+        .comment(Some("new"))
+        .edit(&edited)
+        .unwrap();
+    let after = parse(&edited).unwrap();
+    assert_eq!(after.announce, Some("http://c/announce".to_string()));
+    assert_eq!(after.announce_list,
+               vec![vec!["http://c/announce".to_string()], vec!["http://d/announce".to_string()]]);
+    assert_eq!(after.comment, Some("new".to_string()));
+    assert_eq!(raw_info(&edited), raw_info(TORRENT));
+
+    // a single tracker needs no list
+    let edited = TorrentEditor::new().remove_tracker("http://d/announce").edit(&edited).unwrap();
+    let after = parse(&edited).unwrap();
+    assert_eq!(after.announce, Some("http://c/announce".to_string()));
+    assert!(after.announce_list.is_empty());
+
+    // setting the flags to what they are already changes nothing
+    let editor = TorrentEditor::new().private(false).source(None);
+    assert!(!editor.changes_info(&before));
+    assert_eq!(raw_info(&editor.edit(TORRENT).unwrap()), raw_info(TORRENT));
+}
+
+#[test]
+pub fn test_edit_private_and_source() {
+    let mut key = String::new();
+    File::open("src/tests/data/other.key").unwrap().read_to_string(&mut key).unwrap();
+    let mut signed = parse(TORRENT).unwrap();
+    signed.sign("someone-else", &private_key_from_pem(&key).unwrap(), None).unwrap();
+    let bdict: BDict = signed.into();
+    let torrent = bdict_encode_with_raw(&bdict, &[("info", &raw_info(TORRENT))]);
+    let before = parse(&torrent).unwrap();
+    assert_eq!(before.signatures.len(), 1);
+
+    let editor = TorrentEditor::new().private(true).source(Some("TRACKER"));
+    assert!(editor.changes_info(&before));
+    let edited = editor.edit(&torrent).unwrap();
+    let after = parse(&edited).unwrap();
+    assert!(after.info_hash != before.info_hash);
+    assert_eq!(after.info.private, Some(1));
+    assert_eq!(after.info.source, Some("TRACKER".to_string()));
+    assert_eq!(after.info.name, before.info.name);
+    assert_eq!(after.info.pieces, before.info.pieces);
+    assert!(raw_info(&edited).ends_with(b"1:xd1:ai0eee"));
+    // the old signatures no longer cover the info dict
+    assert!(after.signatures.is_empty());
+    assert_eq!(after.comment, Some("old".to_string()));
+
+    let edited = TorrentEditor::new().private(false).source(None).edit(&edited).unwrap();
+    let after = parse(&edited).unwrap();
+    assert_eq!(after.info.private, None);
+    assert_eq!(after.info.source, None);
+}
@@ -0,0 +1,77 @@
+#[allow(unused_imports)]
+use bencode::{Bencode, BDict, BString, BInt, BList};
+#[allow(unused_imports)]
+use bencode::decode::belement_decode;
+#[allow(unused_imports)]
+use bencode::encode::{belement_encode, belement_encode_to, bint_encode, bstring_encode};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::fs::File;
+
+#[test]
+pub fn test_encodes_ints() {
+    assert_eq!(bint_encode(&BInt::new(0)), b"i0e".to_vec());
+    assert_eq!(bint_encode(&BInt::new(42)), b"i42e".to_vec());
+    assert_eq!(bint_encode(&BInt::new(-7)), b"i-7e".to_vec());
+}
+
+#[test]
+pub fn test_encodes_binary_string() {
+    assert_eq!(bstring_encode(&BString::from_str("spam")), b"4:spam".to_vec());
+    assert_eq!(bstring_encode(&BString::new(&[0, 255, b':'])),
+               vec![b'3', b':', 0, 255, b':']);
+    assert_eq!(bstring_encode(&BString::new(&[])), b"0:".to_vec());
+}
+
+#[test]
+pub fn test_encodes_nested_list() {
+    let mut inner = BList::new();
+    inner.push(Bencode::BString(BString::from_str("e")));
+    inner.push(Bencode::BInt(BInt::new(1)));
+    let mut outer = BList::new();
+    outer.push(Bencode::BList(inner));
+    outer.push(Bencode::BList(BList::new()));
+    assert_eq!(belement_encode(&Bencode::BList(outer)), b"ll1:ei1eelee".to_vec());
+}
+
+#[test]
+pub fn test_encodes_dict_keys_in_byte_order() {
+    let mut dict = BDict::new();
+    dict.insert("spam", Bencode::BString(BString::from_str("eggs")));
+    dict.insert("cow", Bencode::BString(BString::from_str("moo")));
+    dict.insert("Zeta", Bencode::BInt(BInt::new(1)));
+    assert_eq!(belement_encode(&Bencode::BDict(dict)),
+               b"d4:Zetai1e3:cow3:moo4:spam4:eggse".to_vec());
+}
+
+#[test]
+pub fn test_encodes_into_writer() {
+    let mut out: Vec<u8> = Vec::new();
+    belement_encode_to(&Bencode::BInt(BInt::new(3)), &mut out).unwrap();
+    belement_encode_to(&Bencode::BString(BString::from_str("ab")), &mut out).unwrap();
+    assert_eq!(out, b"i3e2:ab".to_vec());
+}
+
+#[test]
+pub fn test_round_trips_decoded_values() {
+    let inputs = ["i-11e", "13:Hello, world!", "ll1:e2:eeee", "ldee",
+                  "d4:listll1:e2:eeee5:thing6:thing1e", "d3:cow3:moo4:spam4:eggse"];
+    for input in inputs.iter() {
+        let element = belement_decode(input.as_bytes()).unwrap().0;
+        assert_eq!(belement_encode(&element), input.as_bytes().to_vec());
+    }
+}
+
+#[test]
+pub fn test_round_trips_torrent_files() {
+    let paths = ["src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent",
+                 "src/tests/data/adventures_holmes_archive.torrent"];
+    for path in paths.iter() {
+        let mut file = File::open(path).unwrap();
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        let element = belement_decode(&bytes).unwrap().0;
+        assert_eq!(belement_encode(&element), bytes);
+    }
+}
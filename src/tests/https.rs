@@ -0,0 +1,52 @@
+#[allow(unused_imports)]
+use https;
+#[allow(unused_imports)]
+use hyper;
+#[allow(unused_imports)]
+use openssl::pkey::PKey;
+#[allow(unused_imports)]
+use openssl::ssl::{SslAcceptor, SslMethod};
+#[allow(unused_imports)]
+use openssl::x509::X509;
+#[allow(unused_imports)]
+use std::fs::File;
+#[allow(unused_imports)]
+use std::io::{Read, Write};
+#[allow(unused_imports)]
+use std::net::TcpListener;
+#[allow(unused_imports)]
+use std::thread;
+
+#[cfg(test)]
+fn read(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    File::open(format!("src/tests/data/{}", name)).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+pub fn test_https_refuses_untrusted_servers() {
+    // a TLS server whose certificate no system root vouches for
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    acceptor.set_certificate(&X509::from_pem(&read("signer.pem")).unwrap()).unwrap();
+    acceptor.set_private_key(&PKey::private_key_from_pem(&read("signer.key")).unwrap())
+        .unwrap();
+    let acceptor = acceptor.build();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("https://{}/announce", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let stream = listener.incoming().next().unwrap().unwrap();
+        // the handshake has to fail, so nothing is ever served
+        let accepted = acceptor.accept(stream).map(|mut stream| {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        });
+        accepted.is_ok()
+    });
+
+    match https::client().get(&url[..]).send() {
+        Err(hyper::Error::Ssl(_)) => (),
+        Err(e) => panic!("Expected a TLS error, got {}", e),
+        Ok(_) => panic!("Trusted a self-made certificate"),
+    }
+    assert!(!server.join().unwrap());
+}
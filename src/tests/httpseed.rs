@@ -0,0 +1,134 @@
+#[allow(unused_imports)]
+use metainfo::MetaInfo;
+#[allow(unused_imports)]
+use seed::{HttpSeed, PieceSource, SeedError, Seeder};
+#[allow(unused_imports)]
+use file::{PartialFile, PartialFileTrait};
+#[allow(unused_imports)]
+use tracker::data::url_encode;
+#[cfg(test)]
+use tests::webseed::{Reply, TestServer, data, make_torrent, serve};
+#[cfg(test)]
+use tests::piece_store;
+#[allow(unused_imports)]
+use tracker::{Transfer, TransferCounters};
+#[allow(unused_imports)]
+use wire::PieceStore;
+#[allow(unused_imports)]
+use std::sync::Arc;
+#[allow(unused_imports)]
+use std::time::{Duration, Instant};
+
+// A BEP 17 seed script serving the torrent's data, with `busy` answering
+// every request instead.
+#[cfg(test)]
+fn serve_pieces(metainfo: &MetaInfo, content: Vec<u8>, busy: Option<Reply>) -> TestServer {
+    let hash = url_encode(&metainfo.info_hash);
+    let piece_length = metainfo.info.piece_length as usize;
+    serve(move |target, _| {
+        if let Some(ref busy) = busy {
+            return busy.clone();
+        }
+        let query = target.splitn(2, '?').nth(1).unwrap_or("");
+        let mut piece = None;
+        let mut ranges = Vec::new();
+        for pair in query.split('&') {
+            let mut pair = pair.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some("info_hash"), Some(value)) if value != hash => {
+                    return (404, Vec::new(), Vec::new())
+                }
+                (Some("piece"), Some(value)) => piece = value.parse::<usize>().ok(),
+                (Some("ranges"), Some(value)) => {
+                    for range in value.split(',') {
+                        let bounds: Vec<usize> =
+                            range.split('-').map(|bound| bound.parse().unwrap()).collect();
+                        ranges.push((bounds[0], bounds[1]));
+                    }
+                }
+                _ => (),
+            }
+        }
+        let start = piece.unwrap() * piece_length;
+        let mut body = Vec::new();
+        for (first, last) in ranges {
+            body.extend_from_slice(&content[(start + first)..(start + last + 1)]);
+        }
+        (200, Vec::new(), body)
+    })
+}
+
+#[test]
+pub fn test_downloads_from_http_seed() {
+    let files = vec![("a", data(20000, 6)), ("b", data(30000, 7))];
+    let metainfo = make_torrent("rustorrent-test-httpseed", &files, false);
+    let content: Vec<u8> = files.iter().flat_map(|&(_, ref data)| data.clone()).collect();
+    let server = serve_pieces(&metainfo, content.clone(), None);
+
+    let url = format!("{}/seed.php", server.url);
+    let seed = HttpSeed::new(&url, &metainfo).unwrap();
+    assert_eq!(seed.piece_url(3, &[(0, 99), (200, 299)]),
+               format!("{}?info_hash={}&piece=3&ranges=0-99,200-299",
+                       url,
+                       url_encode(&metainfo.info_hash)));
+    assert_eq!(seed.fetch_ranges(1, &[(10, 19), (100, 101)]).unwrap(),
+               [&content[16394..16404], &content[16484..16486]].concat());
+    // ranges past the end of the piece are not asked for
+    match seed.fetch_ranges(3, &[(0, 1000)]) {
+        Err(SeedError::NoSuchPiece(3)) => (),
+        other => panic!("Expected a bad range to be refused, got {:?}", other),
+    }
+
+    // pieces are counted as they would be from peers
+    let counters = Arc::new(TransferCounters::new(0));
+    let partial = PieceStore::new(PartialFile::new(&metainfo), counters.clone());
+    let mut seeder = Seeder::new(seed);
+    assert_eq!(seeder.download(&partial).unwrap(), 4);
+    assert!(partial.is_complete());
+    assert_eq!(counters.get(),
+               Transfer {
+                   uploaded: 0,
+                   downloaded: 50000,
+                   left: 0,
+               });
+    assert_eq!(server.requests.lock().unwrap()[1],
+               format!("/seed.php?info_hash={}&piece=0&ranges=0-16383",
+                       url_encode(&metainfo.info_hash)));
+
+    // extra parameters in the URL are kept
+    let seed = HttpSeed::new(&format!("{}/seed?id=1", server.url), &metainfo).unwrap();
+    let prefix = format!("{}/seed?id=1&info_hash=", server.url);
+    assert!(seed.piece_url(0, &[(0, 1)]).starts_with(&prefix));
+}
+
+#[test]
+pub fn test_http_seed_retry_after() {
+    let metainfo = make_torrent("rustorrent-test-httpseed-busy", &[("f", data(100, 8))], false);
+    let busy = serve_pieces(&metainfo, Vec::new(), Some((503, Vec::new(), b"120".to_vec())));
+    let partial = piece_store(&metainfo);
+    let mut seeder = Seeder::new(HttpSeed::new(&busy.url, &metainfo).unwrap());
+    match seeder.download(&partial) {
+        Err(SeedError::RetryAfter(delay)) => assert_eq!(delay, Duration::from_secs(120)),
+        other => panic!("Expected to be told to wait, got {:?}", other),
+    }
+    // the seed says how long, not the usual backoff
+    assert!(!seeder.backoff().is_ready(Instant::now() + Duration::from_secs(100)));
+    assert!(seeder.backoff().is_ready(Instant::now() + Duration::from_secs(121)));
+
+    // a Retry-After header is taken over the body
+    let header = vec![("Retry-After".to_string(), "7".to_string())];
+    let busy = serve_pieces(&metainfo, Vec::new(), Some((503, header, b"oops".to_vec())));
+    match HttpSeed::new(&busy.url, &metainfo).unwrap().fetch_piece(0) {
+        Err(SeedError::RetryAfter(delay)) => assert_eq!(delay, Duration::from_secs(7)),
+        other => panic!("Expected to be told to wait, got {:?}", other),
+    }
+
+    // the seed does not have this torrent
+    let mut other = metainfo.clone();
+    other.info_hash = vec![0; 20];
+    let server = serve_pieces(&metainfo, data(100, 8), None);
+    match HttpSeed::new(&server.url, &other).unwrap().fetch_piece(0) {
+        Err(SeedError::Status(404)) => (),
+        other => panic!("Expected a 404, got {:?}", other),
+    }
+}
@@ -0,0 +1,87 @@
+#[allow(unused_imports)]
+use bencode::{Bencode, BString};
+#[allow(unused_imports)]
+use bencode::decode::belement_decode;
+#[allow(unused_imports)]
+use bencode::encode::belement_encode;
+#[allow(unused_imports)]
+use bencode::json::{Binary, JsonErrorKind, to_json, from_json, base64_encode, base64_decode};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::fs::File;
+
+#[cfg(test)]
+fn decode(bytes: &[u8]) -> Bencode {
+    belement_decode(bytes).unwrap().0
+}
+
+#[test]
+pub fn test_writes_indented_json() {
+    let element = decode(b"d3:cow3:moo5:emptyle4:listli1ei-2ee4:spamdee");
+    assert_eq!(to_json(&element, Binary::Hex),
+               "{\n  \"cow\": \"moo\",\n  \"empty\": [],\n  \"list\": [\n    1,\n    -2\n  ],\n  \
+                \"spam\": {}\n}");
+}
+
+#[test]
+pub fn test_tags_binary_strings_and_keys() {
+    let element = decode(b"d4:$hex3:\x80\x81\x822:\xff\x000:e");
+    let json = to_json(&element, Binary::Hex);
+    assert_eq!(json,
+               "{\n  \"$$hex\": {\"$hex\": \"808182\"},\n  \"$hex:ff00\": \"\"\n}");
+    assert_eq!(from_json(&json).unwrap(), element);
+
+    let json = to_json(&element, Binary::Base64);
+    assert_eq!(json,
+               "{\n  \"$$hex\": {\"$base64\": \"gIGC\"},\n  \"$base64:/wA=\": \"\"\n}");
+    assert_eq!(from_json(&json).unwrap(), element);
+}
+
+#[test]
+pub fn test_escapes_strings() {
+    let element = decode(b"11:a\"b\\c\nd\x01\xc3\xa9f");
+    let json = to_json(&element, Binary::Hex);
+    assert_eq!(json, "\"a\\\"b\\\\c\\nd\\u0001\u{e9}f\"");
+    assert_eq!(from_json(&json).unwrap(), element);
+    assert_eq!(from_json("\"\\ud83d\\ude00\"").unwrap(),
+               Bencode::BString(BString::from_str("\u{1f600}")));
+}
+
+#[test]
+pub fn test_round_trips_torrents() {
+    for path in ["src/tests/data/adventures_holmes_archive.torrent",
+                 "src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent"]
+        .iter() {
+        let mut bytes = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut bytes).unwrap();
+        for &binary in [Binary::Hex, Binary::Base64].iter() {
+            let json = to_json(&decode(&bytes), binary);
+            assert_eq!(belement_encode(&from_json(&json).unwrap()), bytes);
+        }
+    }
+}
+
+#[test]
+pub fn test_base64_matches_known_values() {
+    let cases: [(&[u8], &str); 4] = [(b"", ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foobar", "Zm9vYmFy")];
+    for &(bytes, base64) in cases.iter() {
+        assert_eq!(base64_encode(bytes), base64);
+        assert_eq!(base64_decode(base64).unwrap(), bytes.to_vec());
+    }
+    assert!(base64_decode("Zg=").is_none());
+    assert!(base64_decode("Z===").is_none());
+}
+
+#[test]
+pub fn test_rejects_json_bencode_cannot_hold() {
+    let inputs = ["1.5", "true", "null", "{\"$foo:00\": 1}", "{\"$hex\": \"0\"}", "{\"a\": 1, \"a\": 2}",
+                  "[1,]", "1 2", "\"\\ud83d\""];
+    for input in inputs.iter() {
+        assert!(from_json(input).is_err(), "accepted {}", input);
+    }
+    match from_json("[1, 2").unwrap_err().kind {
+        JsonErrorKind::EndOfInput => (),
+        kind => panic!("Wrong error: {:?}", kind),
+    }
+}
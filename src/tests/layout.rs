@@ -0,0 +1,146 @@
+#[allow(unused_imports)]
+use metainfo::{FileInfo, FileEntry, ModeInfo, MultiFileInfo, SingleFileInfo, TreeFile};
+#[allow(unused_imports)]
+use file::{Layout, FileSpan};
+#[allow(unused_imports)]
+use metainfo::MetaInfoErrorKind;
+#[cfg(test)]
+use tests::parse;
+
+#[cfg(test)]
+fn multi(piece_length: u64, files: &[(&str, u64, bool)]) -> FileInfo {
+    let entries = files.iter()
+        .map(|&(name, length, padding)| {
+            FileEntry {
+                attr: if padding { Some("p".to_string()) } else { None },
+                length: length,
+                md5sum: None,
+                path: vec![name.to_string()],
+                path_utf8: None,
+            }
+        })
+        .collect();
+    FileInfo {
+        piece_length: piece_length,
+        mode_info: ModeInfo::Multi(MultiFileInfo { files: entries }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+fn span(file: usize, name: &str, offset: u64, length: u64, block_offset: u64) -> FileSpan {
+    FileSpan {
+        file: file,
+        path: vec![name.to_string()],
+        offset: offset,
+        length: length,
+        block_offset: block_offset,
+    }
+}
+
+#[test]
+pub fn test_maps_pieces_across_files() {
+    let layout = Layout::new(&multi(16, &[("a", 10, false), ("b", 25, false), ("c", 5, false)]));
+    assert_eq!(layout.total_length(), 40);
+    assert_eq!(layout.piece_count(), 3);
+    assert_eq!(layout.piece_size(1), Some(16));
+    // the last piece is short
+    assert_eq!(layout.piece_size(2), Some(8));
+    assert_eq!(layout.piece_size(3), None);
+
+    assert_eq!(layout.map(0, 0, 16).unwrap(),
+               vec![span(0, "a", 0, 10, 0), span(1, "b", 0, 6, 10)]);
+    assert_eq!(layout.map(1, 4, 12).unwrap(), vec![span(1, "b", 10, 12, 0)]);
+    assert_eq!(layout.map(2, 0, 8).unwrap(),
+               vec![span(1, "b", 22, 3, 0), span(2, "c", 0, 5, 3)]);
+    assert_eq!(layout.map(2, 0, 9), None);
+    assert_eq!(layout.map(3, 0, 1), None);
+
+    // and back again
+    assert_eq!(layout.position(1, 0), Some((0, 10)));
+    assert_eq!(layout.position(2, 4), Some((2, 7)));
+    assert_eq!(layout.position(2, 5), None);
+    assert_eq!(layout.file_pieces(0), 0..1);
+    assert_eq!(layout.file_pieces(1), 0..3);
+    assert_eq!(layout.file_pieces(2), 2..3);
+}
+
+#[test]
+pub fn test_padding_files_are_not_mapped() {
+    let info = multi(16,
+                     &[("a", 10, false), ("pad", 6, true), ("empty", 0, false), ("b", 20, false)]);
+    let layout = Layout::new(&info);
+    assert_eq!(layout.piece_count(), 3);
+    assert!(layout.files()[1].padding);
+    assert_eq!(layout.map(0, 0, 16).unwrap(), vec![span(0, "a", 0, 10, 0)]);
+    assert_eq!(layout.map(1, 0, 16).unwrap(), vec![span(3, "b", 0, 16, 0)]);
+    assert_eq!(layout.file_pieces(2), 0..0);
+    assert_eq!(layout.file_pieces(3), 1..3);
+}
+
+#[test]
+pub fn test_single_file_and_v2_layouts() {
+    let single = FileInfo {
+        piece_length: 16,
+        name: Some("file".to_string()),
+        mode_info: ModeInfo::Single(SingleFileInfo { length: 20, ..Default::default() }),
+        pieces: vec![vec![0; 20], vec![0; 20]],
+        ..Default::default()
+    };
+    let layout = Layout::new(&single);
+    assert_eq!(layout.map(1, 0, 4).unwrap(), vec![span(0, "file", 16, 4, 0)]);
+
+    // v2 files start on piece boundaries without padding files
+    let tree_file = |name: &str, length: u64| {
+        TreeFile {
+            path: vec![name.to_string()],
+            length: length,
+            pieces_root: Some([0; 32]),
+            attr: None,
+        }
+    };
+    let v2 = FileInfo {
+        piece_length: 16,
+        meta_version: Some(2),
+        file_tree: vec![tree_file("a", 20), tree_file("b", 5)],
+        ..Default::default()
+    };
+    let layout = Layout::new(&v2);
+    assert_eq!(layout.total_length(), 37);
+    assert_eq!(layout.piece_count(), 3);
+    // the padding after a file is not part of its last piece
+    assert_eq!(layout.piece_size(0), Some(16));
+    assert_eq!(layout.piece_size(1), Some(4));
+    assert_eq!(layout.piece_size(2), Some(5));
+    assert_eq!(layout.map(1, 0, 4).unwrap(), vec![span(0, "a", 16, 4, 0)]);
+    assert_eq!(layout.map(1, 0, 16), None);
+    assert_eq!(layout.map(2, 0, 5).unwrap(), vec![span(2, "b", 0, 5, 0)]);
+    assert_eq!(layout.position(2, 1), Some((2, 1)));
+}
+
+#[test]
+pub fn test_rejects_piece_hashes_that_do_not_cover_the_files() {
+    // 20 bytes in pieces of 16 need two hashes
+    let hashes = |count: usize| vec![b'h'; 20 * count];
+    let torrent = |length: u64, pieces: &[u8]| {
+        let mut bytes = format!("d4:infod6:lengthi{}e4:name1:a12:piece lengthi16e6:pieces{}:",
+                                length,
+                                pieces.len())
+            .into_bytes();
+        bytes.extend_from_slice(pieces);
+        bytes.extend_from_slice(b"ee");
+        bytes
+    };
+    assert_eq!(parse(&torrent(20, &hashes(2))).unwrap().info.piece_count(), 2);
+    assert_eq!(parse(&torrent(32, &hashes(2))).unwrap().info.piece_count(), 2);
+    assert_eq!(parse(&torrent(0, &[])).unwrap().info.piece_count(), 0);
+    for &(length, count) in &[(20, 1), (20, 3), (16, 2), (0, 1)] {
+        match parse(&torrent(length, &hashes(count))) {
+            Err(e) => {
+                assert_eq!(e.kind(),
+                           &MetaInfoErrorKind::InvalidDataFieldValue("info.pieces".to_string()))
+            }
+            Ok(_) => panic!("Accepted {} hashes for {} bytes", count, length),
+        }
+    }
+}
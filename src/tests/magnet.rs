@@ -0,0 +1,188 @@
+#[allow(unused_imports)]
+use metainfo::{MagnetLink, MagnetError, TorrentBuilder};
+#[allow(unused_imports)]
+use wire::PeerMsg;
+#[allow(unused_imports)]
+use wire::metadata::{MetadataFetcher, MetadataError, METADATA_PIECE_SIZE, UT_METADATA_ID,
+                     fetch_metadata};
+#[allow(unused_imports)]
+use bencode::json::{hex_decode, hex_encode};
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use std::{env, fs};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::net::TcpListener;
+#[allow(unused_imports)]
+use std::thread;
+#[allow(unused_imports)]
+use std::time::Duration;
+
+const HEX_HASH: &'static str = "11f6ad8ec52a2984abaafd7c3b516503785c2072";
+const BASE32_HASH: &'static str = "CH3K3DWFFIUYJK5K7V6DWULFAN4FYIDS";
+
+#[test]
+pub fn test_parses_magnet_link() {
+    let uri = format!("magnet:?xt=urn:btih:{}&dn=Some+File%20Name&tr=http%3A%2F%2Fa%2Fannounce\
+                       &tr.1=udp://b:80&ws=http://seed/&x.pe=10.0.0.1:6881&x.pe=[::1]:51413\
+                       &so=0,2,4-6&xl=100",
+                      HEX_HASH);
+    let link = MagnetLink::try_from(uri.as_str()).unwrap();
+    assert_eq!(link.info_hash, hex_decode(HEX_HASH));
+    assert_eq!(link.info_hash_v2, None);
+    assert_eq!(link.name, Some("Some File Name".to_string()));
+    let plus = format!("magnet:?xt=urn:btih:{}&dn=C%2B%2B+Primer", HEX_HASH);
+    assert_eq!(MagnetLink::try_from(plus.as_str()).unwrap().name,
+               Some("C++ Primer".to_string()));
+    assert_eq!(link.trackers,
+               vec!["http://a/announce".to_string(), "udp://b:80".to_string()]);
+    assert_eq!(link.web_seeds, vec!["http://seed/".to_string()]);
+    assert_eq!(link.peers,
+               vec![("10.0.0.1".to_string(), 6881), ("::1".to_string(), 51413)]);
+    assert_eq!(link.select_only, vec![(0, 0), (2, 2), (4, 6)]);
+    assert!(link.selects(5) && !link.selects(3));
+    assert_eq!(link.info_hashes(), vec![hex_decode(HEX_HASH).unwrap()]);
+}
+
+#[test]
+pub fn test_parses_base32_and_v2_hashes() {
+    let base32 = MagnetLink::try_from(format!("magnet:?xt=urn:btih:{}", BASE32_HASH).as_str());
+    assert_eq!(base32.unwrap().info_hash, hex_decode(HEX_HASH));
+
+    let v2 = "magnet:?xt=urn:btmh:\
+              1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+    let link = MagnetLink::try_from(v2).unwrap();
+    assert_eq!(link.info_hash, None);
+    let hash = link.info_hash_v2.unwrap();
+    assert_eq!(hash[0], 0xca);
+    assert_eq!(hash[31], 0x2e);
+    assert_eq!(link.info_hashes(), vec![hash[..20].to_vec()]);
+}
+
+#[test]
+pub fn test_rejects_bad_magnet_links() {
+    assert_eq!(MagnetLink::try_from("http://example.com/"),
+               Err(MagnetError::NotMagnet));
+    assert_eq!(MagnetLink::try_from("magnet:?dn=name&xt=urn:ed2k:abc"),
+               Err(MagnetError::MissingHash));
+    assert_eq!(MagnetLink::try_from("magnet:?xt=urn:btih:1234"),
+               Err(MagnetError::InvalidHash("urn:btih:1234".to_string())));
+    let bad_range = format!("magnet:?xt=urn:btih:{}&so=4-2", HEX_HASH);
+    assert_eq!(MagnetLink::try_from(bad_range.as_str()),
+               Err(MagnetError::InvalidParameter("so".to_string())));
+    let bad_escape = format!("magnet:?xt=urn:btih:{}&dn=%zz", HEX_HASH);
+    assert_eq!(MagnetLink::try_from(bad_escape.as_str()),
+               Err(MagnetError::InvalidParameter("dn".to_string())));
+}
+
+#[test]
+pub fn test_makes_metainfo_from_fetched_info_dict() {
+    let path = env::temp_dir().join("rustorrent-test-magnet");
+    fs::File::create(&path).unwrap().write_all(&[7; 40000]).unwrap();
+    let metainfo = TorrentBuilder::new(&path).creation_date(0).build().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let uri = format!("magnet:?xt=urn:btih:{}&tr=http://a/announce&tr=http://b/announce",
+                      hex_encode(&metainfo.info_hash));
+    let mut link = MagnetLink::try_from(uri.as_str()).unwrap();
+    let made = link.to_metainfo(&metainfo.info_bytes).unwrap();
+    assert_eq!(made.info_hash, metainfo.info_hash);
+    assert_eq!(made.announce, Some("http://a/announce".to_string()));
+    assert_eq!(made.announce_list.len(), 2);
+    assert_eq!(made.info.pieces, metainfo.info.pieces);
+
+    // an info dict for some other torrent is not taken
+    link.info_hash = hex_decode(HEX_HASH);
+    assert!(link.to_metainfo(&metainfo.info_bytes).is_err());
+}
+
+#[cfg(test)]
+fn data_message(piece: usize, total: usize, data: &[u8]) -> Vec<u8> {
+    let mut payload = format!("d8:msg_typei1e5:piecei{}e10:total_sizei{}ee", piece, total)
+        .into_bytes();
+    payload.extend_from_slice(data);
+    payload
+}
+
+#[test]
+pub fn test_fetches_metadata_in_pieces() {
+    let metadata: Vec<u8> = (0..(METADATA_PIECE_SIZE + 100)).map(|i| i as u8).collect();
+    let mut fetcher = MetadataFetcher::new();
+    match MetadataFetcher::handshake() {
+        PeerMsg::Extended(0, ref payload) => assert_eq!(payload, b"d1:md11:ut_metadatai1eee"),
+        _ => panic!("Expected an extended handshake"),
+    }
+
+    // the peer wants its messages sent with id 3
+    let handshake = format!("d1:md11:ut_metadatai3ee13:metadata_sizei{}ee", metadata.len());
+    let requests = fetcher.on_message(0, handshake.as_bytes()).unwrap();
+    assert_eq!(requests,
+               vec![PeerMsg::Extended(3, b"d8:msg_typei0e5:piecei0ee".to_vec()),
+                    PeerMsg::Extended(3, b"d8:msg_typei0e5:piecei1ee".to_vec())]);
+
+    let total = metadata.len();
+    let second = data_message(1, total, &metadata[METADATA_PIECE_SIZE..]);
+    assert!(fetcher.on_message(UT_METADATA_ID, &second).unwrap().is_empty());
+    assert_eq!(fetcher.metadata(), None);
+    // a piece of the wrong size is refused
+    let short = data_message(0, total, &metadata[..10]);
+    match fetcher.on_message(UT_METADATA_ID, &short) {
+        Err(MetadataError::Malformed) => (),
+        _ => panic!("Accepted a short piece"),
+    }
+    let first = data_message(0, total, &metadata[..METADATA_PIECE_SIZE]);
+    fetcher.on_message(UT_METADATA_ID, &first).unwrap();
+    assert_eq!(fetcher.metadata(), Some(metadata));
+
+    // we do not serve metadata ourselves
+    let request = b"d8:msg_typei0e5:piecei0ee";
+    assert_eq!(fetcher.on_message(UT_METADATA_ID, request).unwrap(),
+               vec![PeerMsg::Extended(3, b"d8:msg_typei2e5:piecei0ee".to_vec())]);
+    match fetcher.on_message(UT_METADATA_ID, b"d8:msg_typei2e5:piecei1ee") {
+        Err(MetadataError::Rejected(1)) => (),
+        _ => panic!("Expected a rejection"),
+    }
+    match MetadataFetcher::new().on_message(0, b"d1:mdee") {
+        Err(MetadataError::NotSupported) => (),
+        _ => panic!("Expected a peer without ut_metadata to be refused"),
+    }
+}
+
+#[test]
+pub fn test_fetch_metadata_passes_over_big_messages() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hash = vec![7; 20];
+    let metadata: Vec<u8> = (0..100).collect();
+    let (peer_hash, peer_metadata) = (hash.clone(), metadata.clone());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut theirs = [0; 68];
+        stream.read_exact(&mut theirs).unwrap();
+        let mut handshake: Vec<u8> = PeerMsg::handshake("BitTorrent protocol".to_string(),
+                                                        "peer".to_string(),
+                                                        &peer_hash)
+            .into();
+        handshake[1 + 19 + 5] |= 0x10;
+        stream.write_all(&handshake).unwrap();
+        // a bitfield far bigger than any metadata message, and a keep-alive
+        let mut bitfield = vec![0, 3, 0x0d, 0x41, 5];
+        bitfield.resize(4 + 200001, 0xff);
+        stream.write_all(&bitfield).unwrap();
+        stream.write_all(&[0, 0, 0, 0]).unwrap();
+        let extended = format!("d1:md11:ut_metadatai3ee13:metadata_sizei{}ee",
+                               peer_metadata.len());
+        let data = data_message(0, peer_metadata.len(), &peer_metadata);
+        for payload in vec![extended.into_bytes(), data] {
+            let id = if payload.starts_with(b"d1:m") { 0 } else { UT_METADATA_ID };
+            let message: Vec<u8> = PeerMsg::Extended(id, payload).into();
+            stream.write_all(&message).unwrap();
+        }
+        let mut rest = Vec::new();
+        let _ = stream.read_to_end(&mut rest);
+    });
+    assert_eq!(fetch_metadata(&addr, &hash, "me", Duration::from_secs(5)).unwrap(),
+               metadata);
+}
@@ -0,0 +1,130 @@
+#[allow(unused_imports)]
+use bencode::{Bencode, DecodeErrorKind};
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use bencode::encode::belement_encode;
+#[allow(unused_imports)]
+use bencode::mapping::{FromBencode, ToBencode};
+
+bencode_dict! {
+    #[allow(dead_code)]
+    #[derive(Debug, PartialEq)]
+    struct Entry {
+        pub length: u64 => required "length",
+        pub name: Option<String> => optional "name",
+        pub path: Vec<String> => required "path",
+    }
+}
+
+bencode_dict! {
+    #[allow(dead_code)]
+    #[derive(Debug, PartialEq)]
+    struct Wrapper {
+        pub entry: Entry => required "entry",
+        pub extra: Vec<u8> => raw "extra",
+        pub flag: bool => required "flag",
+    }
+}
+
+bencode_dict! {
+    #[allow(dead_code)]
+    #[derive(Debug, PartialEq)]
+    struct Named {
+        pub name: Option<String> => lossy "name",
+        pub name_utf8: Option<String> => optional "name.utf-8",
+    }
+}
+
+#[cfg(test)]
+fn decode<T: for<'a> FromBencode<'a>>(input: &str) -> Result<T, ::bencode::DecodeError> {
+    let element = belement_decode_ref(input.as_bytes()).unwrap().0;
+    T::from_bencode(element)
+}
+
+#[test]
+pub fn test_maps_required_and_optional_fields() {
+    let entry: Entry = decode("d6:lengthi5e4:name1:a4:pathl1:b1:cee").unwrap();
+    assert_eq!(entry,
+               Entry {
+                   length: 5,
+                   name: Some("a".to_string()),
+                   path: vec!["b".to_string(), "c".to_string()],
+               });
+
+    let entry: Entry = decode("d6:lengthi5e4:pathlee").unwrap();
+    assert_eq!(entry.name, None);
+}
+
+#[test]
+pub fn test_round_trips_through_encoder() {
+    let input = "d5:entryd6:lengthi5e4:pathl1:bee5:extrali1ei2ee4:flagi1ee";
+    let wrapper: Wrapper = decode(input).unwrap();
+    assert_eq!(wrapper.extra, b"li1ei2ee".to_vec());
+    assert!(wrapper.flag);
+    assert_eq!(belement_encode(&wrapper.to_bencode()), input.as_bytes().to_vec());
+}
+
+#[test]
+pub fn test_leaves_out_missing_optional_fields() {
+    let entry = Entry {
+        length: 1,
+        name: None,
+        path: vec![],
+    };
+    assert_eq!(belement_encode(&entry.to_bencode()),
+               b"d6:lengthi1e4:pathlee".to_vec());
+}
+
+#[test]
+pub fn test_reports_missing_field() {
+    let error = decode::<Entry>("d6:lengthi5ee").unwrap_err();
+    assert_eq!(error.position, Some(0));
+    match error.kind {
+        DecodeErrorKind::MissingField(ref field) => assert_eq!(field, "path"),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_reports_field_of_wrong_type_at_its_value() {
+    let error = decode::<Entry>("d6:length1:x4:pathlee").unwrap_err();
+    assert_eq!(error.position, Some(9));
+    match error.kind {
+        DecodeErrorKind::InvalidField(ref field) => assert_eq!(field, "length"),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_rejects_integers_out_of_range() {
+    let error = decode::<Entry>("d6:lengthi-1e4:pathlee").unwrap_err();
+    match error.kind {
+        DecodeErrorKind::InvalidField(ref field) => assert_eq!(field, "length"),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_passes_nested_errors_through() {
+    let input = "d5:entryd6:lengthi5ee5:extrai0e4:flagi0ee";
+    let error = decode::<Wrapper>(input).unwrap_err();
+    assert_eq!(error.position, Some(8));
+    match error.kind {
+        DecodeErrorKind::MissingField(ref field) => assert_eq!(field, "path"),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_replaces_invalid_utf8_in_lossy_fields() {
+    let element = belement_decode_ref(b"d4:name2:a\xff10:name.utf-82:abe").unwrap().0;
+    let named = Named::from_bencode(element).unwrap();
+    assert_eq!(named,
+               Named {
+                   name: Some("a\u{fffd}".to_string()),
+                   name_utf8: Some("ab".to_string()),
+               });
+    let bytes = belement_encode(&named.to_bencode());
+    assert_eq!(bytes, "d4:name4:a\u{fffd}10:name.utf-82:abe".as_bytes());
+}
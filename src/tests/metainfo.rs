@@ -1,15 +1,19 @@
 #[allow(unused_imports)]
-use metainfo::MetaInfo;
+use metainfo::{MetaInfo, MetaInfoError, MetaInfoErrorKind, ModeInfo};
 #[allow(unused_imports)]
 use bencode::{Bencode, BDict};
 #[allow(unused_imports)]
 use bencode::decode::belement_decode;
 #[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
 use std::io::prelude::*;
 #[allow(unused_imports)]
 use std::fs::File;
 #[allow(unused_imports)]
 use convert::TryFrom;
+#[cfg(test)]
+use tests::parse;
 
 #[test]
 pub fn test_parses_torrent_metainfo_file() {
@@ -39,3 +43,135 @@ pub fn test_parses_torrent_metainfo_file_2() {
         _ => panic!("Got wrong kind of object"),
     }
 }
+
+#[cfg(test)]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+pub fn test_computes_info_hash_from_info_bytes() {
+    let mut file = File::open("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent").unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    let dict = belement_decode_ref(&bytes).unwrap().0.as_dict().unwrap();
+    assert_eq!(dict.get_span("info"), Some(176..39854));
+    let metainfo = MetaInfo::try_from(dict).ok().unwrap();
+    assert_eq!(hex(&metainfo.info_hash),
+               "c2e2a97fe8b3877ffa9f1d6b64a6a74af52004f0");
+}
+
+#[test]
+pub fn test_owned_dict_gives_same_info_hash() {
+    let mut file = File::open("src/tests/data/adventures_holmes_archive.torrent").unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    match belement_decode(&bytes).unwrap().0 {
+        Bencode::BDict(bdict) => {
+            let metainfo = MetaInfo::try_from(bdict).unwrap();
+            assert_eq!(hex(&metainfo.info_hash),
+                       "7547dcace9581078ea17ca21ebb9604a5da5b555");
+        }
+        _ => panic!("Got wrong kind of object"),
+    }
+}
+
+#[cfg(test)]
+fn parse_file(path: &str) -> MetaInfo {
+    let mut file = File::open(path).unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    let dict = belement_decode_ref(&bytes).unwrap().0.as_dict().unwrap();
+    MetaInfo::try_from(dict).unwrap()
+}
+
+#[test]
+pub fn test_reads_announce_list_and_web_seeds() {
+    let metainfo = parse_file("src/tests/data/adventures_holmes_archive.torrent");
+    assert_eq!(metainfo.announce,
+               Some("http://bt1.archive.org:6969/announce".to_string()));
+    assert_eq!(metainfo.announce_list,
+               vec![vec!["http://bt1.archive.org:6969/announce".to_string()],
+                    vec!["http://bt2.archive.org:6969/announce".to_string()]]);
+    assert_eq!(metainfo.url_list,
+               vec!["https://archive.org/download/".to_string(),
+                    "http://ia600305.us.archive.org/17/items/".to_string(),
+                    "http://ia800305.us.archive.org/17/items/".to_string()]);
+    assert_eq!(metainfo.created_by, Some("ia_make_torrent".to_string()));
+    assert_eq!(metainfo.creation_date, Some(1465304381));
+    assert_eq!(metainfo.info.name, Some("adventures_holmes".to_string()));
+    match metainfo.info.mode_info {
+        ModeInfo::Multi(ref multi) => {
+            assert_eq!(multi.files[0].length, 295730637);
+            assert_eq!(multi.files[0].path,
+                       vec!["Adventures of Sherlock Holmes_librivox.m4b".to_string()]);
+        }
+        _ => panic!("Expected a multi file torrent"),
+    }
+}
+
+#[test]
+pub fn test_reads_single_file_torrents() {
+    let metainfo = parse_file("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent");
+    assert_eq!(metainfo.created_by, Some("Transmission/2.84 (14307)".to_string()));
+    assert_eq!(metainfo.creation_date, Some(1470389858));
+    assert_eq!(metainfo.encoding, Some("UTF-8".to_string()));
+    assert_eq!(metainfo.info.private, Some(1));
+    assert!(metainfo.announce_list.is_empty());
+    match metainfo.info.mode_info {
+        ModeInfo::Single(ref single) => assert_eq!(single.length, 1037041664),
+        _ => panic!("Expected a single file torrent"),
+    }
+
+    let metainfo = parse_file("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.iso.torrent");
+    assert_eq!(metainfo.comment, Some("Ubuntu-GNOME CD cdimage.ubuntu.com".to_string()));
+    assert_eq!(metainfo.info.private, None);
+    parse_file("src/tests/data/latinmass_archive.torrent");
+}
+
+#[test]
+pub fn test_reads_optional_keys() {
+    let metainfo = parse(b"d13:announce-listll1:aelee13:creation datei4102444800e\
+                           9:httpseedsl1:he\
+                           4:infod5:filesld4:attr1:x6:lengthi0e4:pathl1:feee\
+                           12:piece lengthi16384e6:pieces0:6:source3:fooe\
+                           5:nodesll4:hosti6881eee8:url-list1:ue")
+        .unwrap();
+    assert_eq!(metainfo.announce, None);
+    assert_eq!(metainfo.announce_list, vec![vec!["a".to_string()]]);
+    assert_eq!(metainfo.creation_date, Some(4102444800));
+    assert_eq!(metainfo.httpseeds, vec!["h".to_string()]);
+    assert_eq!(metainfo.nodes, vec![("host".to_string(), 6881)]);
+    assert_eq!(metainfo.url_list, vec!["u".to_string()]);
+    assert_eq!(metainfo.info.source, Some("foo".to_string()));
+    match metainfo.info.mode_info {
+        ModeInfo::Multi(ref multi) => assert_eq!(multi.files[0].attr, Some("x".to_string())),
+        _ => panic!("Expected a multi file torrent"),
+    }
+}
+
+#[test]
+pub fn test_reports_the_offending_field() {
+    let cases: [(&[u8], MetaInfoErrorKind); 7] = [
+        (b"d8:announcei1ee",
+         MetaInfoErrorKind::FieldIsWrongType("announce".to_string())),
+        (b"d8:announce1:ae",
+         MetaInfoErrorKind::MissingField("info".to_string())),
+        (b"d4:infod6:pieces0:ee",
+         MetaInfoErrorKind::MissingField("info.piece length".to_string())),
+        (b"d4:infod12:piece lengthi0e6:pieces0:ee",
+         MetaInfoErrorKind::InvalidDataFieldValue("info.piece length".to_string())),
+        (b"d4:infod12:piece lengthi1e6:pieces3:abcee",
+         MetaInfoErrorKind::InvalidDataFieldValue("info.pieces".to_string())),
+        (b"d4:infod5:filesld6:lengthi1eee12:piece lengthi1e6:pieces0:ee",
+         MetaInfoErrorKind::MissingField("info.files.path".to_string())),
+        (b"d4:infod5:filesld6:lengthi-1e4:pathl1:aeee12:piece lengthi1e6:pieces0:ee",
+         MetaInfoErrorKind::FieldIsWrongType("info.files.length".to_string())),
+    ];
+    for &(input, ref expected) in cases.iter() {
+        match parse(input) {
+            Err(e) => assert_eq!(e.kind(), expected),
+            Ok(_) => panic!("Accepted {:?}", String::from_utf8_lossy(input)),
+        }
+    }
+}
@@ -1,10 +1,56 @@
+mod announcer;
+mod borrowed;
+mod create;
+mod decode;
+mod edit;
+mod encode;
+mod https;
+mod httpseed;
+mod json;
+mod layout;
+mod magnet;
+mod mapping;
 mod metainfo;
+mod paths;
+mod scrape;
+mod signature;
 mod peer_stream;
+mod store;
+mod stream;
+mod tiers;
+mod tracker;
+mod udptracker;
+mod v2;
+mod webseed;
 
 #[allow(unused_imports)]
 use bencode::{BString, Bencode, BInt, BList};
 #[allow(unused_imports)]
 use bencode::decode::{bint_decode, bstring_decode, bdict_decode, DecodeResult, blist_decode};
+#[allow(unused_imports)]
+use bencode::BDict;
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use bencode::encode::bdict_encode;
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, MetaInfoError};
+#[allow(unused_imports)]
+use tracker::{TrackerReq, TrackerResp, TrackerEvent, TransferCounters};
+#[allow(unused_imports)]
+use file::PartialFile;
+#[allow(unused_imports)]
+use wire::PieceStore;
+#[allow(unused_imports)]
+use std::sync::Arc;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use std::{env, fs};
+#[allow(unused_imports)]
+use std::io::Write;
+#[allow(unused_imports)]
+use std::path::PathBuf;
 
 #[test]
 pub fn test_decodes_int_0() {
@@ -76,7 +122,7 @@ pub fn test_decodes_dict() {
 #[test]
 pub fn test_decodes_dict_with_dict_field() {
     let dict =
-        bdict_decode(&"d4:listl4:worde3:cow3:moo4:spam4:eggse".to_string().into_bytes()).unwrap().0;
+        bdict_decode(&"d3:cow3:moo4:listl4:worde4:spam4:eggse".to_string().into_bytes()).unwrap().0;
     let list = dict.get("list").unwrap();
     match list {
         &Bencode::BList(ref blist) => {
@@ -149,3 +195,77 @@ fn test_decode_str(expected: &str, actual: &str) {
     assert_eq!(bstring.1, actual.len());
     assert_eq!(expected.to_string(), bstring.0.to_string().ok().unwrap());
 }
+
+// Helpers for the other test files.
+
+#[cfg(test)]
+pub fn parse(bytes: &[u8]) -> Result<MetaInfo, MetaInfoError> {
+    MetaInfo::try_from(belement_decode_ref(bytes).unwrap().0.as_dict().unwrap())
+}
+
+#[cfg(test)]
+pub fn encode(metainfo: MetaInfo) -> Vec<u8> {
+    let bdict: BDict = metainfo.into();
+    bdict_encode(&bdict)
+}
+
+// A fresh directory holding `files`, with contents made from their lengths,
+// and the data of all of them in the order given.
+#[cfg(test)]
+pub fn make_dir(name: &str, files: &[(&str, usize)]) -> (PathBuf, Vec<u8>) {
+    let dir = env::temp_dir().join(format!("rustorrent-test-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut data = Vec::new();
+    for &(path, length) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let contents: Vec<u8> = (0..length).map(|i| (i * 7 + length) as u8).collect();
+        fs::File::create(&path).unwrap().write_all(&contents).unwrap();
+        data.extend(contents);
+    }
+    (dir, data)
+}
+
+// An empty store for the torrent, with counters of its own.
+#[cfg(test)]
+pub fn piece_store(metainfo: &MetaInfo) -> PieceStore {
+    PieceStore::new(PartialFile::new(metainfo), Arc::new(TransferCounters::new(0)))
+}
+
+#[cfg(test)]
+pub fn request() -> TrackerReq {
+    TrackerReq {
+        info_hash: vec![1; 20],
+        peer_id: vec![2; 20],
+        port: 6881,
+        uploaded: 0,
+        downloaded: 0,
+        left: 10,
+        compact: true,
+        no_peer_id: false,
+        event: TrackerEvent::Started,
+        ip: None,
+        numwant: None,
+        key: None,
+        trackerid: None,
+        supportcrypto: false,
+        requirecrypto: false,
+        ipv4: None,
+        ipv6: None,
+    }
+}
+
+#[cfg(test)]
+pub fn response(interval: u32, min_interval: Option<u32>) -> TrackerResp {
+    TrackerResp {
+        failure_reason: None,
+        warning_message: None,
+        interval: Some(interval),
+        min_interval: min_interval,
+        tracker_id: None,
+        complete: None,
+        incomplete: None,
+        peers: Vec::new(),
+    }
+}
@@ -0,0 +1,194 @@
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, ModeInfo, PathPolicy, PathEntry, PathProblem, PathRewrite,
+               sanitize_component};
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use convert::TryFrom;
+
+#[cfg(test)]
+fn bytes(s: &[u8]) -> Vec<u8> {
+    let mut out = format!("{}:", s.len()).into_bytes();
+    out.extend_from_slice(s);
+    out
+}
+
+// A multi-file torrent with the given paths, each with an optional
+// `path.utf-8`.
+#[cfg(test)]
+fn torrent(name: &[u8], files: &[(&[&[u8]], Option<&[&str]>)]) -> MetaInfo {
+    let mut out = b"d4:infod5:filesl".to_vec();
+    for &(path, utf8) in files.iter() {
+        out.extend_from_slice(b"d6:lengthi1e4:pathl");
+        for name in path.iter() {
+            out.extend(bytes(name));
+        }
+        out.push(b'e');
+        if let Some(utf8) = utf8 {
+            out.extend_from_slice(b"10:path.utf-8l");
+            for name in utf8.iter() {
+                out.extend(bytes(name.as_bytes()));
+            }
+            out.push(b'e');
+        }
+        out.push(b'e');
+    }
+    out.extend_from_slice(b"e4:name");
+    out.extend(bytes(name));
+    out.extend_from_slice(b"12:piece lengthi16e6:pieces20:");
+    out.extend_from_slice(&[0; 20]);
+    out.extend_from_slice(b"ee");
+    let dict = belement_decode_ref(&out).unwrap().0.as_dict().unwrap();
+    MetaInfo::try_from(dict).unwrap()
+}
+
+#[cfg(test)]
+fn paths(metainfo: &MetaInfo) -> Vec<Vec<String>> {
+    match metainfo.info.mode_info {
+        ModeInfo::Multi(ref multi) => multi.files.iter().map(|file| file.path.clone()).collect(),
+        _ => panic!("Expected a multi-file torrent"),
+    }
+}
+
+#[test]
+pub fn test_rewrites_hostile_paths() {
+    let mut metainfo = torrent(b"dir",
+                               &[(&[b"ok", b"file.txt"], None),
+                                 (&[b"..", b"..", b"etc", b"passwd"], None),
+                                 (&[b"/etc", b"C:", b"", b"."], None),
+                                 (&[b"a\0b", b"x\\y"], None),
+                                 (&[b".."], None)]);
+    let info_hash = metainfo.info_hash.clone();
+    let rewrites = metainfo.sanitize_paths(PathPolicy::Rewrite).unwrap();
+    assert_eq!(paths(&metainfo),
+               vec![vec!["ok".to_string(), "file.txt".to_string()],
+                    vec!["etc".to_string(), "passwd".to_string()],
+                    vec!["_etc".to_string(), "C_".to_string()],
+                    vec!["a_b".to_string(), "x_y".to_string()],
+                    vec!["_".to_string()]]);
+    // the untouched file is not reported
+    let entries: Vec<PathEntry> = rewrites.iter().map(|rewrite| rewrite.entry).collect();
+    assert_eq!(entries,
+               vec![PathEntry::File(1),
+                    PathEntry::File(2),
+                    PathEntry::File(3),
+                    PathEntry::File(4)]);
+    assert_eq!(rewrites[1],
+               PathRewrite {
+                   entry: PathEntry::File(2),
+                   original: vec!["/etc".to_string(),
+                                  "C:".to_string(),
+                                  "".to_string(),
+                                  ".".to_string()],
+                   sanitized: vec!["_etc".to_string(), "C_".to_string()],
+                   problems: vec![PathProblem::Separator,
+                                  PathProblem::Character,
+                                  PathProblem::Empty,
+                                  PathProblem::Dots],
+               });
+    assert_eq!(rewrites[3].problems, vec![PathProblem::Dots, PathProblem::Empty]);
+    assert_eq!(format!("{}", rewrites[0]),
+               "info.files[1] \"../../etc/passwd\" -> \"etc/passwd\" (relative component)");
+    assert_eq!(metainfo.info_hash, info_hash);
+
+    // a second pass has nothing left to do
+    assert_eq!(metainfo.sanitize_paths(PathPolicy::Reject), Ok(Vec::new()));
+}
+
+#[test]
+pub fn test_rejects_hostile_paths() {
+    let mut metainfo = torrent(b"dir",
+                               &[(&[b"ok"], None),
+                                 (&[b"a", b"..", b"b"], None),
+                                 (&[b"/abs"], None)]);
+    let rejected = metainfo.sanitize_paths(PathPolicy::Reject).unwrap_err();
+    assert_eq!(rejected.entry, PathEntry::File(1));
+    assert_eq!(rejected.problems, vec![PathProblem::Dots]);
+    // nothing was changed
+    assert_eq!(paths(&metainfo)[1],
+               vec!["a".to_string(), "..".to_string(), "b".to_string()]);
+
+    let mut metainfo = torrent(b"..", &[(&[b"ok"], None)]);
+    let rejected = metainfo.sanitize_paths(PathPolicy::Reject).unwrap_err();
+    assert_eq!(rejected.entry, PathEntry::Name);
+    metainfo.sanitize_paths(PathPolicy::Rewrite).unwrap();
+    assert_eq!(metainfo.info.name, Some("_".to_string()));
+}
+
+#[test]
+pub fn test_prefers_utf8_paths() {
+    let mut metainfo = torrent(b"dir",
+                               &[(&[b"caf\xe9"], Some(&["caf\u{e9}"])),
+                                 (&[b"\xff\xfe"], None),
+                                 (&[b"bad\xe9"], Some(&["..", "x"]))]);
+    let rewrites = metainfo.sanitize_paths(PathPolicy::Rewrite).unwrap();
+    assert_eq!(paths(&metainfo),
+               vec![vec!["caf\u{e9}".to_string()], vec!["__".to_string()], vec!["x".to_string()]]);
+    assert_eq!(rewrites[0].problems, vec![PathProblem::Utf8]);
+    assert!(!rewrites[0].problems.iter().any(|problem| problem.is_hostile()));
+    assert_eq!(rewrites[2].problems, vec![PathProblem::Utf8, PathProblem::Dots]);
+
+    // names that only were not UTF-8 are fixed even when rejecting
+    let mut metainfo = torrent(b"dir", &[(&[b"caf\xe9"], Some(&["caf\u{e9}"]))]);
+    assert_eq!(metainfo.sanitize_paths(PathPolicy::Reject).unwrap().len(), 1);
+    assert_eq!(paths(&metainfo), vec![vec!["caf\u{e9}".to_string()]]);
+}
+
+#[test]
+pub fn test_handles_reserved_and_long_names() {
+    assert_eq!(sanitize_component("con"),
+               (Some("_con".to_string()), vec![PathProblem::Reserved]));
+    assert_eq!(sanitize_component("LPT1.txt"),
+               (Some("_LPT1.txt".to_string()), vec![PathProblem::Reserved]));
+    assert_eq!(sanitize_component("CONSOLE").1, vec![]);
+    assert_eq!(sanitize_component("name. "),
+               (Some("name".to_string()), vec![PathProblem::Character]));
+    assert_eq!(sanitize_component("what?").0, Some("what_".to_string()));
+
+    let long = format!("{}.mkv", "a".repeat(300));
+    let (name, problems) = sanitize_component(&long);
+    let name = name.unwrap();
+    assert_eq!(problems, vec![PathProblem::TooLong]);
+    assert_eq!(name.len(), 255);
+    assert!(name.ends_with("aaa.mkv"));
+
+    // never cut in the middle of a character
+    let (name, _) = sanitize_component(&"\u{e9}".repeat(200));
+    let name = name.unwrap();
+    assert_eq!(name.len(), 254);
+    assert!(name.chars().all(|c| c == '\u{e9}'));
+}
+
+#[test]
+pub fn test_renames_colliding_paths() {
+    let mut metainfo = torrent(b"dir",
+                               &[(&[b"a?"], None),
+                                 (&[b"a*"], None),
+                                 (&[b"A_"], None),
+                                 (&[b"Read.me"], None),
+                                 (&[b"read.ME"], None)]);
+    let rejected = metainfo.clone().sanitize_paths(PathPolicy::Reject).unwrap_err();
+    assert_eq!(rejected.entry, PathEntry::File(0));
+
+    let mut clean = torrent(b"dir", &[(&[b"Read.me"], None), (&[b"read.ME"], None)]);
+    let rejected = clean.sanitize_paths(PathPolicy::Reject).unwrap_err();
+    assert_eq!(rejected.entry, PathEntry::File(1));
+    assert_eq!(rejected.problems, vec![PathProblem::Collision]);
+
+    let rewrites = metainfo.sanitize_paths(PathPolicy::Rewrite).unwrap();
+    assert_eq!(paths(&metainfo),
+               vec![vec!["a_".to_string()],
+                    vec!["a_ (2)".to_string()],
+                    vec!["A_ (3)".to_string()],
+                    vec!["Read.me".to_string()],
+                    vec!["read (2).ME".to_string()]]);
+    assert_eq!(rewrites.len(), 4);
+    assert_eq!(rewrites[1].problems, vec![PathProblem::Character, PathProblem::Collision]);
+    assert_eq!(rewrites[3],
+               PathRewrite {
+                   entry: PathEntry::File(4),
+                   original: vec!["read.ME".to_string()],
+                   sanitized: vec!["read (2).ME".to_string()],
+                   problems: vec![PathProblem::Collision],
+               });
+}
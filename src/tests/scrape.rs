@@ -0,0 +1,93 @@
+#[allow(unused_imports)]
+use tracker::{HttpTrackerHandler, TrackerError, ScrapeResp, scrape_url};
+#[allow(unused_imports)]
+use tracker::http::TrackerHandler;
+#[cfg(test)]
+use tests::webseed::serve;
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use hyper::Url;
+
+#[cfg(test)]
+fn scrape_of(announce: &str) -> Option<String> {
+    scrape_url(&Url::parse(announce).unwrap()).map(|url| url.to_string())
+}
+
+#[test]
+pub fn test_scrape_url() {
+    assert_eq!(scrape_of("http://example.com/announce"),
+               Some("http://example.com/scrape".to_string()));
+    assert_eq!(scrape_of("http://example.com/x/announce.php?pk=1"),
+               Some("http://example.com/x/scrape.php?pk=1".to_string()));
+    assert_eq!(scrape_of("http://example.com/a"), None);
+    assert_eq!(scrape_of("http://example.com/announce/x"), None);
+    assert_eq!(scrape_of("http://example.com/x_announce"), None);
+}
+
+#[test]
+pub fn test_parses_scrape_response() {
+    let bytes = b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei5e10:downloadedi50e\
+                  10:incompletei10ee20:bbbbbbbbbbbbbbbbbbbbd8:completei1e10:incompletei0eee\
+                  5:flagsd20:min_request_intervali3600eee";
+    let dict = belement_decode_ref(bytes).unwrap().0.as_dict().unwrap();
+    let scrape = ScrapeResp::try_from(dict).unwrap();
+    assert_eq!(scrape.files.len(), 2);
+    assert_eq!(scrape.files[0].info_hash, b"aaaaaaaaaaaaaaaaaaaa".to_vec());
+    assert_eq!((scrape.files[0].complete, scrape.files[0].incomplete), (5, 10));
+    assert_eq!(scrape.files[0].downloaded, 50);
+    assert_eq!(scrape.files[1].downloaded, 0);
+    assert!(scrape.flags.get("min_request_interval").is_some());
+
+    let dict = belement_decode_ref(b"d5:filesd3:abcd8:completei1e10:incompletei0eeee")
+        .unwrap()
+        .0
+        .as_dict()
+        .unwrap();
+    assert!(ScrapeResp::try_from(dict).is_err());
+}
+
+#[test]
+pub fn test_http_scrape() {
+    let server = serve(|target, _| {
+        if target.contains("info_hash=%FF") {
+            return (200, Vec::new(), b"d14:failure reason7:no thise".to_vec());
+        }
+        // one entry for each hash asked for
+        let hashes = target.matches("info_hash=").count();
+        let mut body = b"d5:filesd".to_vec();
+        for i in 0..hashes {
+            body.extend_from_slice(format!("20:{:020}d8:completei{}e10:incompletei1ee", i, i)
+                .as_bytes());
+        }
+        body.extend_from_slice(b"e5:flagsd20:min_request_intervali60eee");
+        (200, Vec::new(), body)
+    });
+    let announce = Url::parse(&format!("{}/announce?pk=secret", server.url)).unwrap();
+    let handler = HttpTrackerHandler::new(announce);
+
+    let hashes: Vec<Vec<u8>> = (0..60).map(|i| vec![i; 20]).collect();
+    let scrape = handler.scrape(&hashes).unwrap();
+    assert_eq!(scrape.files.len(), 60);
+    assert_eq!(scrape.files[59].complete, 9);
+    assert!(scrape.flags.get("min_request_interval").is_some());
+    {
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("/scrape?pk=secret&info_hash=%00%00%00"));
+        assert_eq!(requests[0].matches("info_hash=").count(), 50);
+        assert!(requests[1].contains("&info_hash=%3B%3B%3B"));
+    }
+
+    match handler.scrape(&[vec![0xff; 20]]) {
+        Err(TrackerError::Failure(ref reason)) if reason == "no this" => (),
+        _ => panic!("Expected the tracker's failure"),
+    }
+    let handler = HttpTrackerHandler::new(Url::parse(&format!("{}/a", server.url)).unwrap());
+    match handler.scrape(&hashes) {
+        Err(TrackerError::NoScrape) => (),
+        _ => panic!("Expected no scrape URL"),
+    }
+}
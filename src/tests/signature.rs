@@ -0,0 +1,211 @@
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, Signature, SignaturePolicy, SignatureState, SignatureError};
+#[allow(unused_imports)]
+use rsa::{HashAlgorithm, RsaPrivateKey, RsaPublicKey};
+#[allow(unused_imports)]
+use openssl::pkey::PKey;
+#[allow(unused_imports)]
+use openssl::rsa::Rsa;
+#[allow(unused_imports)]
+use x509::{Certificate, X509Error, private_key_from_pem};
+#[allow(unused_imports)]
+use bencode::BDict;
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use bencode::encode::bdict_encode;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use std::fs::File;
+#[allow(unused_imports)]
+use std::io::Read;
+
+#[cfg(test)]
+fn read(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    File::open(format!("src/tests/data/{}", name)).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+#[cfg(test)]
+fn certificate(name: &str) -> Certificate {
+    Certificate::from_pem(&String::from_utf8(read(name)).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+fn key(name: &str) -> RsaPrivateKey {
+    private_key_from_pem(&String::from_utf8(read(name)).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+fn reparse(metainfo: MetaInfo) -> MetaInfo {
+    let bdict: BDict = metainfo.into();
+    let bytes = bdict_encode(&bdict);
+    MetaInfo::try_from(belement_decode_ref(&bytes).unwrap().0.as_dict().unwrap()).unwrap()
+}
+
+#[cfg(test)]
+fn fixture() -> MetaInfo {
+    let bytes = read("adventures_holmes_archive.torrent");
+    MetaInfo::try_from(belement_decode_ref(&bytes).unwrap().0.as_dict().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_rsa_signature_matches_openssl() {
+    let signer = key("signer.key");
+    let signature = signer.sign(HashAlgorithm::Sha1, b"hello torrent");
+    assert_eq!(signature, read("signer-hello.sig"));
+    let public_key = signer.public_key();
+    assert!(public_key.verify(HashAlgorithm::Sha1, b"hello torrent", &signature));
+    assert!(!public_key.verify(HashAlgorithm::Sha1, b"hello torrent!", &signature));
+    assert!(!public_key.verify(HashAlgorithm::Sha256, b"hello torrent", &signature));
+    let other = key("other.key").public_key();
+    assert!(!other.verify(HashAlgorithm::Sha1, b"hello torrent", &signature));
+}
+
+#[test]
+pub fn test_refuses_short_keys() {
+    let short = Rsa::generate(1024).unwrap();
+    let modulus = short.n().to_vec();
+    assert!(RsaPrivateKey::from_pkey(PKey::from_rsa(short).unwrap()).is_none());
+    assert!(RsaPublicKey::new(&modulus, &[1, 0, 1]).is_none());
+
+    let long = key("signer.key").public_key();
+    assert_eq!(RsaPublicKey::new(&long.modulus(), &[1, 0, 1]), Some(long));
+}
+
+#[test]
+pub fn test_reads_certificates() {
+    let ca = certificate("ca.pem");
+    let signer = certificate("signer.pem");
+    let other = certificate("other.pem");
+    assert_eq!(ca.subject_name(), Some("Example Build CA".to_string()));
+    assert_eq!(signer.subject_name(), Some("build-system".to_string()));
+    assert_eq!(signer.issuer_name(), Some("Example Build CA".to_string()));
+    assert_eq!(other.subject_name(), Some("someone-else".to_string()));
+
+    assert!(signer.is_issued_by(&ca));
+    assert!(ca.is_issued_by(&ca));
+    assert!(other.is_issued_by(&other));
+    assert!(!signer.is_issued_by(&other));
+    assert!(!other.is_issued_by(&ca));
+
+    // PKCS#8 and PKCS#1 keys
+    assert_eq!(&key("signer.key").public_key(), signer.public_key());
+    assert_eq!(&key("other.key").public_key(), other.public_key());
+
+    assert_eq!(Certificate::from_pem("nothing here"), Err(X509Error::Pem));
+    assert_eq!(Certificate::from_der(&signer.der()[..100]), Err(X509Error::Der));
+}
+
+#[test]
+pub fn test_signs_and_verifies_torrent() {
+    let ca = certificate("ca.pem");
+    let signer = certificate("signer.pem");
+    let mut metainfo = fixture();
+    let info_hash = metainfo.info_hash.clone();
+    assert!(metainfo.signatures.is_empty());
+
+    assert_eq!(metainfo.sign("build-system", &key("other.key"), Some(&signer)),
+               Err(SignatureError::KeyMismatch));
+    metainfo.sign("build-system", &key("signer.key"), Some(&signer)).unwrap();
+    let metainfo = reparse(metainfo);
+    assert_eq!(metainfo.info_hash, info_hash);
+    assert_eq!(metainfo.signatures["build-system"].certificate,
+               Some(signer.der().to_vec()));
+
+    let statuses = metainfo.check_signatures(SignaturePolicy::RequireTrusted, &[ca.clone()])
+        .unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].signer, "build-system");
+    assert_eq!(statuses[0].subject, Some("build-system".to_string()));
+    assert_eq!(statuses[0].state, SignatureState::Trusted);
+
+    // a valid signature nothing trusted vouches for
+    let statuses = metainfo.verify_signatures(&[certificate("other.pem")]);
+    assert_eq!(statuses[0].state, SignatureState::Untrusted);
+    assert!(metainfo.check_signatures(SignaturePolicy::RequireValid, &[]).is_ok());
+    assert_eq!(metainfo.check_signatures(SignaturePolicy::RequireTrusted, &[]),
+               Err(SignatureError::Untrusted));
+}
+
+#[test]
+pub fn test_rejects_tampered_and_unsigned_torrents() {
+    let ca = certificate("ca.pem");
+    let mut metainfo = fixture();
+    assert_eq!(metainfo.check_signatures(SignaturePolicy::RequireValid, &[]),
+               Err(SignatureError::Unsigned));
+    assert!(metainfo.check_signatures(SignaturePolicy::RejectInvalid, &[]).unwrap().is_empty());
+
+    // without a certificate the signer is looked up among the trusted ones
+    let other = certificate("other.pem");
+    metainfo.sign("someone-else", &key("other.key"), None).unwrap();
+    let statuses = metainfo.verify_signatures(&[other.clone()]);
+    assert_eq!(statuses[0].state, SignatureState::Trusted);
+    assert_eq!(metainfo.verify_signatures(&[ca.clone()])[0].state,
+               SignatureState::NoCertificate);
+    assert_eq!(metainfo.check_signatures(SignaturePolicy::RequireValid, &[ca.clone()]),
+               Err(SignatureError::Unsigned));
+
+    // any change to the info dict breaks the signature
+    let mut tampered = metainfo.clone();
+    let position = tampered.info_bytes.len() - 2;
+    tampered.info_bytes[position] ^= 1;
+    assert_eq!(tampered.verify_signatures(&[other.clone()])[0].state,
+               SignatureState::Invalid);
+    assert_eq!(tampered.check_signatures(SignaturePolicy::RejectInvalid, &[other.clone()]),
+               Err(SignatureError::Invalid("someone-else".to_string())));
+    assert!(tampered.check_signatures(SignaturePolicy::Allow, &[other.clone()]).is_ok());
+
+    // extra signed data is covered as well
+    let mut signature = metainfo.signatures["someone-else"].clone();
+    signature.info = Some(b"d4:note3:hite".to_vec());
+    metainfo.signatures.insert("someone-else".to_string(), signature);
+    let metainfo = reparse(metainfo);
+    assert_eq!(metainfo.signatures["someone-else"].info, Some(b"d4:note3:hite".to_vec()));
+    assert_eq!(metainfo.verify_signatures(&[other])[0].state, SignatureState::Invalid);
+}
+
+#[test]
+pub fn test_does_not_trust_certificates_that_may_not_sign() {
+    let other = certificate("other.pem");
+    // both are for signer.key and issued by other.pem
+    for name in &["expired.pem", "no-signing.pem"] {
+        let certificate = certificate(name);
+        assert!(certificate.is_issued_by(&other));
+        assert!(!certificate.may_sign());
+        let mut metainfo = fixture();
+        metainfo.sign("build-system", &key("signer.key"), Some(&certificate)).unwrap();
+        // the torrent is intact, the certificate only vouches for nothing
+        assert_eq!(metainfo.verify_signatures(&[other.clone()])[0].state,
+                   SignatureState::Untrusted);
+        assert!(metainfo.check_signatures(SignaturePolicy::RequireValid, &[other.clone()])
+            .is_ok());
+        assert_eq!(metainfo.check_signatures(SignaturePolicy::RequireTrusted, &[other.clone()]),
+                   Err(SignatureError::Untrusted));
+    }
+    assert!(certificate("signer.pem").may_sign());
+}
+
+#[test]
+pub fn test_trusts_only_through_ca_certificates() {
+    let ca = certificate("ca.pem");
+    let signer = certificate("signer.pem");
+    let leaf = certificate("leaf-issued.pem");
+    assert!(signer.is_trusted_by(&[ca.clone()]));
+    assert!(signer.is_trusted_by(&[signer.clone()]));
+    assert!(!signer.is_trusted_by(&[certificate("other.pem")]));
+    assert!(!signer.is_trusted_by(&[]));
+
+    // signer.pem signed leaf-issued.pem but is no CA
+    assert!(leaf.is_issued_by(&signer));
+    assert!(!leaf.is_trusted_by(&[signer.clone()]));
+    assert!(!leaf.is_trusted_by(&[ca.clone(), signer.clone()]));
+    assert!(leaf.is_trusted_by(&[leaf.clone()]));
+
+    let mut metainfo = fixture();
+    metainfo.sign("leaf-issued", &key("other.key"), Some(&leaf)).unwrap();
+    assert_eq!(metainfo.check_signatures(SignaturePolicy::RequireTrusted, &[ca, signer]),
+               Err(SignatureError::Untrusted));
+}
@@ -0,0 +1,104 @@
+#[allow(unused_imports)]
+use metainfo::TorrentBuilder;
+#[allow(unused_imports)]
+use file::{PartialFile, PartialFileTrait};
+#[allow(unused_imports)]
+use tracker::{Transfer, TransferCounters};
+#[allow(unused_imports)]
+use wire::PieceStore;
+#[allow(unused_imports)]
+use std::fs;
+#[allow(unused_imports)]
+use std::io::Read;
+#[allow(unused_imports)]
+use std::sync::Arc;
+#[cfg(test)]
+use tests::make_dir;
+
+#[test]
+pub fn test_bytes_left_leaves_out_padding() {
+    let (dir, _) = make_dir("left-hybrid", &[("a", 40000), ("b", 16384), ("c", 100)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .hybrid(true)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    let mut partial = PartialFile::new(&metainfo);
+    assert_eq!(partial.bytes_left(), 40000 + 16384 + 100);
+
+    let mut data = Vec::new();
+    fs::File::open(dir.join("a")).unwrap().read_to_end(&mut data).unwrap();
+    // the last piece of "a" is mostly padding
+    let mut tail = data[32768..].to_vec();
+    tail.resize(16384, 0);
+    assert!(partial.add_piece(2, 0, tail));
+    assert_eq!(partial.bytes_left(), 32768 + 16384 + 100);
+    // a piece that does not verify is still left
+    assert!(partial.add_piece(0, 0, vec![0; 16384]));
+    assert_eq!(partial.bytes_left(), 32768 + 16384 + 100);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_piece_store_counts_transfers() {
+    let (dir, data) = make_dir("store-counts", &[("a", 20000)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    let counters = Arc::new(TransferCounters::new(0));
+    let store = PieceStore::new(PartialFile::new(&metainfo), counters.clone());
+    assert_eq!(counters.get().left, 20000);
+    // nothing is sent of a piece we do not have
+    assert_eq!(store.send(0, 0, 16), None);
+
+    assert!(store.receive(0, 0, data[..8192].to_vec()));
+    assert!(store.receive(0, 8192, data[8192..16384].to_vec()));
+    assert!(!store.receive(1, 0, vec![0; 16384]));
+    assert!(store.has_piece(0));
+    assert_eq!(counters.get(),
+               Transfer {
+                   uploaded: 0,
+                   downloaded: 16384,
+                   left: 20000 - 16384,
+               });
+
+    assert_eq!(store.send(0, 100, 16), Some(data[100..116].to_vec()));
+    assert_eq!(counters.get().uploaded, 16);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_writes_verified_pieces_into_files() {
+    let (dir, data) = make_dir("store-writes", &[("a", 20000), ("b/c", 100)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    let out = dir.join("out");
+    let partial = PartialFile::new(&metainfo).with_directory(&out);
+    let store = PieceStore::new(partial, Arc::new(TransferCounters::new(0)));
+    // a piece that does not verify is never written
+    let mut bad = data[..16384].to_vec();
+    bad[0] ^= 1;
+    assert!(store.receive(0, 0, bad));
+    assert!(!store.has_piece(0));
+    assert!(!out.join("a").exists());
+
+    // a copy fills in the same pieces, as a web seed's does
+    let seed = store.clone();
+    assert!(seed.receive(0, 0, data[..16384].to_vec()));
+    assert!(store.has_piece(0));
+    assert!(store.receive(1, 0, data[16384..].to_vec()));
+    let mut a = Vec::new();
+    fs::File::open(out.join("a")).unwrap().read_to_end(&mut a).unwrap();
+    assert_eq!(a, &data[..20000]);
+    let mut c = Vec::new();
+    fs::File::open(out.join("b").join("c")).unwrap().read_to_end(&mut c).unwrap();
+    assert_eq!(c, &data[20000..]);
+    assert!(store.is_complete());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,143 @@
+#[allow(unused_imports)]
+use bencode::{DecodeError, DecodeErrorKind};
+#[allow(unused_imports)]
+use bencode::decode::belement_validate;
+#[allow(unused_imports)]
+use bencode::stream::{StreamDecoder, Progress};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::fs::File;
+
+#[cfg(test)]
+fn read_torrent(path: &str) -> Vec<u8> {
+    let mut file = File::open(path).unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+// Feeds `bytes` in chunks of `size` and returns the first thing that is not
+// a request for more.
+#[cfg(test)]
+fn feed(bytes: &[u8], size: usize) -> Progress {
+    let mut decoder = StreamDecoder::new();
+    for chunk in bytes.chunks(size) {
+        match decoder.push(chunk) {
+            Progress::NeedMore => (),
+            progress => return progress,
+        }
+    }
+    Progress::NeedMore
+}
+
+#[test]
+pub fn test_decodes_torrent_in_any_chunk_size() {
+    let bytes = read_torrent("src/tests/data/ubuntu-gnome-14.04.5-desktop-amd64.torrent");
+    for &size in [1, 7, 4096, bytes.len()].iter() {
+        match feed(&bytes, size) {
+            Progress::Done(value) => assert_eq!(value, bytes),
+            progress => panic!("Wrong result for chunks of {}: {:?}", size, progress),
+        }
+    }
+}
+
+#[test]
+pub fn test_asks_for_more_on_every_prefix() {
+    let bytes = b"d4:listli-12e3:abcd1:xleee5:valuei0ee";
+    for end in 0..bytes.len() {
+        match feed(&bytes[..end], 1) {
+            Progress::NeedMore => (),
+            progress => panic!("Wrong result for prefix {}: {:?}", end, progress),
+        }
+    }
+}
+
+#[test]
+pub fn test_agrees_with_decoder_on_errors() {
+    let inputs: [&[u8]; 13] = [b"i03e", b"i-0e", b"i-e", b"i1x", b"x", b"02:ab", b"2;ab",
+                               b"d1:bi0e1:ai0ee", b"d1:ai0e1:ai0ee", b"di0ei0ee", b"d1:ae",
+                               b"i99999999999999999999e", b"le"];
+    for input in inputs.iter() {
+        let expected = belement_validate(input);
+        match feed(input, 1) {
+            Progress::Error(error) => {
+                let expected = expected.unwrap_err();
+                assert_eq!(error.position, expected.position);
+                assert_eq!(format!("{:?}", error.kind), format!("{:?}", expected.kind));
+            }
+            Progress::Done(value) => assert_eq!(value.len(), expected.unwrap()),
+            Progress::NeedMore => panic!("Wanted more for {:?}", input),
+        }
+    }
+}
+
+#[test]
+pub fn test_rejects_deep_nesting() {
+    let bytes = vec![b'l'; 200];
+    match feed(&bytes, 16) {
+        Progress::Error(error) => {
+            match error.kind {
+                DecodeErrorKind::NestingTooDeep => assert_eq!(error.position, Some(128)),
+                _ => panic!("Wrong error: {}", error),
+            }
+        }
+        progress => panic!("Wrong result: {:?}", progress),
+    }
+}
+
+#[test]
+pub fn test_keeps_bytes_after_value() {
+    let mut decoder = StreamDecoder::new();
+    match decoder.push(b"i1e4:sp") {
+        Progress::Done(value) => assert_eq!(value, b"i1e".to_vec()),
+        progress => panic!("Wrong result: {:?}", progress),
+    }
+    assert_eq!(decoder.buffered(), b"4:sp");
+    match decoder.push(b"am") {
+        Progress::Done(value) => assert_eq!(value, b"4:spam".to_vec()),
+        progress => panic!("Wrong result: {:?}", progress),
+    }
+    assert!(decoder.buffered().is_empty());
+}
+
+#[test]
+pub fn test_stays_failed_after_error() {
+    let mut decoder = StreamDecoder::new();
+    assert!(match decoder.push(b"x") {
+        Progress::Error(_) => true,
+        _ => false,
+    });
+    assert!(match decoder.push(b"i1e") {
+        Progress::Error(_) => true,
+        _ => false,
+    });
+    decoder.reset();
+    assert!(match decoder.push(b"i1e") {
+        Progress::Done(_) => true,
+        _ => false,
+    });
+}
+
+#[test]
+pub fn test_fails_past_max_buffered() {
+    let mut decoder = StreamDecoder::new().with_max_buffered(8);
+    assert!(match decoder.push(b"100:abc") {
+        Progress::NeedMore => true,
+        _ => false,
+    });
+    match decoder.push(b"defgh") {
+        Progress::Error(DecodeError { kind: DecodeErrorKind::TooLarge, .. }) => (),
+        progress => panic!("Wrong result: {:?}", progress),
+    }
+    // the limit outlives a reset
+    decoder.reset();
+    assert!(match decoder.push(b"123456789") {
+        Progress::Error(_) => true,
+        _ => false,
+    });
+    assert!(match StreamDecoder::new().with_max_buffered(8).push(b"4:spam") {
+        Progress::Done(_) => true,
+        _ => false,
+    });
+}
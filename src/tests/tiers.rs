@@ -0,0 +1,126 @@
+#[allow(unused_imports)]
+use tracker::{TrackerTiers, TrackerState, TrackerResp, TrackerError};
+#[allow(unused_imports)]
+use metainfo::MetaInfo;
+#[allow(unused_imports)]
+use std::time::{Duration, Instant};
+#[allow(unused_imports)]
+use std::sync::Arc;
+#[cfg(test)]
+use tests::response;
+
+#[cfg(test)]
+fn tiers(urls: &[&[&str]]) -> TrackerTiers {
+    TrackerTiers::from_tiers(urls.iter()
+        .map(|tier| tier.iter().map(|url| url.to_string()).collect())
+        .collect())
+}
+
+#[cfg(test)]
+fn urls(tiers: &TrackerTiers) -> Vec<Vec<String>> {
+    tiers.tiers().iter().map(|tier| tier.iter().map(|t| t.url.clone()).collect()).collect()
+}
+
+#[test]
+pub fn test_tiers_from_metainfo() {
+    let mut metainfo: MetaInfo = Default::default();
+    assert!(TrackerTiers::new(&metainfo).is_empty());
+    metainfo.announce = Some("http://a".to_string());
+    assert_eq!(urls(&TrackerTiers::new(&metainfo)), vec![vec!["http://a".to_string()]]);
+
+    // the list takes over from `announce`, and only shuffles within tiers
+    let tier: Vec<String> = (0..10).map(|i| format!("http://b{}", i)).collect();
+    metainfo.announce_list = vec![tier.clone(), vec!["http://c".to_string()]];
+    let shuffled = urls(&TrackerTiers::new(&metainfo));
+    assert_eq!(shuffled.len(), 2);
+    let mut sorted = shuffled[0].clone();
+    sorted.sort();
+    assert_eq!(sorted, tier);
+    assert_eq!(shuffled[1], vec!["http://c".to_string()]);
+
+    let mut a = TrackerTiers::from_tiers(metainfo.announce_list.clone());
+    let mut b = a.clone();
+    a.shuffle(1);
+    b.shuffle(2);
+    assert!(urls(&a)[0] != urls(&b)[0]);
+}
+
+#[test]
+pub fn test_announce_fails_over_and_promotes() {
+    let mut tiers = tiers(&[&["http://a1", "http://a2", "http://a3"], &["http://b1"]]);
+    let now = Instant::now();
+    let mut asked = Vec::new();
+    let (url, _) = tiers.announce(now, |tracker| {
+            asked.push(tracker.url.clone());
+            if tracker.url == "http://a3" {
+                Ok(TrackerResp {
+                    tracker_id: Some("id-3".to_string()),
+                    ..response(1800, Some(60))
+                })
+            } else {
+                Err(TrackerError::Http("down".to_string()))
+            }
+        })
+        .unwrap();
+    assert_eq!(url, "http://a3");
+    assert_eq!(asked, vec!["http://a1", "http://a2", "http://a3"]);
+    // the one that answered is asked first from now on
+    assert_eq!(urls(&tiers)[0], vec!["http://a3", "http://a1", "http://a2"]);
+
+    let a3 = &tiers.tiers()[0][0];
+    assert_eq!(a3.last_announce, Some(now));
+    assert_eq!(a3.tracker_id, Some("id-3".to_string()));
+    assert_eq!(a3.failures, 0);
+    assert_eq!(tiers.tiers()[0][1].failures, 1);
+    assert_eq!(tiers.tiers()[0][1].last_announce, None);
+
+    // the tracker id is kept when a later response has none
+    let mut sent_id = None;
+    tiers.announce(now, |tracker| {
+            sent_id = tracker.tracker_id.clone();
+            Ok(response(1800, Some(60)))
+        })
+        .unwrap();
+    assert_eq!(sent_id, Some("id-3".to_string()));
+    assert_eq!(tiers.tiers()[0][0].tracker_id, Some("id-3".to_string()));
+}
+
+#[test]
+pub fn test_announce_falls_back_across_tiers() {
+    let mut tiers = tiers(&[&["http://a1", "http://a2"], &["http://b1", "http://b2"]]);
+    let (url, _) = tiers.announce(Instant::now(), |tracker| {
+            match tracker.url.as_str() {
+                "http://b2" => Ok(response(1800, Some(60))),
+                // a failure reason counts as a failure
+                "http://b1" => {
+                    let mut failed = response(1800, Some(60));
+                    failed.failure_reason = Some("unregistered torrent".to_string());
+                    Ok(failed)
+                }
+                _ => Err(TrackerError::Unknown),
+            }
+        })
+        .unwrap();
+    assert_eq!(url, "http://b2");
+    // tiers keep their order, only trackers within one move
+    assert_eq!(urls(&tiers),
+               vec![vec!["http://a1", "http://a2"], vec!["http://b2", "http://b1"]]);
+    assert_eq!(tiers.tiers()[1][1].failures, 1);
+
+    match tiers.announce(Instant::now(), |_| Err(TrackerError::Http("down".to_string()))) {
+        Err(TrackerError::Http(ref e)) if e == "down" => (),
+        _ => panic!("Expected every tracker to fail"),
+    }
+    assert!(tiers.tiers().iter().all(|tier| tier.iter().all(|t| t.failures > 0)));
+    assert_eq!(tiers.tiers()[0][0].failures, 2);
+}
+
+#[test]
+pub fn test_udp_trackers_keep_their_handler() {
+    let tiers = tiers(&[&["udp://t.example:6969", "http://t.example/announce"]]);
+    let udp = tiers.tiers()[0][0].udp.clone().unwrap();
+    assert!(tiers.tiers()[0][1].udp.is_none());
+    // copies of the tiers share it, and so its connection id
+    let copy = tiers.clone();
+    assert!(Arc::ptr_eq(&udp, copy.tiers()[0][0].udp.as_ref().unwrap()));
+}
@@ -0,0 +1,272 @@
+#[allow(unused_imports)]
+use tracker::{TrackerResp, TrackerReq, TrackerEvent, TrackerError, HttpTrackerHandler};
+#[allow(unused_imports)]
+use tracker::http::TrackerHandler;
+#[cfg(test)]
+use tests::webseed::serve;
+#[cfg(test)]
+use tests::request;
+#[allow(unused_imports)]
+use hyper::Url;
+#[allow(unused_imports)]
+use std::error::Error;
+#[allow(unused_imports)]
+use std::fs::File;
+#[allow(unused_imports)]
+use std::io::Read;
+#[allow(unused_imports)]
+use tracker::data::{parse_compact_peers, parse_compact_peers6, Peer};
+#[allow(unused_imports)]
+use bencode::{Bencode, BDict, DecodeErrorKind};
+#[allow(unused_imports)]
+use bencode::decode::belement_decode;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+#[allow(unused_imports)]
+use tracker::data::{url_encode, with_query};
+#[allow(unused_imports)]
+use std::str::FromStr;
+
+#[cfg(test)]
+fn decode_response(bytes: &[u8]) -> TrackerResp {
+    match belement_decode(bytes).unwrap().0 {
+        Bencode::BDict(bdict) => TrackerResp::try_from(bdict).ok().unwrap(),
+        _ => panic!("Got wrong kind of object"),
+    }
+}
+
+#[test]
+pub fn test_parses_compact_ipv4_peers() {
+    let peers = parse_compact_peers(&[10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 20, 0, 80]).unwrap();
+    assert_eq!(peers.len(), 2);
+    assert_eq!(peers[0].ip, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    assert_eq!(peers[0].port, 6881);
+    assert_eq!(peers[1].ip, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)));
+    assert_eq!(peers[1].port, 80);
+    assert!(parse_compact_peers(&[]).unwrap().is_empty());
+    assert!(parse_compact_peers(&[10, 0, 0, 1, 0x1a]).is_err());
+}
+
+#[test]
+pub fn test_parses_compact_ipv6_peers() {
+    let mut bytes = vec![0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x1a, 0xe1];
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 80]);
+    let peers = parse_compact_peers6(&bytes).unwrap();
+    assert_eq!(peers.len(), 2);
+    assert_eq!(peers[0].ip, IpAddr::from_str("2001:db8::1").unwrap());
+    assert_eq!(peers[0].port, 6881);
+    assert_eq!(peers[1].ip, IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)));
+    assert_eq!(peers[1].port, 80);
+    assert!(parse_compact_peers6(&bytes[0..12]).is_err());
+}
+
+#[test]
+pub fn test_tracker_response_merges_peers_and_peers6() {
+    let mut bytes = b"d8:intervali1800e5:peers6:".to_vec();
+    bytes.extend_from_slice(&[127, 0, 0, 1, 0x1a, 0xe1]);
+    bytes.extend_from_slice(b"6:peers618:");
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x1a, 0xe2]);
+    bytes.push(b'e');
+    let response = decode_response(&bytes);
+    assert_eq!(response.interval, Some(1800));
+    assert_eq!(response.peers.len(), 2);
+    assert_eq!(response.peers[0].ip, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+    assert_eq!(response.peers[1].ip, IpAddr::from_str("::1").unwrap());
+    assert_eq!(response.peers[1].port, 6882);
+}
+
+#[test]
+pub fn test_tracker_response_accepts_only_peers6() {
+    let mut bytes = b"d6:peers618:".to_vec();
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x1a, 0xe2]);
+    bytes.push(b'e');
+    assert_eq!(decode_response(&bytes).peers.len(), 1);
+}
+
+#[test]
+pub fn test_tracker_response_parses_dictionary_peers() {
+    let response = decode_response(b"d5:peersld2:ip9:127.0.0.17:peer id20:-RT0001-0482309842014:porti6881eeee");
+    assert_eq!(response.peers,
+               vec![Peer {
+                        peer_id: Some("-RT0001-048230984201".to_string()),
+                        ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                        port: 6881,
+                    }]);
+}
+
+#[test]
+pub fn test_tracker_response_skips_peers_named_by_host() {
+    let response = decode_response(b"d5:peersld2:ip15:tracker.example4:porti6881eed2:ip9:127.0.0.14:porti6882eeee");
+    assert_eq!(response.peers,
+               vec![Peer {
+                        peer_id: None,
+                        ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                        port: 6882,
+                    }]);
+}
+
+#[test]
+pub fn test_decoder_does_not_guess_compact_peers() {
+    // an unprefixed peer blob is malformed bencode and must be reported as such
+    let mut bytes = b"d5:peers".to_vec();
+    bytes.extend_from_slice(&[127, 0, 0, 1, 0x1a, 0xe1]);
+    bytes.push(b'e');
+    let error = belement_decode(&bytes).err().unwrap();
+    match error.kind {
+        DecodeErrorKind::UnknownType => assert_eq!(error.position, Some(8)),
+        _ => panic!("Wrong error: {}", error),
+    }
+}
+
+#[test]
+pub fn test_tracker_response_accepts_failure_without_peers() {
+    let mut bytes = Vec::new();
+    File::open("out.txt").unwrap().read_to_end(&mut bytes).unwrap();
+    let response = decode_response(&bytes);
+    assert_eq!(response.failure_reason,
+               Some("Requested download is not authorized for use with this tracker."
+                   .to_string()));
+    assert!(response.peers.is_empty());
+
+    match belement_decode(b"d8:intervali1800ee").unwrap().0 {
+        Bencode::BDict(bdict) => assert!(TrackerResp::try_from(bdict).is_err()),
+        _ => panic!("Got wrong kind of object"),
+    }
+}
+
+#[test]
+pub fn test_http_tracker_errors() {
+    let server = serve(|target, _| {
+        let path = target.split('?').next().unwrap();
+        let redirect = |to: &str| (302, vec![("Location".to_string(), to.to_string())], vec![]);
+        match path {
+            "/ok" => (200, vec![], b"d8:intervali900e5:peers6:\x7f\0\0\x01\x1a\xe1e".to_vec()),
+            "/refused" => (200, vec![], b"d14:failure reason7:go awaye".to_vec()),
+            "/refused-status" => (403, vec![], b"d14:failure reason7:go awaye".to_vec()),
+            "/warning" => (200, vec![], b"d15:warning message4:slowe".to_vec()),
+            "/garbage" => (200, vec![], b"<html>".to_vec()),
+            "/list" => (200, vec![], b"le".to_vec()),
+            "/redirect" => redirect("/ok"),
+            "/loop-a" => redirect("/loop-b"),
+            "/loop-b" => redirect("/loop-a"),
+            _ => (404, vec![], b"Not Found".to_vec()),
+        }
+    });
+    let announce = |path: &str| {
+        HttpTrackerHandler::new(Url::parse(&format!("{}{}", server.url, path)).unwrap())
+            .request(&request())
+    };
+
+    assert_eq!(announce("/ok").unwrap().interval, Some(900));
+    assert_eq!(announce("/redirect").unwrap().peers.len(), 1);
+    for path in ["/refused", "/refused-status"].iter() {
+        match announce(path) {
+            Err(TrackerError::Failure(ref reason)) if reason == "go away" => (),
+            _ => panic!("Expected the tracker's failure reason from {}", path),
+        }
+    }
+    match announce("/warning") {
+        Err(TrackerError::Warning(ref message)) if message == "slow" => (),
+        _ => panic!("Expected the tracker's warning"),
+    }
+    match announce("/garbage") {
+        Err(TrackerError::ParseError(_)) => (),
+        _ => panic!("Expected a parse error"),
+    }
+    match announce("/list") {
+        Err(TrackerError::InvalidResponse(_)) => (),
+        _ => panic!("Expected an invalid response"),
+    }
+    match announce("/missing") {
+        Err(ref e @ TrackerError::Status(404)) => {
+            assert_eq!(e.description(), "unexpected HTTP status")
+        }
+        _ => panic!("Expected the HTTP status"),
+    }
+    match announce("/loop-a") {
+        Err(TrackerError::RedirectLoop) => (),
+        _ => panic!("Expected a redirect loop"),
+    }
+}
+
+#[test]
+pub fn test_announce_query_has_every_parameter() {
+    let mut req = request();
+    req.info_hash = vec![0x0a; 20];
+    req.event = TrackerEvent::Completed;
+    req.ip = Some(IpAddr::from_str("10.0.0.1").unwrap());
+    req.numwant = Some(30);
+    req.key = Some("k 1".to_string());
+    req.trackerid = Some("id&1".to_string());
+    req.no_peer_id = true;
+    req.supportcrypto = true;
+    req.requirecrypto = true;
+    req.ipv4 = Some(Ipv4Addr::new(192, 168, 0, 2));
+    req.ipv6 = Some(Ipv6Addr::from_str("2001:db8::1").unwrap());
+    let query: Vec<String> = req.to_query_string_pairs()
+        .iter()
+        .map(|&(ref key, ref value)| format!("{}={}", key, value))
+        .collect();
+    assert_eq!(query,
+               vec![format!("info_hash={}", (0..20).map(|_| "%0A").collect::<String>()),
+                    format!("peer_id={}", (0..20).map(|_| "%02").collect::<String>()),
+                    "port=6881".to_string(),
+                    "uploaded=0".to_string(),
+                    "downloaded=0".to_string(),
+                    "left=10".to_string(),
+                    "compact=1".to_string(),
+                    "no_peer_id=1".to_string(),
+                    "event=completed".to_string(),
+                    "ip=10.0.0.1".to_string(),
+                    "numwant=30".to_string(),
+                    "key=k%201".to_string(),
+                    "trackerid=id%261".to_string(),
+                    "supportcrypto=1".to_string(),
+                    "requirecrypto=1".to_string(),
+                    "ipv4=192.168.0.2".to_string(),
+                    "ipv6=2001%3Adb8%3A%3A1".to_string()]);
+
+    // none of the optional ones when unset
+    let pairs = request().to_query_string_pairs();
+    let keys: Vec<&str> = pairs.iter().map(|&(ref key, _)| key.as_str()).collect();
+    assert_eq!(keys,
+               vec!["info_hash", "peer_id", "port", "uploaded", "downloaded", "left", "compact",
+                    "event"]);
+}
+
+#[test]
+pub fn test_announce_url_keeps_passkey() {
+    let url = request().announce_url(&Url::parse("http://t.example/ann?pk=a%2Fb").unwrap());
+    assert!(url.as_str().starts_with("http://t.example/ann?pk=a%2Fb&info_hash=%01%01"));
+    assert!(url.as_str().ends_with("&compact=1&event=started"));
+
+    let url = request().announce_url(&Url::parse("http://t.example/announce").unwrap());
+    assert!(url.as_str().starts_with("http://t.example/announce?info_hash="));
+    let url = with_query(&Url::parse("http://t.example/announce?").unwrap(),
+                         &[("a".to_string(), "1".to_string())]);
+    assert_eq!(url.as_str(), "http://t.example/announce?a=1");
+}
+
+#[test]
+pub fn test_announce_event_names() {
+    let event = |event| {
+        let mut req = request();
+        req.event = event;
+        req.to_query_string_pairs()
+            .into_iter()
+            .find(|&(ref key, _)| key == "event")
+            .map(|(_, value)| value)
+    };
+    assert_eq!(event(TrackerEvent::Started), Some("started".to_string()));
+    assert_eq!(event(TrackerEvent::Stopped), Some("stopped".to_string()));
+    assert_eq!(event(TrackerEvent::Completed), Some("completed".to_string()));
+    assert_eq!(event(TrackerEvent::Regular), None);
+}
+
+#[test]
+pub fn test_url_encode_pads_low_bytes() {
+    assert_eq!(url_encode(&[0x00, 0x01, 0x0f, 0x10, 0xff]), "%00%01%0F%10%FF");
+    assert_eq!(url_encode(b"aZ9.-_~ /"), "aZ9.-_~%20%2F");
+}
@@ -0,0 +1,202 @@
+#[allow(unused_imports)]
+use tracker::{UdpTrackerHandler, TrackerReq, TrackerEvent, TrackerError};
+#[allow(unused_imports)]
+use tracker::http::TrackerHandler;
+#[allow(unused_imports)]
+use hyper::Url;
+#[allow(unused_imports)]
+use byteorder::{ByteOrder, BigEndian};
+#[allow(unused_imports)]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+#[allow(unused_imports)]
+use std::sync::{Arc, Mutex};
+#[allow(unused_imports)]
+use std::thread;
+#[allow(unused_imports)]
+use std::time::Duration;
+#[cfg(test)]
+use tests::request;
+
+// What the stand-in tracker saw.
+#[cfg(test)]
+#[derive(Default)]
+struct Seen {
+    connects: u32,
+    announces: Vec<Vec<u8>>,
+    scrapes: u32,
+}
+
+// A UDP tracker answering on a local port until the test ends. It ignores
+// the first `drop` packets, and answers announces with an error when told
+// to.
+#[cfg(test)]
+fn tracker(bind: &str, drop: u32, error: Option<&str>) -> (Url, Arc<Mutex<Seen>>) {
+    let socket = UdpSocket::bind(bind).unwrap();
+    let address = socket.local_addr().unwrap();
+    let seen = Arc::new(Mutex::new(Seen::default()));
+    let error = error.map(|error| error.to_string());
+    let shared = seen.clone();
+    thread::spawn(move || {
+        let mut buffer = [0; 2048];
+        let mut dropped = 0;
+        loop {
+            let (length, from) = socket.recv_from(&mut buffer).unwrap();
+            if dropped < drop {
+                dropped += 1;
+                continue;
+            }
+            let request = &buffer[..length];
+            let action = BigEndian::read_u32(&request[8..12]);
+            let mut response = vec![0; 8];
+            BigEndian::write_u32(&mut response[4..8], BigEndian::read_u32(&request[12..16]));
+            let mut seen = shared.lock().unwrap();
+            match action {
+                0 => {
+                    assert_eq!(BigEndian::read_u64(&request[0..8]), 0x41727101980);
+                    seen.connects += 1;
+                    response.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 42]);
+                }
+                _ if BigEndian::read_u64(&request[0..8]) != 42 => continue,
+                1 if error.is_some() => {
+                    BigEndian::write_u32(&mut response[0..4], 3);
+                    response.extend_from_slice(error.as_ref().unwrap().as_bytes());
+                }
+                1 => {
+                    seen.announces.push(request.to_vec());
+                    BigEndian::write_u32(&mut response[0..4], 1);
+                    // interval, leechers, seeders
+                    response.extend_from_slice(&[0, 0, 7, 8, 0, 0, 0, 2, 0, 0, 0, 5]);
+                    match from {
+                        SocketAddr::V4(_) => response.extend_from_slice(&[10, 0, 0, 1, 0x1a, 0xe1]),
+                        SocketAddr::V6(_) => {
+                            response.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                                                         0, 0, 0, 0, 1, 0x1a, 0xe1])
+                        }
+                    }
+                }
+                2 => {
+                    seen.scrapes += 1;
+                    BigEndian::write_u32(&mut response[0..4], 2);
+                    for (i, _) in request[16..].chunks(20).enumerate() {
+                        let i = i as u8;
+                        response.extend_from_slice(&[0, 0, 0, i + 1, 0, 0, 0, i + 2, 0, 0, 0, i]);
+                    }
+                }
+                _ => continue,
+            }
+            socket.send_to(&response, from).unwrap();
+        }
+    });
+    let url = match address {
+        SocketAddr::V4(_) => format!("udp://{}/announce", address),
+        SocketAddr::V6(address) => format!("udp://[::1]:{}/announce", address.port()),
+    };
+    (Url::parse(&url).unwrap(), seen)
+}
+
+#[cfg(test)]
+fn udp_request() -> TrackerReq {
+    TrackerReq {
+        uploaded: 3,
+        downloaded: 4,
+        left: 5,
+        numwant: Some(50),
+        key: Some("deadbeef".to_string()),
+        ..request()
+    }
+}
+
+#[test]
+pub fn test_udp_announce() {
+    let (url, seen) = tracker("127.0.0.1:0", 0, None);
+    let handler = UdpTrackerHandler::new(url);
+    let response = handler.request(&udp_request()).unwrap();
+    assert_eq!(response.interval, Some(0x708));
+    assert_eq!(response.incomplete, Some(2));
+    assert_eq!(response.complete, Some(5));
+    assert_eq!(response.peers.len(), 1);
+    assert_eq!(response.peers[0].ip, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    assert_eq!(response.peers[0].port, 6881);
+
+    let seen = seen.lock().unwrap();
+    let announce = &seen.announces[0];
+    assert_eq!(announce.len(), 98);
+    assert_eq!(&announce[16..36], &[1; 20][..]);
+    assert_eq!(&announce[36..56], &[2; 20][..]);
+    assert_eq!(BigEndian::read_u64(&announce[56..64]), 4);
+    assert_eq!(BigEndian::read_u64(&announce[64..72]), 5);
+    assert_eq!(BigEndian::read_u64(&announce[72..80]), 3);
+    // started
+    assert_eq!(BigEndian::read_u32(&announce[80..84]), 2);
+    assert_eq!(BigEndian::read_u32(&announce[84..88]), 0);
+    assert_eq!(BigEndian::read_u32(&announce[88..92]), 0xdeadbeef);
+    assert_eq!(BigEndian::read_i32(&announce[92..96]), 50);
+    assert_eq!(BigEndian::read_u16(&announce[96..98]), 6881);
+}
+
+#[test]
+pub fn test_udp_reuses_connection_and_scrapes() {
+    let (url, seen) = tracker("127.0.0.1:0", 0, None);
+    let handler = UdpTrackerHandler::new(url);
+    handler.request(&udp_request()).unwrap();
+    let stats = handler.scrape(&[vec![1; 20], vec![3; 20]]).unwrap().files;
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[1].info_hash, vec![3; 20]);
+    assert_eq!((stats[0].complete, stats[0].downloaded, stats[0].incomplete), (1, 2, 0));
+    assert_eq!((stats[1].complete, stats[1].downloaded, stats[1].incomplete), (2, 3, 1));
+
+    // too many for one packet
+    let hashes: Vec<Vec<u8>> = (0..100).map(|i| vec![i; 20]).collect();
+    let stats = handler.scrape(&hashes).unwrap().files;
+    assert_eq!(stats.len(), 100);
+    assert_eq!(stats[80].info_hash, vec![80; 20]);
+    assert_eq!(stats[80].complete, 7);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.connects, 1);
+    assert_eq!(seen.scrapes, 3);
+}
+
+#[test]
+pub fn test_udp_retransmits_and_times_out() {
+    // the connect and the first announce are lost
+    let (url, seen) = tracker("127.0.0.1:0", 2, None);
+    let mut handler = UdpTrackerHandler::new(url);
+    handler.set_timeouts(Duration::from_millis(20), 8);
+    assert!(handler.request(&udp_request()).is_ok());
+    assert_eq!(seen.lock().unwrap().connects, 1);
+
+    let (url, _) = tracker("127.0.0.1:0", 100, None);
+    let mut handler = UdpTrackerHandler::new(url);
+    handler.set_timeouts(Duration::from_millis(5), 2);
+    match handler.request(&udp_request()) {
+        Err(TrackerError::Timeout) => (),
+        _ => panic!("Expected the tracker to time out"),
+    }
+}
+
+#[test]
+pub fn test_udp_error_reply() {
+    let (url, seen) = tracker("127.0.0.1:0", 0, Some("unregistered torrent"));
+    let handler = UdpTrackerHandler::new(url);
+    match handler.request(&udp_request()) {
+        Err(TrackerError::Failure(ref reason)) if reason == "unregistered torrent" => (),
+        _ => panic!("Expected the tracker's error"),
+    }
+    // the connection id is not trusted after an error
+    assert!(handler.request(&udp_request()).is_err());
+    assert_eq!(seen.lock().unwrap().connects, 2);
+}
+
+#[test]
+pub fn test_udp_ipv6_peers() {
+    // not every machine has IPv6
+    if UdpSocket::bind("[::1]:0").is_err() {
+        return;
+    }
+    let (url, _) = tracker("[::1]:0", 0, None);
+    let response = UdpTrackerHandler::new(url).request(&udp_request()).unwrap();
+    assert_eq!(response.peers.len(), 1);
+    assert_eq!(response.peers[0].ip,
+               IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)));
+}
@@ -0,0 +1,266 @@
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, MetaInfoError, TorrentBuilder, ModeInfo};
+#[allow(unused_imports)]
+use bencode::BDict;
+#[allow(unused_imports)]
+use bencode::borrowed::belement_decode_ref;
+#[allow(unused_imports)]
+use bencode::encode::bdict_encode;
+#[allow(unused_imports)]
+use bencode::json::hex_encode;
+#[allow(unused_imports)]
+use convert::TryFrom;
+#[allow(unused_imports)]
+use openssl::sha::{Sha256, sha256};
+#[allow(unused_imports)]
+use file::merkle::{self, BLOCK_SIZE};
+#[allow(unused_imports)]
+use file::{PartialFile, PartialFileTrait};
+#[allow(unused_imports)]
+use std::{env, fs};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::path::PathBuf;
+#[cfg(test)]
+use tests::{parse, encode, make_dir};
+
+#[cfg(test)]
+fn make_file(name: &str, length: usize) -> (PathBuf, Vec<u8>) {
+    let path = env::temp_dir().join(format!("rustorrent-test-{}", name));
+    let data: Vec<u8> = (0..length).map(|i| (i * 13 + 5) as u8).collect();
+    fs::File::create(&path).unwrap().write_all(&data).unwrap();
+    (path, data)
+}
+
+#[test]
+pub fn test_sha256_known_vector() {
+    assert_eq!(hex_encode(&sha256(b"abc")),
+               "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+
+#[test]
+pub fn test_merkle_roots() {
+    // a file of one block is its own root
+    let block = vec![1u8; 100];
+    let hashes = merkle::block_hashes(&block);
+    assert_eq!(merkle::pieces_root(&hashes, 16384), Some(sha256(&block)));
+    assert_eq!(merkle::pieces_root(&[], 16384), None);
+
+    // three blocks are padded with a zero hash to four
+    let data = vec![2u8; 2 * BLOCK_SIZE + 10];
+    let hashes = merkle::block_hashes(&data);
+    assert_eq!(hashes.len(), 3);
+    let pair = |a: &[u8], b: &[u8]| {
+        let mut hasher = Sha256::new();
+        hasher.update(a);
+        hasher.update(b);
+        hasher.finish()
+    };
+    let expected = pair(&pair(&hashes[0], &hashes[1]), &pair(&hashes[2], &[0; 32]));
+    assert_eq!(merkle::pieces_root(&hashes, 65536), Some(expected));
+    assert_eq!(merkle::padding_hash(2), pair(&[0; 32], &[0; 32]));
+
+    // a layer hashes up to the same root as the blocks do
+    let layer = merkle::piece_layer(&hashes, 16384);
+    assert_eq!(layer, hashes);
+    assert_eq!(merkle::layer_root(&layer, 16384), expected);
+}
+
+#[test]
+pub fn test_verifies_pieces_and_layers() {
+    let data: Vec<u8> = (0..(3 * 32768 + 100)).map(|i| (i % 251) as u8).collect();
+    let length = data.len() as u64;
+    let hashes = merkle::block_hashes(&data);
+    let root = merkle::pieces_root(&hashes, 32768).unwrap();
+    let layer = merkle::piece_layer(&hashes, 32768);
+    assert_eq!(layer.len(), 4);
+    assert!(merkle::verify_layer(&layer, length, 32768, &root));
+    assert!(!merkle::verify_layer(&layer[..3], length, 32768, &root));
+
+    for (index, piece) in data.chunks(32768).enumerate() {
+        assert!(merkle::verify_piece(piece, index, length, 32768, &root, Some(&layer)));
+    }
+    let mut bad = data[..32768].to_vec();
+    bad[5] ^= 1;
+    assert!(!merkle::verify_piece(&bad, 0, length, 32768, &root, Some(&layer)));
+    assert!(!merkle::verify_piece(&data[..32768], 0, length, 32768, &root, None));
+
+    // a file that fits in one piece is checked against its root
+    let small = &data[..20000];
+    let root = merkle::pieces_root(&merkle::block_hashes(small), 32768).unwrap();
+    assert!(merkle::verify_piece(small, 0, 20000, 32768, &root, None));
+    assert!(!merkle::verify_piece(&small[..19999], 0, 20000, 32768, &root, None));
+}
+
+#[test]
+pub fn test_builds_and_parses_v2_torrent() {
+    let (path, data) = make_file("v2", 5 * 16384 + 1);
+    let metainfo = TorrentBuilder::new(&path)
+        .piece_length(32768)
+        .v2(true)
+        .creation_date(0)
+        .threads(2)
+        .build()
+        .unwrap();
+    assert!(metainfo.info.pieces.is_empty());
+    assert_eq!(metainfo.info.meta_version, Some(2));
+    assert_eq!(metainfo.info.file_tree.len(), 1);
+    let root = merkle::pieces_root(&merkle::block_hashes(&data), 32768).unwrap();
+    assert_eq!(metainfo.info.file_tree[0].pieces_root, Some(root));
+    assert_eq!(metainfo.piece_layers.get(&root).unwrap().len(), 3);
+
+    let hash = metainfo.info_hash_v2.unwrap();
+    assert_eq!(metainfo.info_hash, hash[..20].to_vec());
+    let parsed = parse(&encode(metainfo.clone())).unwrap();
+    assert_eq!(parsed.info_hash_v2, Some(hash));
+    assert_eq!(parsed.info_hash, metainfo.info_hash);
+    assert_eq!(parsed.piece_layers, metainfo.piece_layers);
+    match parsed.info.mode_info {
+        ModeInfo::Single(ref single) => assert_eq!(single.length, data.len() as u64),
+        _ => panic!("Expected a single file torrent"),
+    }
+
+    // a layer that does not hash up to the root is rejected
+    let mut tampered = metainfo.clone();
+    tampered.piece_layers.get_mut(&root).unwrap()[1][0] ^= 1;
+    match parse(&encode(tampered)) {
+        Err(ref e) => assert_eq!(e.field(), "piece layers"),
+        Ok(_) => panic!("Accepted a bad piece layer"),
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+pub fn test_rejects_bad_v2_info() {
+    let bad_version = b"d4:infod9:file treede12:meta versioni3e4:name1:a12:piece lengthi16384eee";
+    match parse(bad_version) {
+        Err(ref e) => assert_eq!(e.field(), "info.meta version"),
+        Ok(_) => panic!("Accepted meta version 3"),
+    }
+    let bad_length = b"d4:infod9:file treede12:meta versioni2e4:name1:a12:piece lengthi20000eee";
+    match parse(bad_length) {
+        Err(ref e) => assert_eq!(e.field(), "info.piece length"),
+        Ok(_) => panic!("Accepted a piece length that is not a power of two"),
+    }
+    let no_files = b"d4:infod9:file treede12:meta versioni2e4:name1:a12:piece lengthi16384eee";
+    match parse(no_files) {
+        Err(ref e) => assert_eq!(e.field(), "info.file tree"),
+        Ok(_) => panic!("Accepted an empty file tree"),
+    }
+}
+
+#[test]
+pub fn test_builds_hybrid_torrent_with_padding() {
+    let (dir, _) = make_dir("hybrid", &[("a", 40000), ("b", 16384), ("c", 100)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .hybrid(true)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    assert!(metainfo.info.is_v1() && metainfo.info.is_v2());
+    let files = match metainfo.info.mode_info {
+        ModeInfo::Multi(ref multi) => multi.files.clone(),
+        _ => panic!("Expected a multi file torrent"),
+    };
+    // "a" is padded to three pieces, "b" fills its piece and "c" is last
+    let padding: Vec<(u64, bool)> = files.iter().map(|f| (f.length, f.is_padding())).collect();
+    assert_eq!(padding,
+               vec![(40000, false), (9152, true), (16384, false), (100, false)]);
+    assert_eq!(metainfo.info.pieces.len(), 5);
+    assert_eq!(metainfo.info.piece_count(), 5);
+
+    let parsed = parse(&encode(metainfo.clone())).unwrap();
+    assert_eq!(parsed.info_hash, metainfo.info_hash);
+    assert_eq!(parsed.info_hash_v2, metainfo.info_hash_v2);
+    assert_eq!(parsed.info_hashes(),
+               vec![metainfo.info_hash.clone(), metainfo.info_hash_v2_truncated().unwrap()]);
+
+    // every v1 piece, padding included, passes both hash sets
+    let mut data = Vec::new();
+    for &(name, length) in &[("a", 40000), ("b", 16384), ("c", 100)] {
+        fs::File::open(dir.join(name)).unwrap().read_to_end(&mut data).unwrap();
+        let padding = (16384 - length % 16384) % 16384;
+        if name != "c" {
+            data.extend(vec![0; padding]);
+        }
+    }
+    for (index, piece) in data.chunks(16384).enumerate() {
+        assert!(parsed.verify_piece_v1(index, piece));
+        assert!(parsed.verify_piece_v2(index, piece));
+        assert!(parsed.verify_piece(index, piece));
+    }
+    let mut bad = data[16384..32768].to_vec();
+    bad[0] ^= 1;
+    assert!(!parsed.verify_piece(1, &bad));
+    assert!(!parsed.verify_piece(5, &data[..100]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_rejects_hybrid_torrent_with_mismatched_layouts() {
+    let (dir, _) = make_dir("hybrid-bad", &[("a", 20000), ("b", 100)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .hybrid(true)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    assert!(parse(&encode(metainfo.clone())).is_ok());
+
+    // without its padding "b" no longer starts on a piece boundary, even
+    // with the hash count fitting the shorter data
+    let mut unpadded = metainfo.clone();
+    unpadded.info_bytes = Vec::new();
+    if let ModeInfo::Multi(ref mut multi) = unpadded.info.mode_info {
+        multi.files.remove(1);
+    }
+    unpadded.info.pieces.pop();
+    match parse(&encode(unpadded)) {
+        Err(ref e) => assert_eq!(e.field(), "info.files"),
+        Ok(_) => panic!("Accepted a hybrid torrent without padding"),
+    }
+
+    // the v1 list names a file the tree does not have
+    let mut renamed = metainfo.clone();
+    renamed.info_bytes = Vec::new();
+    if let ModeInfo::Multi(ref mut multi) = renamed.info.mode_info {
+        multi.files[2].path = vec!["c".to_string()];
+    }
+    match parse(&encode(renamed)) {
+        Err(ref e) => assert_eq!(e.field(), "info.file tree"),
+        Ok(_) => panic!("Accepted a hybrid torrent whose layouts disagree"),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_partial_file_completes_v2_pieces() {
+    let (dir, _) = make_dir("v2-partial", &[("a", 20000), ("b", 100)]);
+    let metainfo = TorrentBuilder::new(&dir)
+        .piece_length(16384)
+        .v2(true)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    let mut data = Vec::new();
+    fs::File::open(dir.join("a")).unwrap().read_to_end(&mut data).unwrap();
+    let mut partial = PartialFile::new(&metainfo);
+
+    assert!(partial.add_piece(0, 0, data[..16384].to_vec()));
+    assert!(partial.has_piece(0));
+    // a complete piece is not written over
+    assert!(!partial.add_piece(0, 0, vec![0; 16]));
+    assert_eq!(partial.get_piece_mut(0).get_offset(0, 16), Some(&data[..16]));
+
+    // the tail of "a" is short, and thrown away when it does not match
+    let mut bad = data[16384..].to_vec();
+    bad[0] ^= 1;
+    assert!(partial.add_piece(1, 0, bad));
+    assert!(!partial.has_piece(1));
+    assert!(partial.add_piece(1, 0, data[16384..].to_vec()));
+    assert!(partial.has_piece(1));
+    assert!(!partial.is_complete());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,229 @@
+#[allow(unused_imports)]
+use metainfo::{MetaInfo, TorrentBuilder};
+#[allow(unused_imports)]
+use seed::{Backoff, PieceSource, SeedError, Seeder, WebSeed};
+#[allow(unused_imports)]
+use file::{PartialFile, PartialFileTrait};
+#[allow(unused_imports)]
+use std::{env, fs, thread};
+#[allow(unused_imports)]
+use std::io::prelude::*;
+#[allow(unused_imports)]
+use std::io::{BufRead, BufReader};
+#[allow(unused_imports)]
+use std::net::TcpListener;
+#[allow(unused_imports)]
+use std::path::PathBuf;
+#[allow(unused_imports)]
+use std::sync::{Arc, Mutex};
+#[allow(unused_imports)]
+use std::time::{Duration, Instant};
+#[cfg(test)]
+use tests::piece_store;
+
+// What a test server answers: the status, extra headers and the body.
+#[cfg(test)]
+pub type Reply = (u16, Vec<(String, String)>, Vec<u8>);
+
+#[cfg(test)]
+pub struct TestServer {
+    pub url: String,
+    // The path and query of every request, in order.
+    pub requests: Arc<Mutex<Vec<String>>>,
+}
+
+// A local HTTP server answering each GET with `handler`, given the request's
+// target and its byte range, if any.
+#[cfg(test)]
+pub fn serve<F>(handler: F) -> TestServer
+    where F: Fn(&str, Option<(u64, u64)>) -> Reply + Send + Sync + 'static
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let handler = Arc::new(handler);
+    let log = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut target = String::new();
+            let mut range = None;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 && line.trim() != "" {
+                if line.starts_with("GET ") {
+                    target = line.split(' ').nth(1).unwrap_or("").to_string();
+                } else if line.to_lowercase().starts_with("range: bytes=") {
+                    let spec = line["range: bytes=".len()..].trim().to_string();
+                    let mut bounds = spec.split('-').map(|bound| bound.parse().unwrap());
+                    range = Some((bounds.next().unwrap(), bounds.next().unwrap()));
+                }
+                line.clear();
+            }
+            log.lock().unwrap().push(target.clone());
+            let (status, headers, body) = handler(&target, range);
+            let mut response = format!("HTTP/1.1 {} Test\r\nContent-Length: {}\r\n\
+                                        Connection: close\r\n",
+                                       status,
+                                       body.len());
+            for (name, value) in headers {
+                response.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            response.push_str("\r\n");
+            let _ = stream.write_all(response.as_bytes());
+            let _ = stream.write_all(&body);
+        }
+    });
+    TestServer {
+        url: url,
+        requests: requests,
+    }
+}
+
+// Serves files by path, honouring byte ranges when `ranges` is set.
+#[cfg(test)]
+pub fn serve_files(files: Vec<(String, Vec<u8>)>, ranges: bool) -> TestServer {
+    serve(move |target, range| {
+        match files.iter().find(|&&(ref path, _)| path == target) {
+            Some(&(_, ref data)) => {
+                match range {
+                    Some((first, last)) if ranges => {
+                        (206, Vec::new(), data[(first as usize)..(last as usize + 1)].to_vec())
+                    }
+                    _ => (200, Vec::new(), data.clone()),
+                }
+            }
+            None => (404, Vec::new(), Vec::new()),
+        }
+    })
+}
+
+// Writes the files under a fresh directory and makes a torrent of it.
+#[cfg(test)]
+pub fn make_torrent(name: &str, files: &[(&str, Vec<u8>)], hybrid: bool) -> MetaInfo {
+    let dir = env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for &(path, ref data) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(path).unwrap().write_all(data).unwrap();
+    }
+    let target = if files.len() == 1 { dir.join(files[0].0) } else { dir.clone() };
+    let metainfo = TorrentBuilder::new(&target)
+        .piece_length(16384)
+        .hybrid(hybrid)
+        .creation_date(0)
+        .build()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    metainfo
+}
+
+#[cfg(test)]
+pub fn data(length: usize, seed: usize) -> Vec<u8> {
+    (0..length).map(|i| (i * 7 + seed) as u8).collect()
+}
+
+#[test]
+pub fn test_downloads_single_file_from_web_seed() {
+    let file = data(40000, 1);
+    let metainfo =
+        make_torrent("rustorrent-test-webseed-single", &[("file.bin", file.clone())], false);
+    let server = serve_files(vec![("/file.bin".to_string(), file.clone()),
+                                  ("/mirror/file.bin".to_string(), file)],
+                             true);
+
+    // a URL naming a directory gets the torrent's name added
+    let seed = WebSeed::new(&format!("{}/mirror/", server.url), &metainfo).unwrap();
+    assert_eq!(seed.file_url(0), Some(format!("{}/mirror/file.bin", server.url).as_str()));
+
+    let partial = piece_store(&metainfo);
+    let seed = WebSeed::new(&format!("{}/file.bin", server.url), &metainfo).unwrap();
+    let mut seeder = Seeder::new(seed);
+    assert_eq!(seeder.download(&partial).unwrap(), 3);
+    assert!(partial.is_complete());
+    assert_eq!(*server.requests.lock().unwrap(), vec!["/file.bin"; 3]);
+    // nothing is left to ask for
+    assert_eq!(seeder.download(&partial).unwrap(), 0);
+}
+
+#[test]
+pub fn test_downloads_multi_file_layout_from_web_seed() {
+    let files = vec![("a", data(20000, 2)), ("sub/b c", data(30000, 3)), ("sub/d", data(10, 4))];
+    // padding files are not asked for
+    let metainfo = make_torrent("rustorrent-test-webseed-multi", &files, true);
+    let name = metainfo.info.name.clone().unwrap();
+    let served = files.iter()
+        .map(|&(path, ref data)| {
+            (format!("/mirror/{}/{}", name, path.replace(' ', "%20")), data.clone())
+        })
+        .collect();
+    // a server ignoring ranges still works
+    let server = serve_files(served, false);
+
+    let seed = WebSeed::new(&format!("{}/mirror", server.url), &metainfo).unwrap();
+    assert_eq!(seed.file_url(2),
+               Some(format!("{}/mirror/{}/sub/b%20c", server.url, name).as_str()));
+    assert_eq!(seed.file_url(1), None);
+    let partial = piece_store(&metainfo);
+    let mut seeder = Seeder::new(seed);
+    seeder.download(&partial).unwrap();
+    assert!(partial.is_complete());
+    assert_eq!(seeder.backoff().failures(), 0);
+}
+
+#[test]
+pub fn test_backs_off_from_failing_web_seeds() {
+    let file = data(20000, 5);
+    let metainfo =
+        make_torrent("rustorrent-test-webseed-bad", &[("file.bin", file.clone())], false);
+    let mut bad = file.clone();
+    bad[17000] ^= 1;
+    let server = serve_files(vec![("/file.bin".to_string(), bad)], true);
+
+    let partial = piece_store(&metainfo);
+    let mut seeder = Seeder::new(WebSeed::new(&format!("{}/file.bin", server.url), &metainfo)
+        .unwrap());
+    // the first piece is fine, the second fails its hash check
+    match seeder.download(&partial) {
+        Err(SeedError::BadPiece(1)) => (),
+        other => panic!("Expected a bad piece, got {:?}", other),
+    }
+    assert!(partial.has_piece(0) && !partial.has_piece(1));
+    assert_eq!(seeder.backoff().failures(), 1);
+    assert!(!seeder.backoff().is_ready(Instant::now()));
+
+    let mut seeder = Seeder::new(WebSeed::new(&format!("{}/gone", server.url), &metainfo)
+        .unwrap());
+    match seeder.download(&partial) {
+        Err(SeedError::Status(404)) => (),
+        other => panic!("Expected a 404, got {:?}", other),
+    }
+    assert!(WebSeed::new("ftp://mirror/file.bin", &metainfo).is_err());
+}
+
+#[test]
+pub fn test_backoff_grows_until_success() {
+    let now = Instant::now();
+    let mut backoff = Backoff::new();
+    assert!(backoff.is_ready(now));
+    backoff.fail(now, None);
+    assert!(!backoff.is_ready(now + Duration::from_secs(4)));
+    assert!(backoff.is_ready(now + Duration::from_secs(5)));
+    backoff.fail(now, None);
+    assert_eq!(backoff.until(), Some(now + Duration::from_secs(10)));
+    for _ in 0..20 {
+        backoff.fail(now, None);
+    }
+    assert_eq!(backoff.until(), Some(now + Duration::from_secs(30 * 60)));
+    // the server knows best
+    backoff.fail(now, Some(Duration::from_secs(2)));
+    assert_eq!(backoff.until(), Some(now + Duration::from_secs(2)));
+    backoff.succeed();
+    assert_eq!(backoff.failures(), 0);
+    assert!(backoff.is_ready(now));
+}
@@ -0,0 +1,206 @@
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracker::{TrackerError, TrackerEvent, TrackerResp, TrackerState, TrackerTiers};
+
+// Used until a tracker says how often it wants to hear from us.
+const DEFAULT_INTERVAL_SECONDS: u64 = 1800;
+// Failed announces are tried again after 15 * 2^(failures - 1) seconds, up
+// to half an hour.
+const BASE_BACKOFF_SECONDS: u64 = 15;
+const MAX_BACKOFF_SECONDS: u64 = 1800;
+
+// The byte counts a tracker is told about.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Transfer {
+    pub uploaded: u64,
+    pub downloaded: u64,
+    // Bytes of the pieces we do not have yet.
+    pub left: u64,
+}
+
+// Counts kept up to date by whatever moves the torrent's data, and read by
+// the announcer each time it announces.
+#[derive(Debug)]
+pub struct TransferCounters {
+    transfer: Mutex<Transfer>,
+}
+
+impl TransferCounters {
+    pub fn new(left: u64) -> TransferCounters {
+        TransferCounters {
+            transfer: Mutex::new(Transfer {
+                uploaded: 0,
+                downloaded: 0,
+                left: left,
+            }),
+        }
+    }
+
+    pub fn add_uploaded(&self, bytes: u64) {
+        self.transfer.lock().unwrap().uploaded += bytes;
+    }
+
+    pub fn add_downloaded(&self, bytes: u64) {
+        self.transfer.lock().unwrap().downloaded += bytes;
+    }
+
+    pub fn set_left(&self, bytes: u64) {
+        self.transfer.lock().unwrap().left = bytes;
+    }
+
+    pub fn get(&self) -> Transfer {
+        *self.transfer.lock().unwrap()
+    }
+}
+
+// Announces one torrent over its life: `started` first, then at the interval
+// the tracker asks for, `completed` as soon as `min interval` allows once
+// the download finishes, and `stopped` at the end. Failed announces are
+// tried again later and later.
+//
+//     while running {
+//         if announcer.is_due(Instant::now()) {
+//             let result = announcer.announce(Instant::now(), |tracker, event, transfer| {
+//                 handler_for(&tracker.url).request(&request(event, transfer))
+//             });
+//         }
+//         thread::sleep(Duration::from_secs(1));
+//     }
+//     announcer.stop(Instant::now(), ...);
+pub struct Announcer {
+    tiers: TrackerTiers,
+    counters: Arc<TransferCounters>,
+    // Whether a tracker has heard `started` and not yet `stopped`.
+    started: bool,
+    // Whether there is no `completed` to send, as it was sent already or the
+    // torrent was complete when it started.
+    completed: bool,
+    last_announce: Option<Instant>,
+    interval: Duration,
+    min_interval: Option<Duration>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Announcer {
+    pub fn new(tiers: TrackerTiers, counters: Arc<TransferCounters>) -> Announcer {
+        Announcer {
+            tiers: tiers,
+            counters: counters,
+            started: false,
+            completed: false,
+            last_announce: None,
+            interval: Duration::from_secs(DEFAULT_INTERVAL_SECONDS),
+            min_interval: None,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn tiers(&self) -> &TrackerTiers {
+        &self.tiers
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    // Failed announces in a row.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    // The event the next announce carries.
+    pub fn event(&self) -> TrackerEvent {
+        if !self.started {
+            TrackerEvent::Started
+        } else if !self.completed && self.counters.get().left == 0 {
+            TrackerEvent::Completed
+        } else {
+            TrackerEvent::Regular
+        }
+    }
+
+    // When the next announce is due, None when it is due right away.
+    pub fn due_at(&self) -> Option<Instant> {
+        if self.retry_at.is_some() {
+            return self.retry_at;
+        }
+        let last = match self.last_announce {
+            Some(last) => last,
+            None => return None,
+        };
+        match self.event() {
+            // no need to wait out the whole interval for this one
+            TrackerEvent::Completed => self.min_interval.map(|min_interval| last + min_interval),
+            _ => Some(last + self.interval),
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.due_at().map_or(true, |due| now >= due)
+    }
+
+    // Announces through the tiers, giving `request` the tracker to ask and
+    // what to tell it. Gives the URL of the tracker that answered and its
+    // response.
+    pub fn announce<F>(&mut self,
+                       now: Instant,
+                       mut request: F)
+                       -> Result<(String, TrackerResp), TrackerError>
+        where F: FnMut(&TrackerState, TrackerEvent, Transfer) -> Result<TrackerResp, TrackerError>
+    {
+        let event = self.event();
+        let transfer = self.counters.get();
+        match self.tiers.announce(now, |tracker| request(tracker, event, transfer)) {
+            Ok((url, response)) => {
+                self.last_announce = Some(now);
+                self.failures = 0;
+                self.retry_at = None;
+                self.min_interval =
+                    response.min_interval.map(|seconds| Duration::from_secs(seconds as u64));
+                let interval = response.interval
+                    .map_or(DEFAULT_INTERVAL_SECONDS, |seconds| seconds as u64);
+                self.interval = cmp::max(Duration::from_secs(interval),
+                                         self.min_interval.unwrap_or(Duration::from_secs(0)));
+                match event {
+                    TrackerEvent::Started => {
+                        self.started = true;
+                        // a torrent seeded from the start is never completed
+                        self.completed = transfer.left == 0;
+                    }
+                    TrackerEvent::Completed => self.completed = true,
+                    _ => (),
+                }
+                Ok((url, response))
+            }
+            Err(e) => {
+                self.failures += 1;
+                self.retry_at = Some(now + self.backoff());
+                Err(e)
+            }
+        }
+    }
+
+    // Tells the trackers we are leaving, if they were told we came. This is
+    // tried once, as there is no later to try again in.
+    pub fn stop<F>(&mut self, now: Instant, mut request: F) -> Result<(), TrackerError>
+        where F: FnMut(&TrackerState, TrackerEvent, Transfer) -> Result<TrackerResp, TrackerError>
+    {
+        if !self.started {
+            return Ok(());
+        }
+        self.started = false;
+        let transfer = self.counters.get();
+        self.tiers
+            .announce(now, |tracker| request(tracker, TrackerEvent::Stopped, transfer))
+            .map(|_| ())
+    }
+
+    // How long to wait after the latest of the failures in a row.
+    fn backoff(&self) -> Duration {
+        let doublings = cmp::min(self.failures.saturating_sub(1), 16);
+        Duration::from_secs(cmp::min(BASE_BACKOFF_SECONDS << doublings, MAX_BACKOFF_SECONDS))
+    }
+}
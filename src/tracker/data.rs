@@ -1,10 +1,16 @@
 use std::net::IpAddr;
 use convert::TryFrom;
 use metainfo::SHA1Hash20b;
-use bencode::{Bencode, BDict, BString, BInt, BList, DecodeError, DecodeErrorKind};
+use bencode::{Bencode, BString, BList, BDict, DecodeError, DecodeErrorKind};
+use bencode::mapping::{FromBencode, ToBencode};
+use bencode::borrowed::{BencodeRef, BDictRef, belement_decode_ref};
+use bencode::decode::DecodeResult;
+use bencode::encode::bdict_encode;
 use std::str::FromStr;
 use std::string::ToString;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use byteorder::{ByteOrder, BigEndian};
+use hyper::Url;
 
 pub struct TrackerReq {
     pub info_hash: SHA1Hash20b,
@@ -20,11 +26,16 @@ pub struct TrackerReq {
     pub numwant: Option<u32>,
     pub key: Option<String>,
     pub trackerid: Option<String>,
+    pub supportcrypto: bool,
+    pub requirecrypto: bool,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
 }
 fn url_encode_str(string: &str) -> String {
     url_encode(&string.to_string().into_bytes())
 }
-fn url_encode(bytes: &[u8]) -> String {
+// Percent-encodes everything but RFC 3986 unreserved characters.
+pub fn url_encode(bytes: &[u8]) -> String {
     let mut string = String::new();
     for &byte in bytes {
         if ('a' as u8 <= byte && byte <= 'z' as u8) || ('A' as u8 <= byte && byte <= 'Z' as u8) ||
@@ -32,22 +43,16 @@ fn url_encode(bytes: &[u8]) -> String {
            byte == '-' as u8 || byte == '_' as u8 || byte == '~' as u8 {
             string.push(byte as char);
         } else {
-            string.push_str(&format!("%{:X}", byte));
+            string.push_str(&format!("%{:02X}", byte));
         }
     }
     string
 }
 
-fn convert_hash(hash: &SHA1Hash20b) -> String {
-    let mut out = String::new();
-    for b in hash.iter() {
-        let ittle_bit = format!("{:02X}", b);
-        out.push_str(&ittle_bit);
-    }
-    out
-}
-
 impl TrackerReq {
+    // Every parameter of the announce in the order BEP 3 lists them, each
+    // value already percent-encoded. Optional ones are left out when unset,
+    // and flags when off.
     pub fn to_query_string_pairs(&self) -> Vec<(String, String)> {
         let mut pairs: Vec<(String, String)> = Vec::new();
         pairs.push(("info_hash".to_string(), url_encode(&self.info_hash)));
@@ -56,34 +61,59 @@ impl TrackerReq {
         pairs.push(("uploaded".to_string(), self.uploaded.to_string()));
         pairs.push(("downloaded".to_string(), self.downloaded.to_string()));
         pairs.push(("left".to_string(), self.left.to_string()));
-        pairs.push(("compact".to_string(),
-                    (if self.compact {
-                1
-            } else {
-                0
-            })
-            .to_string()));
-        pairs.push(("no_peer_id".to_string(),
-                    (if self.no_peer_id {
-                1
-            } else {
-                0
-            })
-            .to_string()));
-        pairs.push(("event".to_string(), self.event.to_string()));
-
-        self.ip
-            .iter()
-            .map(|ip_addr| pairs.push(("ip".to_string(), url_encode_str(&ip_addr.to_string()))));
-        self.numwant.iter().map(|numwant| {
-            pairs.push(("numwant".to_string(), url_encode_str(&numwant.to_string())))
-        });
-        self.key
-            .iter()
-            .map(|key| pairs.push(("key".to_string(), url_encode_str(&key.to_string()))));
-
+        pairs.push(("compact".to_string(), (if self.compact { "1" } else { "0" }).to_string()));
+        if self.no_peer_id {
+            pairs.push(("no_peer_id".to_string(), "1".to_string()));
+        }
+        if self.event != TrackerEvent::Regular {
+            pairs.push(("event".to_string(), self.event.to_string()));
+        }
+        if let Some(ip) = self.ip {
+            pairs.push(("ip".to_string(), url_encode_str(&ip.to_string())));
+        }
+        if let Some(numwant) = self.numwant {
+            pairs.push(("numwant".to_string(), numwant.to_string()));
+        }
+        if let Some(ref key) = self.key {
+            pairs.push(("key".to_string(), url_encode_str(key)));
+        }
+        if let Some(ref trackerid) = self.trackerid {
+            pairs.push(("trackerid".to_string(), url_encode_str(trackerid)));
+        }
+        if self.supportcrypto {
+            pairs.push(("supportcrypto".to_string(), "1".to_string()));
+        }
+        if self.requirecrypto {
+            pairs.push(("requirecrypto".to_string(), "1".to_string()));
+        }
+        // BEP 7, the addresses a multihomed client can also be reached on
+        if let Some(ipv4) = self.ipv4 {
+            pairs.push(("ipv4".to_string(), url_encode_str(&ipv4.to_string())));
+        }
+        if let Some(ipv6) = self.ipv6 {
+            pairs.push(("ipv6".to_string(), url_encode_str(&ipv6.to_string())));
+        }
         pairs
     }
+
+    // The URL to announce to. The parameters go after any query the announce
+    // URL has already, which private trackers keep a passkey in.
+    pub fn announce_url(&self, announce: &Url) -> Url {
+        with_query(announce, &self.to_query_string_pairs())
+    }
+}
+
+// `url` with the pairs, already percent-encoded, added to its query.
+pub fn with_query(url: &Url, pairs: &[(String, String)]) -> Url {
+    let mut query: Vec<String> = url.query()
+        .iter()
+        .filter(|query| !query.is_empty())
+        .map(|query| query.to_string())
+        .collect();
+    query.extend(pairs.iter().map(|&(ref key, ref value)| format!("{}={}", key, value)));
+    let mut url = url.clone();
+    url.set_query(Some(&query.join("&")));
+    url
 }
 
 fn missing_field(fld: &str) -> DecodeError {
@@ -105,86 +135,150 @@ impl TryFrom<BDict> for TrackerReq {
     }
 }
 
-impl TryFrom<BDict> for TrackerResp {
-    type Err = DecodeError;
-    fn try_from(dict: BDict) -> Result<Self, Self::Err> {
-        // required fields
-        let interval: Option<BInt> = dict.get_copy("interval");
-        let tracker_id = dict.get_copy("tracker id");
-        // .ok_or(missing_field("tracker id")));
-        let complete: Option<BInt> = dict.get_copy("complete");
-
-        // optional fields
-        let failure_reason: Option<String> = dict.get_copy("failure reason");
-        let warning_message: Option<String> = dict.get_copy("warning message");
-        let min_interval: Option<BInt> = dict.get_copy("min interval");
-
-        // parse the peer list
-        let peers_list = match dict.get("peers") {
-            Some(&Bencode::BList(ref blist_peers)) => {
-                let mut peers_list = Vec::new();
-                let blist: Vec<BDict> = match Vec::try_from(Bencode::BList(blist_peers.clone())) {
-                    Ok(x) => x,
-                    Err(_) => return Err(missing_field("peers")),
-                };
-                for peer in blist {
-                    let peer_id: Option<String> = peer.get_copy("peer id");
-                    let peer_ip: String = try!(peer.get_copy("ip").ok_or(missing_field("ip")));
-                    let peer_port: BInt = try!(peer.get_copy("port").ok_or(missing_field("port")));
-                    let ip = try!(IpAddr::from_str(&peer_ip).map_err(|_| missing_field("ip")));
-
-                    peers_list.push(Peer {
-                        peer_id: peer_id,
+bencode_dict! {
+    // An HTTP tracker's answer to an announce, before its peer lists are put
+    // together.
+    struct AnnounceDict {
+        pub complete: Option<u32> => optional "complete",
+        pub failure_reason: Option<String> => lossy "failure reason",
+        pub incomplete: Option<u32> => optional "incomplete",
+        pub interval: Option<u32> => optional "interval",
+        pub min_interval: Option<u32> => optional "min interval",
+        pub peers: Option<PeerList> => optional "peers",
+        pub peers6: Option<CompactPeers6> => optional "peers6",
+        pub tracker_id: Option<String> => lossy "tracker id",
+        pub warning_message: Option<String> => lossy "warning message",
+    }
+}
+
+bencode_dict! {
+    // A peer in the long form of the peers list. Peer ids are raw bytes.
+    struct PeerDict {
+        pub ip: String => required "ip",
+        pub peer_id: Option<String> => lossy "peer id",
+        pub port: u16 => required "port",
+    }
+}
+
+// The peers list, either as dicts or in the compact form.
+struct PeerList(Vec<Peer>);
+
+impl<'a> FromBencode<'a> for PeerList {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<PeerList, DecodeError> {
+        match element {
+            BencodeRef::BList(list) => {
+                let mut peers = Vec::new();
+                for peer in list.iter() {
+                    let peer = try!(PeerDict::from_bencode(peer));
+                    // BEP 3 allows a DNS name here, which is not looked up, so
+                    // such a peer is left out rather than the whole response
+                    let ip = match IpAddr::from_str(&peer.ip) {
+                        Ok(ip) => ip,
+                        Err(_) => continue,
+                    };
+                    peers.push(Peer {
+                        peer_id: peer.peer_id,
                         ip: ip,
-                        port: peer_port.to_i64() as u16,
+                        port: peer.port,
                     });
                 }
-                peers_list
+                Ok(PeerList(peers))
             }
-            Some(&Bencode::BString(ref bsp)) => {
-                let bstring_peers = bsp.to_bytes();
-                let total = bstring_peers.len();
-                let mut peers_list = Vec::new();
-                if total % 6 == 0 {
-                    let peer_count = total / 6;
-                    for i in 0..peer_count {
-                        let ip = IpAddr::V4(Ipv4Addr::new(bstring_peers[i],
-                                                          bstring_peers[i + 1],
-                                                          bstring_peers[i + 2],
-                                                          bstring_peers[i + 3]));
-
-                        peers_list.push(Peer {
-                            peer_id: None,
-                            ip: ip,
-                            port: ((bstring_peers[i + 4] as u16) << 8) +
-                                  (bstring_peers[i + 5] as u16),
-                        });
+            BencodeRef::BString(bytes) => parse_compact_peers(bytes).map(PeerList),
+            _ => Err(missing_field("peers")),
+        }
+    }
+}
+
+impl ToBencode for PeerList {
+    fn to_bencode(&self) -> Bencode {
+        let peers: Vec<Bencode> = self.0
+            .iter()
+            .map(|peer| {
+                PeerDict {
+                        ip: peer.ip.to_string(),
+                        peer_id: peer.peer_id.clone(),
+                        port: peer.port,
                     }
-                } else {
-                    return Err(missing_field("peers"));
-                }
-                peers_list
+                    .to_bencode()
+            })
+            .collect();
+        Bencode::BList(BList::from(peers))
+    }
+}
+
+// BEP 7 peers, only ever compact.
+struct CompactPeers6(Vec<Peer>);
+
+impl<'a> FromBencode<'a> for CompactPeers6 {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<CompactPeers6, DecodeError> {
+        let bytes = try!(element.as_bytes().ok_or(missing_field("peers6")));
+        parse_compact_peers6(bytes).map(CompactPeers6)
+    }
+}
+
+impl ToBencode for CompactPeers6 {
+    fn to_bencode(&self) -> Bencode {
+        let mut bytes = Vec::new();
+        for peer in self.0.iter() {
+            if let IpAddr::V6(ip) = peer.ip {
+                bytes.extend_from_slice(&ip.octets());
+                let mut port = [0; 2];
+                BigEndian::write_u16(&mut port, peer.port);
+                bytes.extend_from_slice(&port);
             }
-            _ => return Err(missing_field("peers")),
-        };
+        }
+        Bencode::BString(BString::new(&bytes))
+    }
+}
 
-        // piece it together
+impl<'a> TryFrom<BDictRef<'a>> for TrackerResp {
+    type Err = DecodeError;
+    fn try_from(dict: BDictRef<'a>) -> Result<Self, Self::Err> {
+        let announce = try!(AnnounceDict::from_bencode(BencodeRef::BDict(dict)));
+        // a refusal comes without peers
+        if announce.peers.is_none() && announce.peers6.is_none() &&
+           announce.failure_reason.is_none() {
+            return Err(missing_field("peers"));
+        }
+        let mut peers = announce.peers.map_or(Vec::new(), |peers| peers.0);
+        peers.extend(announce.peers6.map_or(Vec::new(), |peers| peers.0));
         Ok(TrackerResp {
-            failure_reason: failure_reason,
-            warning_message: warning_message,
-            interval: interval.map(|i| i.to_i64() as u32),
-            min_interval: min_interval.map(|i| i.to_i64() as u32),
-            tracker_id: tracker_id,
-            complete: complete.map(|c| c.to_i64() as u32),
-            peers: peers_list,
+            failure_reason: announce.failure_reason,
+            warning_message: announce.warning_message,
+            interval: announce.interval,
+            min_interval: announce.min_interval,
+            tracker_id: announce.tracker_id,
+            complete: announce.complete,
+            incomplete: announce.incomplete,
+            peers: peers,
         })
     }
 }
 
+impl TryFrom<BDict> for TrackerResp {
+    type Err = DecodeError;
+    fn try_from(bdict: BDict) -> Result<Self, Self::Err> {
+        let bytes = bdict_encode(&bdict);
+        match try!(belement_decode_ref(&bytes)) {
+            DecodeResult(BencodeRef::BDict(dict), _) => TrackerResp::try_from(dict),
+            _ => {
+                Err(DecodeError {
+                    position: None,
+                    kind: DecodeErrorKind::ConversionError,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TrackerEvent {
     Started,
     Stopped,
     Completed,
+    // One of the announces at the tracker's interval, which has no event.
+    Regular,
 }
 
 impl ToString for TrackerEvent {
@@ -192,22 +286,157 @@ impl ToString for TrackerEvent {
         (match self {
                 &TrackerEvent::Started => "started",
                 &TrackerEvent::Stopped => "stopped",
-                &TrackerEvent::Completed => "complete",
+                &TrackerEvent::Completed => "completed",
+                &TrackerEvent::Regular => "",
             })
             .to_string()
     }
 }
 
+// One torrent's swarm as a scrape gives it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrapeStats {
+    pub info_hash: SHA1Hash20b,
+    // Seeders.
+    pub complete: u32,
+    // Leechers.
+    pub incomplete: u32,
+    // Downloads the tracker saw finish.
+    pub downloaded: u32,
+}
+
+// What a scrape gives for the torrents the tracker knows of, which may be
+// fewer than were asked for. `flags` is as the tracker sent it, BEP 48 has
+// `min_request_interval` in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrapeResp {
+    pub files: Vec<ScrapeStats>,
+    pub flags: BDict,
+}
+
+bencode_dict! {
+    struct ScrapeDict {
+        pub files: ScrapeFiles => required "files",
+        pub flags: Option<BDict> => optional "flags",
+    }
+}
+
+bencode_dict! {
+    // What a scrape says of one torrent.
+    struct ScrapeCounts {
+        pub complete: u32 => required "complete",
+        pub downloaded: Option<u32> => optional "downloaded",
+        pub incomplete: u32 => required "incomplete",
+    }
+}
+
+// The counts keyed by the raw info-hashes.
+struct ScrapeFiles(Vec<ScrapeStats>);
+
+impl<'a> FromBencode<'a> for ScrapeFiles {
+    fn from_bencode(element: BencodeRef<'a>) -> Result<ScrapeFiles, DecodeError> {
+        let files = try!(element.as_dict().ok_or(missing_field("files")));
+        let mut stats = Vec::new();
+        for (info_hash, counts) in files.iter() {
+            if info_hash.len() != 20 {
+                return Err(missing_field("files"));
+            }
+            let counts = try!(ScrapeCounts::from_bencode(counts));
+            stats.push(ScrapeStats {
+                info_hash: info_hash.to_vec(),
+                complete: counts.complete,
+                incomplete: counts.incomplete,
+                downloaded: counts.downloaded.unwrap_or(0),
+            });
+        }
+        Ok(ScrapeFiles(stats))
+    }
+}
+
+impl ToBencode for ScrapeFiles {
+    fn to_bencode(&self) -> Bencode {
+        let mut files = BDict::new();
+        for stats in self.0.iter() {
+            let counts = ScrapeCounts {
+                complete: stats.complete,
+                downloaded: Some(stats.downloaded),
+                incomplete: stats.incomplete,
+            };
+            files.insert_bytes(&stats.info_hash, counts.to_bencode());
+        }
+        Bencode::BDict(files)
+    }
+}
+
+impl<'a> TryFrom<BDictRef<'a>> for ScrapeResp {
+    type Err = DecodeError;
+    fn try_from(dict: BDictRef<'a>) -> Result<Self, Self::Err> {
+        let scrape = try!(ScrapeDict::from_bencode(BencodeRef::BDict(dict)));
+        Ok(ScrapeResp {
+            files: scrape.files.0,
+            flags: scrape.flags.unwrap_or(BDict::new()),
+        })
+    }
+}
+
 pub struct TrackerResp {
     pub failure_reason: Option<String>,
     pub warning_message: Option<String>,
     pub interval: Option<u32>,
     pub min_interval: Option<u32>,
     pub tracker_id: Option<String>,
+    // Seeders and leechers.
     pub complete: Option<u32>,
+    pub incomplete: Option<u32>,
     pub peers: Vec<Peer>,
 }
 
+// BEP 23 compact form, 4 bytes of IPv4 address then 2 bytes of port
+pub fn parse_compact_peers(bytes: &[u8]) -> Result<Vec<Peer>, DecodeError> {
+    const PEER_LEN: usize = 6;
+    if bytes.len() % PEER_LEN != 0 {
+        return Err(missing_field("peers"));
+    }
+    Ok(bytes.chunks(PEER_LEN)
+        .map(|peer| {
+            Peer {
+                peer_id: None,
+                ip: IpAddr::V4(Ipv4Addr::new(peer[0], peer[1], peer[2], peer[3])),
+                port: BigEndian::read_u16(&peer[4..6]),
+            }
+        })
+        .collect())
+}
+
+// BEP 7 compact form, 16 bytes of IPv6 address then 2 bytes of port
+pub fn parse_compact_peers6(bytes: &[u8]) -> Result<Vec<Peer>, DecodeError> {
+    const PEER_LEN: usize = 18;
+    if bytes.len() % PEER_LEN != 0 {
+        return Err(missing_field("peers6"));
+    }
+    Ok(bytes.chunks(PEER_LEN)
+        .map(|peer| {
+            let mut segments = [0u16; 8];
+            for (i, segment) in segments.iter_mut().enumerate() {
+                *segment = BigEndian::read_u16(&peer[(i * 2)..(i * 2 + 2)]);
+            }
+            Peer {
+                peer_id: None,
+                ip: IpAddr::V6(Ipv6Addr::new(segments[0],
+                                             segments[1],
+                                             segments[2],
+                                             segments[3],
+                                             segments[4],
+                                             segments[5],
+                                             segments[6],
+                                             segments[7])),
+                port: BigEndian::read_u16(&peer[16..18]),
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Peer {
     pub peer_id: Option<String>,
     pub ip: IpAddr,
@@ -1,43 +1,139 @@
-use tracker::data::{TrackerReq, TrackerResp};
-use bencode::Bencode;
-use bencode::decode::{belement_decode, DecodeResult};
-use bencode::BDict;
+use tracker::data::{TrackerReq, TrackerResp, ScrapeResp, url_encode, with_query};
+use metainfo::SHA1Hash20b;
+use bencode::borrowed::{BencodeRef, BDictRef, belement_decode_ref};
 use hyper::Url;
 use hyper::client::Request;
-use hyper::client::Client;
+use hyper::client::{Client, RedirectPolicy, Response};
 use hyper::net::HttpStream;
 use std::error::Error;
 use std::fmt;
-use std::io::Read;
+use std::io::{self, Read};
+use std::time::Duration;
 use convert::TryFrom;
-use bencode::DecodeError;
+use bencode::{BDict, DecodeError, DecodeErrorKind};
+use hyper;
+use bencode::decode::DecodeResult;
+use bencode::stream::{StreamDecoder, Progress};
 use std::fs::File;
 use std::io::Write;
+use https;
 
 pub trait TrackerHandler {
     fn request(self: &Self, req: &TrackerReq) -> Result<TrackerResp, TrackerError>;
+    // Swarm counts for several torrents at once, without announcing.
+    fn scrape(self: &Self, hashes: &[SHA1Hash20b]) -> Result<ScrapeResp, TrackerError>;
 }
 
+// Trackers and the proxies in front of them cut off URLs much longer than
+// this many info-hashes make, so bigger scrapes are sent in parts.
+const SCRAPE_HASHES_PER_REQUEST: usize = 50;
+const TIMEOUT_SECONDS: u64 = 30;
+// Redirects followed for one request, more than any tracker needs.
+const MAX_REDIRECTS: usize = 5;
+// Far more than any announce or scrape needs.
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
 pub struct HttpTrackerHandler {
     url: Url,
+    client: Client,
 }
 
 impl HttpTrackerHandler {
     pub fn new(url: Url) -> HttpTrackerHandler {
-        HttpTrackerHandler { url: url }
+        let mut client = https::client();
+        client.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        client.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        // followed by hand, to notice loops
+        client.set_redirect_policy(RedirectPolicy::FollowNone);
+        HttpTrackerHandler {
+            url: url,
+            client: client,
+        }
+    }
+
+    // Gets the decoded reply to a GET, following any redirects. Trackers
+    // send a failure reason with an error status too, so the reply to one is
+    // read before giving the status up as the error.
+    fn get(&self, url: Url) -> Result<Vec<u8>, TrackerError> {
+        let mut url = url;
+        let mut visited = Vec::new();
+        loop {
+            let mut response = try!(self.client.get(url.clone()).send().map_err(http_error));
+            let status = response.status;
+            if status.is_redirection() {
+                let location =
+                    try!(location(&response).ok_or(TrackerError::Status(status.to_u16())));
+                visited.push(url.clone());
+                url = try!(url.join(&location).map_err(|_| {
+                    TrackerError::InvalidResponse(format!("redirect to {}", location))
+                }));
+                if visited.contains(&url) || visited.len() > MAX_REDIRECTS {
+                    return Err(TrackerError::RedirectLoop);
+                }
+                continue;
+            }
+            let body = read_response(&mut response);
+            if status.is_success() {
+                return body;
+            }
+            let reason = body.ok().and_then(|bytes| {
+                decode_dict(&bytes).ok().and_then(|dict| failure_reason(&dict))
+            });
+            return Err(reason.map_or(TrackerError::Status(status.to_u16()),
+                                     TrackerError::Failure));
+        }
     }
 }
 
+// The scrape URL of a tracker by the usual convention: the last part of the
+// announce path must start with "announce", which becomes "scrape". None
+// when it does not, as then the tracker cannot be scraped.
+pub fn scrape_url(announce: &Url) -> Option<Url> {
+    let path = announce.path().to_string();
+    let start = path.rfind('/').map_or(0, |slash| slash + 1);
+    if !path[start..].starts_with("announce") {
+        return None;
+    }
+    let mut url = announce.clone();
+    url.set_path(&format!("{}scrape{}", &path[..start], &path[start + "announce".len()..]));
+    Some(url)
+}
+
 #[derive(Debug)]
 pub enum TrackerError {
     Unknown,
     ParseError(DecodeError),
     Http(String),
+    // The tracker refused, with its reason.
+    Failure(String),
+    // A reply with nothing but a warning in it.
+    Warning(String),
+    // An HTTP status other than success, without a failure reason.
+    Status(u16),
+    // Redirects that come back to where they started, or never end.
+    RedirectLoop,
+    // No answer in the time allowed, after any retransmissions.
+    Timeout,
+    // A reply that is not what was asked for, or too short to read.
+    InvalidResponse(String),
+    // The announce URL has no scrape URL to go with it.
+    NoScrape,
 }
 
 impl Error for TrackerError {
     fn description(&self) -> &str {
-        unimplemented!()
+        match *self {
+            TrackerError::Unknown => "unknown tracker error",
+            TrackerError::ParseError(_) => "could not decode the tracker's reply",
+            TrackerError::Http(_) => "HTTP request failed",
+            TrackerError::Failure(_) => "tracker refused the request",
+            TrackerError::Warning(_) => "tracker only sent a warning",
+            TrackerError::Status(_) => "unexpected HTTP status",
+            TrackerError::RedirectLoop => "tracker redirects in a loop",
+            TrackerError::Timeout => "tracker did not answer in time",
+            TrackerError::InvalidResponse(_) => "invalid tracker reply",
+            TrackerError::NoScrape => "tracker cannot be scraped",
+        }
     }
 }
 
@@ -54,6 +150,30 @@ impl fmt::Display for TrackerError {
             &TrackerError::Http(ref e) => {
                 f.write_str(e);
             }
+            &TrackerError::Failure(ref reason) => {
+                f.write_str("Tracker failure: ");
+                f.write_str(reason);
+            }
+            &TrackerError::Warning(ref message) => {
+                f.write_str("Tracker warning: ");
+                f.write_str(message);
+            }
+            &TrackerError::Status(status) => {
+                f.write_str(&format!("Unexpected HTTP status {}", status));
+            }
+            &TrackerError::RedirectLoop => {
+                f.write_str("The tracker redirects in a loop");
+            }
+            &TrackerError::Timeout => {
+                f.write_str("The tracker did not answer in time");
+            }
+            &TrackerError::InvalidResponse(ref e) => {
+                f.write_str("Invalid response: ");
+                f.write_str(e);
+            }
+            &TrackerError::NoScrape => {
+                f.write_str("The tracker does not support scraping");
+            }
         };
         Ok(())
     }
@@ -61,29 +181,97 @@ impl fmt::Display for TrackerError {
 
 impl TrackerHandler for HttpTrackerHandler {
     fn request(&self, req: &TrackerReq) -> Result<TrackerResp, TrackerError> {
-        // build the url
-        let mut url = self.url.clone();
-        let query_string = req.to_query_string_pairs()
-            .iter()
-            .fold(String::new(),
-                  |string, &(ref k, ref v)| format!("{}&{}={}", string, k, v));
-        url.set_query(Some(&query_string));
-
-        // make the request
-        let client = Client::new();
-        match client.get(url).send() {
-            Ok(mut response) => {
-                let mut response_bytes = Vec::new();
-                response.read_to_end(&mut response_bytes);
-                let response_dict =
-                    match BDict::try_from(belement_decode(&response_bytes).unwrap().0) {
-                        Ok(bdict) => bdict,
-                        Err(error) => return Err(TrackerError::ParseError(error)),
-                    };
-                let tracker_response: TrackerResp = TrackerResp::try_from(response_dict).unwrap();
-                Ok(tracker_response)
+        let bytes = try!(self.get(req.announce_url(&self.url)));
+        let dict = try!(decode_dict(&bytes));
+        if let Some(reason) = failure_reason(&dict) {
+            return Err(TrackerError::Failure(reason));
+        }
+        match TrackerResp::try_from(dict) {
+            Ok(response) => Ok(response),
+            Err(_) if dict.get("warning message").is_some() => {
+                let message = dict.get_bytes("warning message").unwrap_or(b"");
+                Err(TrackerError::Warning(String::from_utf8_lossy(message).into_owned()))
+            }
+            Err(e) => Err(TrackerError::ParseError(e)),
+        }
+    }
+
+    fn scrape(&self, hashes: &[SHA1Hash20b]) -> Result<ScrapeResp, TrackerError> {
+        let base = try!(scrape_url(&self.url).ok_or(TrackerError::NoScrape));
+        let mut scrape = ScrapeResp {
+            files: Vec::new(),
+            flags: BDict::new(),
+        };
+        for hashes in hashes.chunks(SCRAPE_HASHES_PER_REQUEST) {
+            // any query the tracker's URL came with, a passkey say, is kept
+            let pairs: Vec<(String, String)> = hashes.iter()
+                .map(|hash| ("info_hash".to_string(), url_encode(hash)))
+                .collect();
+            let url = with_query(&base, &pairs);
+
+            let bytes = try!(self.get(url));
+            let dict = try!(decode_dict(&bytes));
+            if let Some(reason) = failure_reason(&dict) {
+                return Err(TrackerError::Failure(reason));
             }
-            Err(e) => Err(TrackerError::Http(e.to_string())),
+            let part = try!(ScrapeResp::try_from(dict).map_err(TrackerError::ParseError));
+            scrape.files.extend(part.files);
+            scrape.flags = part.flags;
         }
+        Ok(scrape)
+    }
+}
+
+// Decodes the reply as it comes in, so a malformed one is noticed without
+// waiting for the rest of it and anything after the value is ignored.
+fn read_response<R: Read>(response: &mut R) -> Result<Vec<u8>, TrackerError> {
+    let mut decoder = StreamDecoder::new().with_max_buffered(MAX_RESPONSE_SIZE);
+    let mut chunk = [0; 4096];
+    loop {
+        let read = try!(response.read(&mut chunk).map_err(io_error));
+        if read == 0 {
+            return Err(TrackerError::ParseError(DecodeError {
+                position: Some(decoder.buffered().len()),
+                kind: DecodeErrorKind::EndOfStream,
+            }));
+        }
+        match decoder.push(&chunk[..read]) {
+            Progress::NeedMore => (),
+            Progress::Done(bytes) => return Ok(bytes),
+            Progress::Error(e) => return Err(TrackerError::ParseError(e)),
+        }
+    }
+}
+
+fn decode_dict(bytes: &[u8]) -> Result<BDictRef, TrackerError> {
+    match belement_decode_ref(bytes) {
+        Ok(DecodeResult(BencodeRef::BDict(dict), _)) => Ok(dict),
+        Ok(_) => Err(TrackerError::InvalidResponse("not a dict".to_string())),
+        Err(e) => Err(TrackerError::ParseError(e)),
+    }
+}
+
+fn failure_reason(dict: &BDictRef) -> Option<String> {
+    dict.get_bytes("failure reason").map(|reason| String::from_utf8_lossy(reason).into_owned())
+}
+
+fn location(response: &Response) -> Option<String> {
+    response.headers
+        .get_raw("Location")
+        .and_then(|values| values.get(0))
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+fn http_error(error: hyper::Error) -> TrackerError {
+    match error {
+        hyper::Error::Io(e) => io_error(e),
+        e => TrackerError::Http(e.to_string()),
+    }
+}
+
+fn io_error(error: io::Error) -> TrackerError {
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => TrackerError::Timeout,
+        _ => TrackerError::Http(error.to_string()),
     }
 }
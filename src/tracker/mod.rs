@@ -1,8 +1,15 @@
 pub mod http;
 pub mod data;
+pub mod tiers;
+pub mod udp;
+pub mod announcer;
 
-pub use tracker::http::{HttpTrackerHandler, TrackerError};
-pub use tracker::data::{TrackerReq, TrackerResp, TrackerEvent};
+pub use tracker::http::{HttpTrackerHandler, TrackerError, scrape_url};
+pub use tracker::data::{TrackerReq, TrackerResp, TrackerEvent, ScrapeStats,
+                        ScrapeResp};
+pub use tracker::tiers::{TrackerTiers, TrackerState};
+pub use tracker::udp::UdpTrackerHandler;
+pub use tracker::announcer::{Announcer, Transfer, TransferCounters};
 
 
 
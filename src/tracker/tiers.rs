@@ -0,0 +1,157 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use hyper::Url;
+use metainfo::MetaInfo;
+use tracker::{TrackerError, TrackerResp, UdpTrackerHandler};
+
+// Deliberately short of BEP 15, whose schedule of 15 * 2^n seconds up to
+// n = 8 is what `UdpTrackerHandler` keeps by default. The trackers of the
+// tiers are asked one after another on the announcing thread, so that
+// schedule would let one dead tracker hold up the rest for over two hours.
+// Here one gets a single retransmission, 15 + 30 seconds, before the next is
+// tried. The full schedule needs announces that do not block the tier walk.
+const UDP_BASE_TIMEOUT_SECONDS: u64 = 15;
+const UDP_MAX_RETRANSMISSIONS: u32 = 1;
+
+// What is known about one tracker from announcing to it.
+#[derive(Debug, Clone)]
+pub struct TrackerState {
+    pub url: String,
+    pub last_announce: Option<Instant>,
+    // Failures in a row, reset by the next success.
+    pub failures: u32,
+    // Sent back on every later announce once the tracker has given one.
+    pub tracker_id: Option<String>,
+    // Kept for as long as the tracker is, so its connection id is reused.
+    pub udp: Option<Arc<UdpTrackerHandler>>,
+}
+
+impl TrackerState {
+    pub fn new(url: &str) -> TrackerState {
+        TrackerState {
+            url: url.to_string(),
+            last_announce: None,
+            failures: 0,
+            tracker_id: None,
+            udp: udp_handler(url),
+        }
+    }
+
+    fn succeed(&mut self, now: Instant, response: &TrackerResp) {
+        self.last_announce = Some(now);
+        self.failures = 0;
+        if response.tracker_id.is_some() {
+            self.tracker_id = response.tracker_id.clone();
+        }
+    }
+}
+
+fn udp_handler(url: &str) -> Option<Arc<UdpTrackerHandler>> {
+    match Url::parse(url) {
+        Ok(url) => {
+            if url.scheme() != "udp" {
+                return None;
+            }
+            let mut handler = UdpTrackerHandler::new(url);
+            handler.set_timeouts(Duration::from_secs(UDP_BASE_TIMEOUT_SECONDS),
+                                 UDP_MAX_RETRANSMISSIONS);
+            Some(Arc::new(handler))
+        }
+        Err(_) => None,
+    }
+}
+
+// The trackers of a torrent in BEP 12 tiers. Each announce goes to the
+// first tracker of the first tier that answers, trying the rest in order
+// when one fails. A tracker that answers moves to the front of its tier, so
+// it is asked first next time.
+#[derive(Debug, Clone)]
+pub struct TrackerTiers {
+    tiers: Vec<Vec<TrackerState>>,
+}
+
+impl TrackerTiers {
+    // The torrent's tiers, each shuffled as BEP 12 asks. A lone `announce`
+    // is a tier of its own.
+    pub fn new(metainfo: &MetaInfo) -> TrackerTiers {
+        let tiers = if metainfo.announce_list.is_empty() {
+            metainfo.announce.iter().map(|url| vec![url.clone()]).collect()
+        } else {
+            metainfo.announce_list.clone()
+        };
+        let mut tiers = TrackerTiers::from_tiers(tiers);
+        tiers.shuffle(RandomState::new().build_hasher().finish());
+        tiers
+    }
+
+    // The tiers in the order given, without shuffling.
+    pub fn from_tiers(tiers: Vec<Vec<String>>) -> TrackerTiers {
+        TrackerTiers {
+            tiers: tiers.into_iter()
+                .filter(|tier| !tier.is_empty())
+                .map(|tier| tier.iter().map(|url| TrackerState::new(url)).collect())
+                .collect(),
+        }
+    }
+
+    pub fn tiers(&self) -> &[Vec<TrackerState>] {
+        &self.tiers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiers.is_empty()
+    }
+
+    // Shuffles the trackers within each tier, the tiers stay in order.
+    pub fn shuffle(&mut self, seed: u64) {
+        // xorshift64*, which must not start from zero
+        let mut state = seed | 1;
+        for tier in self.tiers.iter_mut() {
+            for i in (1..tier.len()).rev() {
+                state ^= state >> 12;
+                state ^= state << 25;
+                state ^= state >> 27;
+                let random = state.wrapping_mul(0x2545f4914f6cdd1d);
+                tier.swap(i, (random % (i as u64 + 1)) as usize);
+            }
+        }
+    }
+
+    // Announces to the trackers in turn until one answers, giving its URL
+    // and response. Every tracker tried has its state updated, and when all
+    // of them fail the last error is given.
+    pub fn announce<F>(&mut self,
+                       now: Instant,
+                       mut request: F)
+                       -> Result<(String, TrackerResp), TrackerError>
+        where F: FnMut(&TrackerState) -> Result<TrackerResp, TrackerError>
+    {
+        let mut last_error = TrackerError::Unknown;
+        for tier in self.tiers.iter_mut() {
+            for index in 0..tier.len() {
+                let result = request(&tier[index]).and_then(|response| {
+                    match response.failure_reason.clone() {
+                        Some(reason) => Err(TrackerError::Failure(reason)),
+                        None => Ok(response),
+                    }
+                });
+                match result {
+                    Ok(response) => {
+                        tier[index].succeed(now, &response);
+                        let tracker = tier.remove(index);
+                        let url = tracker.url.clone();
+                        tier.insert(0, tracker);
+                        return Ok((url, response));
+                    }
+                    Err(e) => {
+                        tier[index].failures += 1;
+                        last_error = e;
+                    }
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
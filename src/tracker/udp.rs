@@ -0,0 +1,251 @@
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use byteorder::{ByteOrder, BigEndian};
+use hyper::Url;
+use metainfo::SHA1Hash20b;
+use bencode::BDict;
+use tracker::data::{TrackerReq, TrackerResp, TrackerEvent, ScrapeStats, ScrapeResp,
+                    parse_compact_peers, parse_compact_peers6};
+use tracker::http::{TrackerHandler, TrackerError};
+
+// The magic constant that starts every connect request.
+const PROTOCOL_ID: u64 = 0x41727101980;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+// Trackers accept a connection id for two minutes, it is only reused for one
+// so that it does not run out on the way.
+const CONNECTION_ID_LIFETIME_SECONDS: u64 = 60;
+// Requests are sent again after 15 * 2^n seconds, up to n = 8.
+const BASE_TIMEOUT_SECONDS: u64 = 15;
+const MAX_RETRANSMISSIONS: u32 = 8;
+// What fits in a packet that is never fragmented, bigger scrapes are sent in
+// parts.
+const MAX_SCRAPE_HASHES: usize = 74;
+
+// A BEP 15 UDP tracker. Every request needs a connection id, which is got
+// from the tracker first and kept for later requests while it is good.
+#[derive(Debug)]
+pub struct UdpTrackerHandler {
+    url: Url,
+    connection: Mutex<Option<(u64, Instant)>>,
+    base_timeout: Duration,
+    max_retransmissions: u32,
+}
+
+impl UdpTrackerHandler {
+    pub fn new(url: Url) -> UdpTrackerHandler {
+        UdpTrackerHandler {
+            url: url,
+            connection: Mutex::new(None),
+            base_timeout: Duration::from_secs(BASE_TIMEOUT_SECONDS),
+            max_retransmissions: MAX_RETRANSMISSIONS,
+        }
+    }
+
+    // Waits `base * 2^n` for the nth retransmission, giving up after `max`.
+    pub fn set_timeouts(&mut self, base: Duration, max_retransmissions: u32) {
+        self.base_timeout = base;
+        self.max_retransmissions = max_retransmissions;
+    }
+
+    fn address(&self) -> Result<SocketAddr, TrackerError> {
+        let host = try!(self.url
+            .host_str()
+            .ok_or(TrackerError::Http(format!("no host in {}", self.url))));
+        let port = try!(self.url.port().ok_or(TrackerError::Http(format!("no port in {}",
+                                                                           self.url))));
+        let host = host.trim_left_matches('[').trim_right_matches(']');
+        let mut addresses = try!((host, port)
+            .to_socket_addrs()
+            .map_err(|e| TrackerError::Http(e.to_string())));
+        addresses.next().ok_or(TrackerError::Http(format!("could not resolve {}", host)))
+    }
+
+    // Sends a request with the given action and body, connecting first when
+    // there is no connection id to use, and sending it again whenever an
+    // answer takes too long. Gives the tracker's address and its answer.
+    fn transaction(&self,
+                   action: u32,
+                   body: &[u8])
+                   -> Result<(SocketAddr, Vec<u8>), TrackerError> {
+        let address = try!(self.address());
+        let local: SocketAddr = match address {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = try!(UdpSocket::bind(local).map_err(|e| TrackerError::Http(e.to_string())));
+        try!(socket.connect(address).map_err(|e| TrackerError::Http(e.to_string())));
+
+        let mut attempt = 0;
+        loop {
+            let timeout = self.base_timeout * (1 << cmp::min(attempt, 16));
+            let connection_id = match self.connection_id() {
+                Some(id) => Some(id),
+                None => {
+                    let mut request = vec![0; 16];
+                    BigEndian::write_u64(&mut request[0..8], PROTOCOL_ID);
+                    try!(exchange(&socket, ACTION_CONNECT, request, timeout)).map(|response| {
+                        let id = BigEndian::read_u64(&response[8..16]);
+                        *self.connection.lock().unwrap() = Some((id, Instant::now()));
+                        id
+                    })
+                }
+            };
+            if let Some(connection_id) = connection_id {
+                let mut request = vec![0; 16];
+                BigEndian::write_u64(&mut request[0..8], connection_id);
+                request.extend_from_slice(body);
+                let result = exchange(&socket, action, request, timeout);
+                if let Err(TrackerError::Failure(_)) = result {
+                    // most likely the connection id ran out
+                    *self.connection.lock().unwrap() = None;
+                }
+                if let Some(response) = try!(result) {
+                    return Ok((address, response));
+                }
+            }
+            if attempt >= self.max_retransmissions {
+                return Err(TrackerError::Timeout);
+            }
+            attempt += 1;
+        }
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        let lifetime = Duration::from_secs(CONNECTION_ID_LIFETIME_SECONDS);
+        match *self.connection.lock().unwrap() {
+            Some((id, since)) if since.elapsed() < lifetime => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl TrackerHandler for UdpTrackerHandler {
+    fn request(&self, req: &TrackerReq) -> Result<TrackerResp, TrackerError> {
+        let mut body = Vec::with_capacity(82);
+        body.extend_from_slice(&req.info_hash);
+        body.extend_from_slice(&req.peer_id);
+        let mut numbers = [0; 42];
+        BigEndian::write_u64(&mut numbers[0..8], req.downloaded);
+        BigEndian::write_u64(&mut numbers[8..16], req.left);
+        BigEndian::write_u64(&mut numbers[16..24], req.uploaded);
+        BigEndian::write_u32(&mut numbers[24..28],
+                             match req.event {
+                                 TrackerEvent::Regular => 0,
+                                 TrackerEvent::Completed => 1,
+                                 TrackerEvent::Started => 2,
+                                 TrackerEvent::Stopped => 3,
+                             });
+        // only an IPv4 address can be given, the tracker uses the sender's
+        // otherwise
+        if let Some(IpAddr::V4(ip)) = req.ip {
+            numbers[28..32].copy_from_slice(&ip.octets());
+        }
+        let key = req.key.as_ref().and_then(|key| u32::from_str_radix(key, 16).ok());
+        BigEndian::write_u32(&mut numbers[32..36], key.unwrap_or(0));
+        BigEndian::write_i32(&mut numbers[36..40],
+                             req.numwant.map(|numwant| numwant as i32).unwrap_or(-1));
+        BigEndian::write_u16(&mut numbers[40..42], req.port as u16);
+        body.extend_from_slice(&numbers);
+
+        let (address, response) = try!(self.transaction(ACTION_ANNOUNCE, &body));
+        if response.len() < 20 {
+            return Err(TrackerError::InvalidResponse("announce is too short".to_string()));
+        }
+        // peers come in the address family the tracker was asked over
+        let peers = match address {
+            SocketAddr::V4(_) => parse_compact_peers(&response[20..]),
+            SocketAddr::V6(_) => parse_compact_peers6(&response[20..]),
+        };
+        let peers = try!(peers.map_err(|_| {
+            TrackerError::InvalidResponse("peers are not whole".to_string())
+        }));
+        Ok(TrackerResp {
+            failure_reason: None,
+            warning_message: None,
+            interval: Some(BigEndian::read_u32(&response[8..12])),
+            min_interval: None,
+            tracker_id: None,
+            complete: Some(BigEndian::read_u32(&response[16..20])),
+            incomplete: Some(BigEndian::read_u32(&response[12..16])),
+            peers: peers,
+        })
+    }
+
+    // The counts come back in the order the hashes were sent. There are no
+    // flags in BEP 15.
+    fn scrape(&self, hashes: &[SHA1Hash20b]) -> Result<ScrapeResp, TrackerError> {
+        let mut files = Vec::new();
+        for hashes in hashes.chunks(MAX_SCRAPE_HASHES) {
+            let body: Vec<u8> = hashes.iter().flat_map(|hash| hash.iter().cloned()).collect();
+            let (_, response) = try!(self.transaction(ACTION_SCRAPE, &body));
+            if response.len() < 8 + hashes.len() * 12 {
+                return Err(TrackerError::InvalidResponse("scrape is too short".to_string()));
+            }
+            files.extend(hashes.iter().zip(response[8..].chunks(12)).map(|(hash, counts)| {
+                ScrapeStats {
+                    info_hash: hash.clone(),
+                    complete: BigEndian::read_u32(&counts[0..4]),
+                    downloaded: BigEndian::read_u32(&counts[4..8]),
+                    incomplete: BigEndian::read_u32(&counts[8..12]),
+                }
+            }));
+        }
+        Ok(ScrapeResp {
+            files: files,
+            flags: BDict::new(),
+        })
+    }
+}
+
+// Sends one request, filling in its action and a new transaction id, and
+// waits for the answer to it. None when there was none in time.
+fn exchange(socket: &UdpSocket,
+            action: u32,
+            mut request: Vec<u8>,
+            timeout: Duration)
+            -> Result<Option<Vec<u8>>, TrackerError> {
+    let transaction_id = RandomState::new().build_hasher().finish() as u32;
+    BigEndian::write_u32(&mut request[8..12], action);
+    BigEndian::write_u32(&mut request[12..16], transaction_id);
+    try!(socket.send(&request).map_err(|e| TrackerError::Http(e.to_string())));
+
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; 65536];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        try!(socket.set_read_timeout(Some(deadline - now))
+            .map_err(|e| TrackerError::Http(e.to_string())));
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                          e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(TrackerError::Http(e.to_string())),
+        };
+        let response = &buffer[..length];
+        // anything left over from an earlier request is not for this one
+        if length < 8 || BigEndian::read_u32(&response[4..8]) != transaction_id {
+            continue;
+        }
+        return match BigEndian::read_u32(&response[0..4]) {
+            ACTION_ERROR => {
+                Err(TrackerError::Failure(String::from_utf8_lossy(&response[8..]).into_owned()))
+            }
+            ACTION_CONNECT if action == ACTION_CONNECT && length < 16 => {
+                Err(TrackerError::InvalidResponse("connect is too short".to_string()))
+            }
+            received if received == action => Ok(Some(response.to_vec())),
+            received => Err(TrackerError::InvalidResponse(format!("action {}", received))),
+        };
+    }
+}
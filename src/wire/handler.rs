@@ -3,9 +3,14 @@ use wire::action::{PeerId, PeerAction};
 use metainfo::MetaInfo;
 use metainfo::SHA1Hash20b;
 use wire::peer_info::PeerState;
+use wire::store::PieceStore;
 
 pub trait ServerHandler {
-    fn new(metainfo: MetaInfo, hash: SHA1Hash20b, our_peer_id: &str) -> Self;
+    fn new(metainfo: MetaInfo,
+           hash: SHA1Hash20b,
+           our_peer_id: &str,
+           store: PieceStore)
+           -> Self;
     fn on_peer_connect(&mut self, peer: &mut PeerState);
     fn on_message_receive(&mut self, peer: &mut PeerState, msg: PeerMsg);
     fn on_peer_disconnect(&mut self, peer: &mut PeerState);
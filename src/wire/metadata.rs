@@ -0,0 +1,301 @@
+use std::{cmp, error, fmt, io};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use byteorder::{ByteOrder, BigEndian};
+use bencode::borrowed::belement_decode_ref;
+use bencode::decode::DecodeResult;
+use bencode::encode::belement_encode;
+use bencode::mapping::{FromBencode, ToBencode};
+use metainfo::SHA1Hash20b;
+use wire::msg::{PeerMsg, parse_handshake};
+
+// BEP 9 sends the info dict in pieces of this size, only the last is shorter.
+pub const METADATA_PIECE_SIZE: usize = 16 * 1024;
+// The id peers are told to use for `ut_metadata` messages sent to us.
+pub const UT_METADATA_ID: u8 = 1;
+// The peer wire id of every BEP 10 message.
+const EXTENDED_MSG_ID: u8 = 20;
+// The extended handshake itself always has id 0.
+const EXTENDED_HANDSHAKE_ID: u8 = 0;
+const MAX_METADATA_SIZE: usize = 8 * 1024 * 1024;
+const MAX_MESSAGE_SIZE: usize = METADATA_PIECE_SIZE + 1024;
+// Other messages are never held on to, this only turns away nonsense lengths.
+const MAX_SKIPPED_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+const PROTOCOL_ID: &'static str = "BitTorrent protocol";
+
+const REQUEST: i64 = 0;
+const DATA: i64 = 1;
+const REJECT: i64 = 2;
+
+#[derive(Debug)]
+pub enum MetadataError {
+    IOError(io::Error),
+    // The peer does not speak BEP 10, or has no `ut_metadata`.
+    NotSupported,
+    WrongInfoHash,
+    Rejected(usize),
+    TooLarge(usize),
+    Malformed,
+}
+
+bencode_dict! {
+    // The BEP 10 handshake, as far as metadata goes.
+    struct ExtendedHandshake {
+        pub m: Option<ExtensionIds> => optional "m",
+        pub metadata_size: Option<i64> => optional "metadata_size",
+    }
+}
+
+bencode_dict! {
+    struct ExtensionIds {
+        pub ut_metadata: Option<i64> => optional "ut_metadata",
+    }
+}
+
+bencode_dict! {
+    // The dict heading every `ut_metadata` message, data messages are
+    // followed by the piece itself.
+    struct MetadataMsg {
+        pub msg_type: Option<i64> => optional "msg_type",
+        pub piece: i64 => required "piece",
+        pub total_size: Option<i64> => optional "total_size",
+    }
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetadataError::IOError(ref e) => write!(f, "{}", e),
+            MetadataError::NotSupported => f.write_str("peer does not send metadata"),
+            MetadataError::WrongInfoHash => f.write_str("peer is in another swarm"),
+            MetadataError::Rejected(piece) => write!(f, "peer rejected metadata piece {}", piece),
+            MetadataError::TooLarge(size) => write!(f, "metadata of {} bytes is too large", size),
+            MetadataError::Malformed => f.write_str("malformed metadata message"),
+        }
+    }
+}
+
+impl error::Error for MetadataError {
+    fn description(&self) -> &str {
+        match *self {
+            MetadataError::IOError(_) => "could not talk to the peer",
+            MetadataError::NotSupported => "peer does not send metadata",
+            MetadataError::WrongInfoHash => "peer is in another swarm",
+            MetadataError::Rejected(_) => "peer rejected a metadata piece",
+            MetadataError::TooLarge(_) => "metadata is too large",
+            MetadataError::Malformed => "malformed metadata message",
+        }
+    }
+}
+
+impl From<io::Error> for MetadataError {
+    fn from(error: io::Error) -> MetadataError {
+        MetadataError::IOError(error)
+    }
+}
+
+// Collects the info dict from a peer's `ut_metadata` messages. It does not
+// check the result, which is up to whoever knows the info-hash.
+pub struct MetadataFetcher {
+    their_id: Option<u8>,
+    size: usize,
+    pieces: Vec<Option<Vec<u8>>>,
+}
+
+impl MetadataFetcher {
+    pub fn new() -> MetadataFetcher {
+        MetadataFetcher {
+            their_id: None,
+            size: 0,
+            pieces: Vec::new(),
+        }
+    }
+
+    // Our BEP 10 handshake, offering `ut_metadata`.
+    pub fn handshake() -> PeerMsg {
+        let handshake = ExtendedHandshake {
+            m: Some(ExtensionIds { ut_metadata: Some(UT_METADATA_ID as i64) }),
+            metadata_size: None,
+        };
+        PeerMsg::Extended(EXTENDED_HANDSHAKE_ID, encode(&handshake))
+    }
+
+    // Takes an extended message from the peer and gives the messages to send
+    // back.
+    pub fn on_message(&mut self, id: u8, payload: &[u8]) -> Result<Vec<PeerMsg>, MetadataError> {
+        match id {
+            EXTENDED_HANDSHAKE_ID => self.on_handshake(payload),
+            UT_METADATA_ID => self.on_metadata(payload),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    // The info dict, once every piece has arrived.
+    pub fn metadata(&self) -> Option<Vec<u8>> {
+        if self.pieces.is_empty() || self.pieces.iter().any(|piece| piece.is_none()) {
+            return None;
+        }
+        Some(self.pieces.iter().flat_map(|piece| piece.as_ref().unwrap().iter().cloned()).collect())
+    }
+
+    fn on_handshake(&mut self, payload: &[u8]) -> Result<Vec<PeerMsg>, MetadataError> {
+        let handshake: ExtendedHandshake = try!(decode(payload)).0;
+        let their_id = handshake.m.and_then(|m| m.ut_metadata);
+        let their_id = match their_id {
+            Some(id @ 1...255) => id as u8,
+            _ => return Err(MetadataError::NotSupported),
+        };
+        let size = match handshake.metadata_size {
+            Some(size) if size > MAX_METADATA_SIZE as i64 => {
+                return Err(MetadataError::TooLarge(size as usize))
+            }
+            Some(size) if size > 0 => size as usize,
+            _ => return Err(MetadataError::NotSupported),
+        };
+        self.their_id = Some(their_id);
+        self.size = size;
+        let count = (size + METADATA_PIECE_SIZE - 1) / METADATA_PIECE_SIZE;
+        self.pieces = vec![None; count];
+        Ok((0..count).map(|piece| self.message(REQUEST, piece)).collect())
+    }
+
+    fn on_metadata(&mut self, payload: &[u8]) -> Result<Vec<PeerMsg>, MetadataError> {
+        // the piece's data follows the dict
+        let (msg, end): (MetadataMsg, usize) = try!(decode(payload));
+        let piece = match msg.piece {
+            piece if piece >= 0 => piece as usize,
+            _ => return Err(MetadataError::Malformed),
+        };
+        match msg.msg_type {
+            Some(REQUEST) => Ok(vec![self.message(REJECT, piece)]),
+            Some(DATA) => {
+                if piece >= self.pieces.len() {
+                    return Err(MetadataError::Malformed);
+                }
+                let expected = ::std::cmp::min(METADATA_PIECE_SIZE,
+                                               self.size - piece * METADATA_PIECE_SIZE);
+                let data = &payload[end..];
+                if data.len() != expected {
+                    return Err(MetadataError::Malformed);
+                }
+                self.pieces[piece] = Some(data.to_vec());
+                Ok(Vec::new())
+            }
+            Some(REJECT) => Err(MetadataError::Rejected(piece)),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn message(&self, msg_type: i64, piece: usize) -> PeerMsg {
+        let msg = MetadataMsg {
+            msg_type: Some(msg_type),
+            piece: piece as i64,
+            total_size: None,
+        };
+        PeerMsg::Extended(self.their_id.unwrap_or(0), encode(&msg))
+    }
+}
+
+// Reads the dict at the start of a payload, with where it ends.
+fn decode<'a, A: FromBencode<'a>>(payload: &'a [u8]) -> Result<(A, usize), MetadataError> {
+    match belement_decode_ref(payload) {
+        Ok(DecodeResult(element, end)) => {
+            A::from_bencode(element).map(|value| (value, end)).map_err(|_| MetadataError::Malformed)
+        }
+        Err(_) => Err(MetadataError::Malformed),
+    }
+}
+
+fn encode<A: ToBencode>(value: &A) -> Vec<u8> {
+    belement_encode(&value.to_bencode())
+}
+
+// Gets the info dict from one peer over a blocking connection, for magnet
+// links where there is no MetaInfo yet to start the protocol with.
+pub fn fetch_metadata(addr: &SocketAddr,
+                      info_hash: &SHA1Hash20b,
+                      peer_id: &str,
+                      timeout: Duration)
+                      -> Result<Vec<u8>, MetadataError> {
+    let mut stream = try!(TcpStream::connect(addr));
+    try!(stream.set_read_timeout(Some(timeout)));
+    try!(stream.set_write_timeout(Some(timeout)));
+    let handshake: Vec<u8> =
+        PeerMsg::handshake(PROTOCOL_ID.to_string(), peer_id.to_string(), info_hash).into();
+    try!(stream.write_all(&handshake));
+
+    let mut theirs = [0; 68];
+    try!(stream.read_exact(&mut theirs));
+    match parse_handshake(&theirs) {
+        Ok((PeerMsg::HandShake(_, ref hash, _), _)) if hash == info_hash => (),
+        Ok(_) => return Err(MetadataError::WrongInfoHash),
+        Err(_) => return Err(MetadataError::Malformed),
+    }
+    if !supports_extensions(&theirs) {
+        return Err(MetadataError::NotSupported);
+    }
+    let ours: Vec<u8> = MetadataFetcher::handshake().into();
+    try!(stream.write_all(&ours));
+
+    let mut fetcher = MetadataFetcher::new();
+    let mut buffer = Vec::new();
+    // bytes still to come of a message we have no use for
+    let mut skipping = 0;
+    let mut chunk = [0; 4096];
+    loop {
+        let read = try!(stream.read(&mut chunk));
+        if read == 0 {
+            return Err(MetadataError::IOError(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                             "peer closed the connection")));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        loop {
+            let skipped = cmp::min(skipping, buffer.len());
+            buffer.drain(..skipped);
+            skipping -= skipped;
+            if skipping > 0 || buffer.len() < 4 {
+                break;
+            }
+            let length = BigEndian::read_u32(&buffer[..4]) as usize;
+            if length == 0 {
+                buffer.drain(..4);
+                continue;
+            }
+            if buffer.len() < 5 {
+                break;
+            }
+            // everything but extended messages, like a big bitfield, is
+            // passed over without being kept
+            if buffer[4] != EXTENDED_MSG_ID {
+                if length > MAX_SKIPPED_MESSAGE_SIZE {
+                    return Err(MetadataError::Malformed);
+                }
+                buffer.drain(..4);
+                skipping = length;
+                continue;
+            }
+            if length > MAX_MESSAGE_SIZE {
+                return Err(MetadataError::Malformed);
+            }
+            if buffer.len() < 4 + length {
+                break;
+            }
+            let message: Vec<u8> = buffer.drain(..(4 + length)).skip(4).collect();
+            if message.len() >= 2 {
+                for reply in try!(fetcher.on_message(message[1], &message[2..])) {
+                    let reply: Vec<u8> = reply.into();
+                    try!(stream.write_all(&reply));
+                }
+            }
+        }
+        if let Some(metadata) = fetcher.metadata() {
+            return Ok(metadata);
+        }
+    }
+}
+
+// BEP 10 is bit 20 from the right of the reserved bytes.
+pub fn supports_extensions(handshake: &[u8]) -> bool {
+    handshake.len() >= 1 + 19 + 8 && handshake[1 + 19 + 5] & 0x10 != 0
+}
@@ -5,6 +5,9 @@ mod action;
 mod msg;
 mod peer_info;
 mod strategy;
+mod store;
+pub mod metadata;
 
 pub use wire::stream::{Protocol, ChanMsg};
 pub use wire::msg::PeerMsg;
+pub use wire::store::PieceStore;
@@ -22,6 +22,8 @@ pub enum PeerMsg {
     Piece(u32, u32, Vec<u8>),
     Cancel(u32, u32, u32),
     Port(u32),
+    // BEP 10, the extended message id and its payload
+    Extended(u8, Vec<u8>),
 }
 
 impl PeerMsg {
@@ -45,6 +47,7 @@ impl PeerMsg {
             &PeerMsg::Piece(_, _, _) => 7,
             &PeerMsg::Cancel(_, _, _) => 8,
             &PeerMsg::Port(_) => 9,
+            &PeerMsg::Extended(_, _) => 20,
         })
     }
 
@@ -70,7 +73,8 @@ impl Into<Vec<u8>> for PeerMsg {
                 }
                 out.push(p_bytes.len() as u8);
                 out.extend_from_slice(p_bytes);
-                out.append(&mut vec![0, 0, 0, 0, 0, 0, 0, 0]);
+                // we speak the BEP 10 extension protocol
+                out.append(&mut vec![0, 0, 0, 0, 0, 0x10, 0, 0]);
                 out.append(&mut info_hash);
                 out.append(&mut peer_id);
                 return out;
@@ -105,6 +109,11 @@ impl Into<Vec<u8>> for PeerMsg {
                 out.write_u32::<BigEndian>(port);
                 out
             }
+            PeerMsg::Extended(id, ref payload) => {
+                out.push(id);
+                out.extend_from_slice(payload);
+                out
+            }
             _ => out,
         };
         let mut front_part = Vec::new();
@@ -222,6 +231,12 @@ pub fn parse_peermsg(bytes: &[u8]) -> Result<(PeerMsg, usize), MsgParseError> {
             let port = BigEndian::read_u32(&bytes[0..PORT_LEN]);
             Ok(PeerMsg::Port(port))
         }
+        20 => {
+            if len < ID_LEN + 1 {
+                return Err(MsgParseError::TooShortForId);
+            }
+            Ok(PeerMsg::Extended(bytes[0], Vec::from(&bytes[1..end])))
+        }
         _ => Err(MsgParseError::InvalidId),
     };
 
@@ -2,14 +2,13 @@ use wire::handler::ServerHandler;
 use wire::action::{PeerId, PeerStreamAction, PeerAction};
 use metainfo::MetaInfo;
 use metainfo::SHA1Hash20b;
-use file::PartialFile;
 use std::collections::HashMap;
 use wire::msg::PeerMsg;
 use std::time::SystemTime;
-use file::{PartialFileTrait, PeerFile};
 use bit_vec::BitVec;
 use wire::peer_info::PeerState;
 use wire::strategy::{Strategy, BitTorrentProtocol};
+use wire::store::PieceStore;
 
 const TIMEOUT_SECONDS: u64 = 60 * 5;
 const KEEPALIVE_PERIOD: u64 = 30;
@@ -17,7 +16,6 @@ const KEEPALIVE_PERIOD: u64 = 30;
 pub struct PeerServer {
     hash: SHA1Hash20b,
     our_peer_id: String,
-    partial_file: PartialFile,
     num_pieces: usize,
     pieces_to_request: BitVec,
     strategy: BitTorrentProtocol,
@@ -26,25 +24,27 @@ pub struct PeerServer {
 const PROTOCOL_ID: &'static str = "BitTorrent protocol";
 
 impl ServerHandler for PeerServer {
-    fn new(metainfo: MetaInfo, hash: SHA1Hash20b, our_peer_id: &str) -> Self {
-        let num_pieces = metainfo.info.pieces.len();
-        let partial_file = PartialFile::new(&metainfo.info);
-        let pl = partial_file.piece_length();
+    fn new(metainfo: MetaInfo,
+           hash: SHA1Hash20b,
+           our_peer_id: &str,
+           store: PieceStore)
+           -> Self {
+        let num_pieces = metainfo.info.piece_count();
 
         PeerServer {
             hash: hash,
             our_peer_id: our_peer_id.to_string(),
-            partial_file: partial_file,
             num_pieces: num_pieces,
             pieces_to_request: BitVec::from_elem(num_pieces, true),
-            strategy: BitTorrentProtocol::new(metainfo),
+            strategy: BitTorrentProtocol::new(metainfo, store),
         }
     }
 
     fn on_peer_connect(&mut self, peer: &mut PeerState) {
+        let hash = peer.info_hash.clone().unwrap_or(self.hash.clone());
         let handshake = PeerMsg::handshake(PROTOCOL_ID.to_string(),
                                            self.our_peer_id.to_string(),
-                                           &self.hash);
+                                           &hash);
 
         peer.write_message_out(handshake.into());
     }
@@ -85,21 +85,6 @@ impl PeerServer {
         for_removal
     }*/
 
-    /*fn _get_piece_from_req(&mut self, index: usize, begin: u32, offset: u32) -> Option<PeerMsg> {
-        if self.partial_file.has_piece(index as usize) {
-            let piece = self.partial_file.get_piece_mut(index as usize);
-            return match piece.get_offset(begin as usize, offset as usize) {
-                Some(piece_data) => {
-                    let msg = PeerMsg::Piece(begin, offset, Vec::from(piece_data));
-                    Some(msg)
-                }
-                _ => None,
-            };
-        }
-        None
-
-    }*/
-
     fn _on_message_receive(&mut self, peer: &mut PeerState, msg: PeerMsg) {
         peer.last_msg_time = SystemTime::now();
 
@@ -133,6 +118,7 @@ impl PeerServer {
             PeerMsg::Cancel(index, begin, block) => return,
             // self.strategy.on_cancel(id, index, begin, block),
             PeerMsg::Port(port) => self.strategy.on_port(peer, port as u16),
+            PeerMsg::Extended(..) => return,
         };
     }
 }
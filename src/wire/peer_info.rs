@@ -4,6 +4,7 @@ use wire::action::PeerId;
 use std::io;
 use std::io::{Read, Write};
 use wire::msg::{PeerMsg, parse_peermsg};
+use metainfo::SHA1Hash20b;
 
 pub struct PeerState {
     pub peer_id: PeerId,
@@ -18,6 +19,9 @@ pub struct PeerState {
     pub file: PeerFile,
     pub score: u64,
     pub connection_time: SystemTime,
+    // The info-hash this peer's swarm goes by, once known. Hybrid torrents
+    // have two.
+    pub info_hash: Option<SHA1Hash20b>,
 
     piece_size: usize,
     buffer: MessageBuffer
@@ -43,6 +47,7 @@ impl PeerState {
             file: PeerFile::new(len),
             score: 0,
             connection_time: SystemTime::now(),
+            info_hash: None,
             buffer: MessageBuffer::new(),
             piece_size: piece_size
         }
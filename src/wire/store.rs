@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+use bit_vec::BitVec;
+use file::{PartialFile, PartialFileTrait};
+use tracker::TransferCounters;
+
+// The torrent's pieces as the peer wire sees them. Every block that comes
+// in or goes out through it is counted for the trackers. Copies share the
+// same pieces, so web seeds fill in what peers are asked for.
+#[derive(Clone)]
+pub struct PieceStore {
+    file: Arc<Mutex<PartialFile>>,
+    counters: Arc<TransferCounters>,
+}
+
+impl PieceStore {
+    pub fn new(file: PartialFile, counters: Arc<TransferCounters>) -> PieceStore {
+        counters.set_left(file.bytes_left());
+        PieceStore {
+            file: Arc::new(Mutex::new(file)),
+            counters: counters,
+        }
+    }
+
+    // Adds a block of a piece. Gives whether it fitted, it may still have
+    // been thrown away with the rest of a piece that did not verify.
+    pub fn receive(&self, index: u32, begin: u32, block: Vec<u8>) -> bool {
+        let length = block.len() as u64;
+        let mut file = self.file.lock().unwrap();
+        if !file.add_piece(index as usize, begin as usize, block) {
+            return false;
+        }
+        self.counters.add_downloaded(length);
+        self.counters.set_left(file.bytes_left());
+        true
+    }
+
+    // A block of a piece we have, to send to a peer that asked for it.
+    pub fn send(&self, index: u32, begin: u32, length: u32) -> Option<Vec<u8>> {
+        let mut file = self.file.lock().unwrap();
+        if !file.has_piece(index as usize) {
+            return None;
+        }
+        let block = file.get_piece_mut(index as usize)
+            .get_offset(begin as usize, length as usize)
+            .map(Vec::from);
+        if let Some(ref block) = block {
+            self.counters.add_uploaded(block.len() as u64);
+        }
+        block
+    }
+
+    pub fn piece_length(&self) -> u64 {
+        self.file.lock().unwrap().piece_length()
+    }
+}
+
+impl PartialFileTrait for PieceStore {
+    fn length(&self) -> usize {
+        self.file.lock().unwrap().length()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.file.lock().unwrap().is_complete()
+    }
+
+    fn has_piece(&self, i: usize) -> bool {
+        self.file.lock().unwrap().has_piece(i)
+    }
+
+    fn bit_array(&self) -> BitVec {
+        self.file.lock().unwrap().bit_array()
+    }
+}
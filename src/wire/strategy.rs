@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use bit_vec::BitVec;
 use file::PartialFile;
 use file::PartialFileTrait;
+use wire::store::PieceStore;
 use wire::action::PeerId;
 use file::PeerFile;
 use metainfo::SHA1Hash20b;
@@ -27,38 +28,27 @@ pub trait Strategy {
 }
 
 pub struct BitTorrentProtocol {
+    info_hashes: Vec<SHA1Hash20b>,
     num_pieces: usize,
     piece_length: u64,
-    partial_file: PartialFile,
+    store: PieceStore,
 }
 
 impl BitTorrentProtocol {
-    pub fn new(metainfo: MetaInfo) -> BitTorrentProtocol {
-        let num_pieces = metainfo.info.pieces.len();
-        let partial_file = PartialFile::new(&metainfo.info);
-        let piece_length = partial_file.piece_length();
+    pub fn new(metainfo: MetaInfo, store: PieceStore) -> BitTorrentProtocol {
+        let num_pieces = metainfo.info.piece_count();
+        let piece_length = store.piece_length();
 
         BitTorrentProtocol {
-            partial_file: partial_file,
+            info_hashes: metainfo.info_hashes(),
+            store: store,
             num_pieces: num_pieces,
             piece_length: piece_length,
         }
     }
 
-    fn _get_piece_from_req(&mut self, index: usize, begin: u32, offset: u32) -> Option<Vec<u8>> {
-        if self.partial_file.has_piece(index as usize) {
-            let piece = self.partial_file.get_piece_mut(index as usize);
-            match piece.get_offset(begin as usize, offset as usize) {
-                Some(bytes) => return Some(Vec::from(bytes)),
-                _ => {}
-            }
-        }
-
-        None
-    }
-
     fn _get_missing(&self, peer_file: &PeerFile) -> BitVec {
-        let mut missing = self.partial_file.bit_array();
+        let mut missing = self.store.bit_array();
         missing.negate();
         let mut them = peer_file.bit_array();
         missing.intersect(&them);
@@ -73,7 +63,14 @@ const PIECE_SCORE: u64 = 5;
 
 impl Strategy for BitTorrentProtocol {
     fn on_handshake(&mut self, peer: &mut PeerState, their_hash: SHA1Hash20b, peer_id: SHA1Hash20b)  {
-        if /*their_hash == &self.hash*/ true {
+        // a peer we dialed has to answer for the swarm we found it in, one
+        // that dialed us can be in any of the torrent's swarms
+        let expected = match peer.info_hash {
+            Some(ref hash) => hash == &their_hash,
+            None => self.info_hashes.contains(&their_hash),
+        };
+        if expected {
+            peer.info_hash = Some(their_hash);
             peer.has_handshake = true;
             peer.choke(false);
             peer.interested(true);
@@ -103,7 +100,7 @@ impl Strategy for BitTorrentProtocol {
     }
 
     fn on_request(&mut self, peer: &mut PeerState, index: u32, begin: u32, length: u32)  {
-        match self._get_piece_from_req(index as usize, begin, length) {
+        match self.store.send(index, begin, length) {
             Some(data) => {
                 peer.send_piece_data(index, begin, data);
             } 
@@ -115,7 +112,7 @@ impl Strategy for BitTorrentProtocol {
 
     fn on_piece(&mut self, peer: &mut PeerState, index: u32, begin: u32, block: Vec<u8>)  {
         let block_len = block.len();
-        self.partial_file.add_piece(index as usize, begin as usize, block);
+        self.store.receive(index, begin, block);
         peer.score += PIECE_SCORE;
     }
 
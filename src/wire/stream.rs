@@ -22,6 +22,7 @@ use wire::msg::{PeerMsg, parse_peermsg, parse_handshake};
 use wire::action::PeerId;
 use wire::peer::PeerServer;
 use wire::peer_info::PeerState;
+use wire::store::PieceStore;
 
 const OUTSIDE_MSG: Token = Token(0);
 pub type StreamId = u32;
@@ -35,19 +36,24 @@ pub struct Protocol {
     info: MetaInfo,
     info_hash: SHA1Hash20b,
     next_peer_id: usize,
+    running: bool,
 }
 
 #[derive(Debug)]
 pub enum ChanMsg {
-    NewPeer(IpAddr, u16),
+    // A peer from the swarm of the given info-hash.
+    NewPeer(IpAddr, u16, SHA1Hash20b),
     ThrottleOn(usize),
     ThrottleOff,
+    // Ends `run`, closing every stream.
+    Shutdown,
 }
 
 impl Protocol {
     pub fn new(info: &MetaInfo,
                hash: SHA1Hash20b,
-               our_peer_id: &str)
+               our_peer_id: &str,
+               store: PieceStore)
                -> (Protocol, Sender<ChanMsg>, Receiver<ChanMsg>) {
         let poll = Poll::new().unwrap();
 
@@ -67,8 +73,12 @@ impl Protocol {
                     receiver: from_outside,
                     info: info.clone(),
                     info_hash: hash.clone(),
-                    handler: ServerHandler::new(info.clone(), hash.clone(), our_peer_id),
+                    handler: ServerHandler::new(info.clone(),
+                                                hash.clone(),
+                                                our_peer_id,
+                                                store),
                     next_peer_id: 1,
+                    running: true,
                 };
 
                 (proto, to_inside, from_inside)
@@ -79,12 +89,13 @@ impl Protocol {
     pub fn run(&mut self) {
         const EVENT_CAPACITY: usize = 25;
         let mut events = Events::with_capacity(EVENT_CAPACITY);
-        loop {
+        while self.running {
             self.poll.poll(&mut events, None).unwrap();
             for event in events.iter() {
                 self._handle_event(event);
             }
         }
+        self.streams.clear();
     }
 
     fn _handle_event(&mut self, event: Event) {
@@ -154,12 +165,13 @@ impl Protocol {
 
     fn _handle_outside_msg(&mut self, msg: ChanMsg) {
         match msg {
-            ChanMsg::NewPeer(ip, port) => self._handle_new_peer(ip, port),
+            ChanMsg::NewPeer(ip, port, hash) => self._handle_new_peer(ip, port, hash),
+            ChanMsg::Shutdown => self.running = false,
             _ => (),
         }
     }
 
-    fn _handle_new_peer(&mut self, addr: IpAddr, port: u16) {
+    fn _handle_new_peer(&mut self, addr: IpAddr, port: u16, hash: SHA1Hash20b) {
         for (id, &(ref socket, _)) in &self.streams {
             match (socket.peer_addr(), addr) {
                 (Ok(SocketAddr::V4(peer)), IpAddr::V4(p)) => {
@@ -178,10 +190,11 @@ impl Protocol {
         match self._connect_to_peer(addr, port) {
             Some((sock, Token(id_usize))) => {
                 let id = id_usize as u32;
-                let num_pieces = self.info.info.pieces.len();
+                let num_pieces = self.info.info.piece_count();
 
                 
                 let mut peer = PeerState::new(num_pieces, self.info.info.piece_length as usize, id);
+                peer.info_hash = Some(hash);
 
                 self.handler.on_peer_connect(&mut peer);
                 self.streams.insert(id, (sock, peer));
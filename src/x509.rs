@@ -0,0 +1,164 @@
+// X.509 certificates and RSA private keys, as OpenSSL reads them: who a
+// certificate names, its public key, whether it may sign, and whether a
+// chain of trusted certificates vouches for it.
+use std::{error, fmt};
+use foreign_types::ForeignTypeRef;
+use openssl::asn1::Asn1Time;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::stack::Stack;
+use openssl::x509::{X509, X509NameRef, X509StoreContext, X509VerifyResult};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::X509VerifyFlags;
+use openssl_sys;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+
+// What `X509_get_key_usage` returns without a keyUsage extension.
+const NO_KEY_USAGE: u32 = 0xffffffff;
+// The keyUsage bits that allow signing data other than certificates.
+const DIGITAL_SIGNATURE: u32 = 0x80;
+const NON_REPUDIATION: u32 = 0x40;
+
+#[derive(Debug, PartialEq)]
+pub enum X509Error {
+    Pem,
+    Der,
+    // Only RSA keys are known.
+    UnsupportedAlgorithm,
+}
+
+impl fmt::Display for X509Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(error::Error::description(self))
+    }
+}
+
+impl error::Error for X509Error {
+    fn description(&self) -> &str {
+        match *self {
+            X509Error::Pem => "malformed PEM",
+            X509Error::Der => "malformed DER",
+            X509Error::UnsupportedAlgorithm => "unsupported key or signature algorithm",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Certificate {
+    x509: X509,
+    der: Vec<u8>,
+    public_key: RsaPublicKey,
+}
+
+impl Certificate {
+    pub fn from_der(der: &[u8]) -> Result<Certificate, X509Error> {
+        Certificate::new(try!(X509::from_der(der).map_err(|_| X509Error::Der)))
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Certificate, X509Error> {
+        Certificate::new(try!(X509::from_pem(pem.as_bytes()).map_err(|_| X509Error::Pem)))
+    }
+
+    fn new(x509: X509) -> Result<Certificate, X509Error> {
+        let der = try!(x509.to_der().map_err(|_| X509Error::Der));
+        let public_key = try!(x509.public_key()
+            .ok()
+            .and_then(RsaPublicKey::from_pkey)
+            .ok_or(X509Error::UnsupportedAlgorithm));
+        Ok(Certificate {
+            x509: x509,
+            der: der,
+            public_key: public_key,
+        })
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.public_key
+    }
+
+    // The subject's common name, which is what a signer is usually known by.
+    pub fn subject_name(&self) -> Option<String> {
+        common_name(self.x509.subject_name())
+    }
+
+    pub fn issuer_name(&self) -> Option<String> {
+        common_name(self.x509.issuer_name())
+    }
+
+    // Whether `issuer` is named as this certificate's issuer and signed it.
+    pub fn is_issued_by(&self, issuer: &Certificate) -> bool {
+        issuer.x509.issued(&self.x509) == X509VerifyResult::OK &&
+        issuer.x509.public_key().and_then(|key| self.x509.verify(&key)).unwrap_or(false)
+    }
+
+    // Whether the certificate is valid now and its key usage, if it has one,
+    // allows signing.
+    pub fn may_sign(&self) -> bool {
+        let now = match Asn1Time::days_from_now(0) {
+            Ok(now) => now,
+            Err(_) => return false,
+        };
+        if self.x509.not_before() > now || self.x509.not_after() < now {
+            return false;
+        }
+        let usage = unsafe { openssl_sys::X509_get_key_usage(self.x509.as_ptr()) };
+        usage == NO_KEY_USAGE || usage & (DIGITAL_SIGNATURE | NON_REPUDIATION) != 0
+    }
+
+    // Whether one of `trusted` is this certificate or vouches for it through
+    // a chain of valid CA certificates.
+    pub fn is_trusted_by(&self, trusted: &[Certificate]) -> bool {
+        let verified = X509StoreBuilder::new().and_then(|mut store| {
+            // any trusted certificate is an anchor, not only self-signed ones
+            try!(store.set_flags(X509VerifyFlags::PARTIAL_CHAIN));
+            for certificate in trusted {
+                try!(store.add_cert(certificate.x509.clone()));
+            }
+            let store = store.build();
+            let chain = try!(Stack::new());
+            let mut context = try!(X509StoreContext::new());
+            context.init(&store, &self.x509, &chain, |context| context.verify_cert())
+        });
+        verified.unwrap_or(false)
+    }
+}
+
+impl PartialEq for Certificate {
+    fn eq(&self, other: &Certificate) -> bool {
+        self.der == other.der
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Certificate")
+            .field("subject", &self.subject_name())
+            .field("issuer", &self.issuer_name())
+            .finish()
+    }
+}
+
+// PKCS#1 `RSA PRIVATE KEY` or PKCS#8 `PRIVATE KEY`, as DER or PEM.
+pub fn private_key_from_der(der: &[u8]) -> Result<RsaPrivateKey, X509Error> {
+    let key = PKey::private_key_from_der(der)
+        .or_else(|_| Rsa::private_key_from_der(der).and_then(PKey::from_rsa));
+    let key = try!(key.map_err(|_| X509Error::Der));
+    RsaPrivateKey::from_pkey(key).ok_or(X509Error::UnsupportedAlgorithm)
+}
+
+pub fn private_key_from_pem(pem: &str) -> Result<RsaPrivateKey, X509Error> {
+    let key = try!(PKey::private_key_from_pem(pem.as_bytes()).map_err(|_| X509Error::Pem));
+    RsaPrivateKey::from_pkey(key).ok_or(X509Error::UnsupportedAlgorithm)
+}
+
+fn common_name(name: &X509NameRef) -> Option<String> {
+    name.entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|name| name.to_string())
+}